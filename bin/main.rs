@@ -1,12 +1,12 @@
-use libmc;
-
+use std::io::IsTerminal;
 use std::process::exit;
 
 fn main() {
     let args: Vec<String> = std::env::args().collect();
     match args.as_slice() {
         [_, ref file] => {
-            exit(libmc::compile_file(file, None, true, true, true));
+            let color = std::io::stdout().is_terminal() && std::env::var_os("NO_COLOR").is_none();
+            exit(libmc::compile_file(file, None, true, true, true, color));
         }
         _ => {
             println!("What do you mean?");
@@ -5,8 +5,6 @@ use std::io::Write;
 use std::path::Path;
 use std::process::{exit, Command, ExitStatus, Output, Stdio};
 
-use libmc;
-
 fn run_ocaml(file_path: &str) -> String {
     let ret: Output = Command::new("ocaml")
         .arg(file_path)
@@ -34,7 +32,7 @@ fn run_mc(file_path_str: &str) -> Result<String, McError> {
     let file_stem = file_path.file_stem().unwrap();
     let file_stem_str = file_stem.to_str().unwrap();
 
-    let ret = libmc::compile_file(file_path_str, Some("_test"), false, false, false);
+    let ret = libmc::compile_file(file_path_str, Some("_test"), false, false, false, false);
 
     if ret != 0 {
         return Err(McError::CompileError);
@@ -44,7 +42,7 @@ fn run_mc(file_path_str: &str) -> Result<String, McError> {
         status,
         stdout,
         stderr,
-    } = Command::new(&format!("_test/{}", file_stem_str))
+    } = Command::new(format!("_test/{}", file_stem_str))
         .stdout(Stdio::piped())
         .stderr(Stdio::piped())
         .spawn()
@@ -134,7 +132,7 @@ fn create_dir(path: &str) {
     match fs::create_dir(path) {
         Ok(()) => (),
         Err(err) if err.kind() == std::io::ErrorKind::AlreadyExists => (),
-        Err(err) => panic!(err),
+        Err(err) => std::panic::panic_any(err),
     }
 }
 
@@ -0,0 +1,58 @@
+type color = Red | Green | Blue
+
+type shape =
+  | Circle of float
+  | Rect of float * float
+  | Empty
+
+type expr =
+  | Num of int
+  | Add of expr * expr
+  | Mul of expr * expr
+  | Neg of expr
+
+let rec color_code c =
+  match c with
+  | Red -> 1
+  | Green -> 2
+  | Blue -> 3
+in
+
+let rec area s =
+  match s with
+  | Circle r -> 3.0 *. r *. r
+  | Rect (w, h) -> w *. h
+  | Empty -> 0.0
+in
+
+let rec eval e =
+  match e with
+  | Num n -> n
+  | Add (e1, e2) -> eval e1 + eval e2
+  | Mul (e1, e2) -> eval e1 * eval e2
+  | Neg e -> - (eval e)
+in
+
+let rec is_zero_sum e =
+  match e with
+  | Add (Num 0, Num 0) -> true
+  | Add (_, _) -> false
+  | _ -> false
+in
+
+print_int (color_code Red);
+print_newline ();
+print_int (color_code Blue);
+print_newline ();
+print_int (truncate (area (Circle 2.0)));
+print_newline ();
+print_int (truncate (area (Rect (3.0, 4.5))));
+print_newline ();
+print_int (truncate (area Empty));
+print_newline ();
+print_int (eval (Add (Num 1, Mul (Num 6, Neg (Num 7)))));
+print_newline ();
+print_int (if is_zero_sum (Add (Num 0, Num 0)) then 1 else 0);
+print_newline ();
+print_int (if is_zero_sum (Add (Num 0, Num 1)) then 1 else 0);
+print_newline ()
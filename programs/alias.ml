@@ -0,0 +1,31 @@
+type vec = float array
+type point = float * float
+type shape = Circle of point * float | Poly of point list
+type matrix = row array and row = vec
+
+let rec dot (a : vec) (b : vec) (n : int) : float =
+  if n = 0 then 0.0 else a.(n - 1) *. b.(n - 1) +. dot a b (n - 1)
+
+let rec dist (p : point) (q : point) : float =
+  let (x1, y1) = p in
+  let (x2, y2) = q in
+  sqrt ((x2 -. x1) *. (x2 -. x1) +. (y2 -. y1) *. (y2 -. y1))
+
+let rec perimeter s =
+  match s with
+  | Circle ((_, _), r) -> 2.0 *. 3.0 *. r
+  | Poly [] -> 0.0
+  | Poly (p :: ps) ->
+    let rec go (first : point) (prev : point) (l : point list) =
+      match l with
+      | [] -> dist prev first
+      | q :: rest -> dist prev q +. go first q rest in
+    go p p ps
+
+let () =
+  let a : vec = Array.create 3 2.0 in
+  let m : matrix = Array.create 2 a in
+  let r : row = m.(1) in
+  Printf.printf "%.1f\n" (dot a r 3);
+  Printf.printf "%.1f\n" (perimeter (Circle ((0.0, 0.0), 1.0)));
+  Printf.printf "%.1f\n" (perimeter (Poly [(0.0, 0.0); (3.0, 0.0); (3.0, 4.0)]))
@@ -0,0 +1,18 @@
+let rec loud b = print_int (if b then 1 else 0); b in
+let rec check b = if b then print_int 1 else print_int 0; print_newline () in
+check (true && false);
+check (false || true);
+check (loud false && loud true);
+check (loud true || loud false);
+check (loud true && loud true || loud false);
+check (1 < 2 && 3 < 4 || 5 < 6 && 7 > 8);
+check (false && true || true);
+check (not true || false);
+let rec mem x arr n = n > 0 && (arr.(n - 1) = x || mem x arr (n - 1)) in
+let arr = Array.make 5 3 in
+arr.(2) <- 7;
+check (mem 7 arr 5);
+check (mem 8 arr 5);
+let x = 3 in
+check (x > 0 && x < 10 && x <> 5);
+if x = 3 || x / 0 = 1 then print_int 42 else print_int 0; print_newline ()
@@ -0,0 +1,11 @@
+let rec add (x : int) (y : int) : int = x + y in
+let rec scale (k : float) v = Array.create 2 (k *. v.(0)) in
+let z : float = 1.5 in
+let p : int * float = (add 1 2, z) in
+let rec apply (f : int -> int) x = f x in
+let l : int list = [1; 2] in
+let a : float array = scale 2.0 (Array.create 1 z) in
+let (i, f) = p in
+print_int (apply (fun (x : int) -> x * 2) i + List.length l);
+print_newline ();
+Printf.printf "%.1f %.1f\n" (f : float) (a.(1))
@@ -0,0 +1,17 @@
+let rec id x = x in
+let rec popcount x = if x = 0 then 0 else (x land 1) + popcount (x lsr 1) in
+print_int (12 land 10); print_newline ();
+print_int (12 lor 10); print_newline ();
+print_int (12 lxor 10); print_newline ();
+print_int (lnot 5); print_newline ();
+print_int (lnot (id 0)); print_newline ();
+print_int (1 lsl 10); print_newline ();
+print_int (-16 asr 2); print_newline ();
+print_int ((1 lsl 40) lsr 38); print_newline ();
+print_int (1 lsl 2 lsl 3); print_newline ();
+print_int (2 * 3 lsl 2); print_newline ();
+print_int (1 + 2 land 3); print_newline ();
+print_int (id 3 lsl id 2); print_newline ();
+print_int (- 1 lsl 3); print_newline ();
+print_int (popcount 255 + popcount (1 lsl 40)); print_newline ();
+print_int (lnot 0 lxor 7 land 12); print_newline ()
@@ -0,0 +1,23 @@
+let rec classify c =
+  match c with
+  | 'a' -> "vowel"
+  | 'e' -> "vowel"
+  | 'i' -> "vowel"
+  | 'o' -> "vowel"
+  | 'u' -> "vowel"
+  | ' ' -> "space"
+  | '\n' -> "newline"
+  | _ -> if 'a' <= c && c <= 'z' then "consonant" else "other" in
+let s = "hi you\n" in
+for i = 0 to String.length s - 1 do
+  print_string (classify (String.get s i)); print_newline ()
+done;
+print_char 'x'; print_char '\t'; print_char '\''; print_char '\\'; print_char '\n';
+print_int (Char.code 'A'); print_newline ();
+print_char (Char.chr 98); print_newline ();
+print_char (Char.chr (Char.code 'a' + 25)); print_newline ();
+let rec upper c = if c >= 'a' && c <= 'z' then Char.chr (Char.code c - 32) else c in
+print_char (upper 'q'); print_char (upper '!'); print_newline ();
+(try print_char (Char.chr 256) with Invalid_argument msg -> print_string msg);
+print_newline ();
+print_int (if 'b' > 'a' then 1 else 0); print_newline ()
@@ -0,0 +1,77 @@
+type color = Red | Green | Blue
+type shape = Circle of float | Rect of float * float | Empty
+type point = { x : int; y : int }
+exception Oops of int
+let rec pb b = print_string (if b then "true" else "false"); print_newline () in
+pb ((1, 2) = (1, 2));
+pb ((1, 2) = (1, 3));
+pb ((1, 2) < (1, 3));
+pb ((2, 0) > (1, 3));
+pb ((1, "b") <> (1, "a"));
+pb ("abc" < "abd");
+pb ("ab" < "abc");
+pb ("" = "");
+let a = Array.make 3 1 in
+let b = Array.make 3 1 in
+pb (a = b);
+pb (a == b);
+pb (a != b);
+pb (a == a);
+b.(2) <- 0;
+pb (a = b);
+pb (b < a);
+pb (Array.make 2 5 < Array.make 3 0);
+pb ([1; 2; 3] = [1; 2; 3]);
+pb ([1; 2] < [1; 2; 3]);
+pb ([] < [1]);
+pb ([2] > [1; 5]);
+pb (Red < Green);
+pb (Blue = Blue);
+pb (Circle 1.0 = Circle 1.0);
+pb (Empty < Circle 0.0);
+pb (Circle 5.0 < Rect (0.0, 0.0));
+pb (Rect (1.0, 2.0) < Rect (1.0, 3.0));
+pb ({ x = 1; y = 2 } = { x = 1; y = 2 });
+pb ({ x = 1; y = 2 } < { x = 1; y = 1 });
+pb (ref 3 = ref 3);
+pb (Oops 1 = Oops 1);
+pb (Oops 1 = Not_found);
+pb ((0.0 /. 0.0, 1) = (0.0 /. 0.0, 1));
+pb ([[1]; [2; 3]] = [[1]; [2; 3]]);
+pb ((1, 2.5) >= (1, 2.5));
+print_int (compare (1, 2) (1, 3)); print_newline ();
+print_int (compare [3] [1; 2]); print_newline ();
+print_int (compare "x" "x"); print_newline ();
+print_int (compare 2 1); print_newline ();
+print_int (compare (0.0 /. 0.0) 1.0); print_newline ();
+print_int (compare (0.0 /. 0.0, 1) (0.0 /. 0.0, 1)); print_newline ();
+pb (if (1, 2) = (1, 2) then true else false);
+pb (if [1; 2] <> [1; 2] then true else false);
+let rec mem x l = match l with [] -> false | y :: l -> x = y || mem x l in
+let rec mem2 x l = mem x l in
+let rec max2 a b = if compare a b > 0 then a else b in
+pb (mem2 (1, 2) [(0, 0); (1, 2)]);
+pb (mem2 "a" ["b"]);
+pb (mem 3 [1; 3]);
+pb (mem 1.5 [2.5; 1.5]);
+pb (mem [1] [[2]; [1]]);
+let (a, b) = max2 (1, "b") (1, "a") in
+print_string b; print_newline ();
+print_string (max2 "x" "y"); print_newline ();
+print_int (max2 3 4); print_newline ();
+pb (max2 (Array.make 2 1) (Array.make 1 5) == Array.make 2 1);
+print_int (compare 1.0 (0.0 /. 0.0)); print_newline ();
+print_int (compare (0.0 /. 0.0) (0.0 /. 0.0)); print_newline ();
+(* `compare` as a value *)
+List.iter (fun c -> print_int c; print_newline ()) (List.map (compare 2) [1; 2; 3]);
+let cmp = compare in
+print_int (cmp "a" "b"); print_newline ();
+print_int (cmp [2.5] [1.5]); print_newline ();
+(* The same closure is equal to itself *)
+let f = fun x -> x + 1 in
+pb (f = f);
+print_int (compare f f); print_newline ();
+pb ((1, f) = (1, f));
+pb ([f; f] = [f; f]);
+let compare = fun x y -> 42 in
+print_int (compare 1 2); print_newline ()
@@ -0,0 +1,64 @@
+exception Empty
+exception Found of int
+exception Pair of int * float
+
+let rec find arr len x i =
+  if i = len then raise Not_found
+  else if arr.(i) = x then i
+  else find arr len x (i + 1)
+
+let rec first_even arr len =
+  for i = 0 to len - 1 do
+    if arr.(i) mod 2 = 0 then raise (Found (arr.(i))) else ()
+  done;
+  raise Empty
+
+let rec safe_div x y = if y = 0 then failwith "division by zero" else x / y
+
+let rec try_div x y = try safe_div x y with Failure _ -> -1
+
+let rec check n = assert (n > 0); n * 2
+
+let () =
+  let arr = Array.make 5 1 in
+  arr.(3) <- 4;
+  print_int (find arr 5 4 0);
+  print_newline ();
+  print_int (try find arr 5 7 0 with Not_found -> -1);
+  print_newline ();
+  print_int (try first_even arr 5 with Found n -> n | Empty -> 0);
+  print_newline ();
+  print_int (try first_even (Array.make 2 1) 2 with Found n -> n | Empty -> 0);
+  print_newline ();
+  print_int (try_div 10 2 + try_div 1 0);
+  print_newline ();
+  (* Handlers that don't match propagate the exception to the outer handler *)
+  print_int (try (try raise Empty with Not_found -> 1) with Empty -> 2);
+  print_newline ();
+  (* Float results and exception arguments *)
+  let f = try 1.0 +. raise (Pair (3, 0.5)) with Pair (n, g) -> float_of_int n +. g in
+  print_int (truncate (f *. 10.0));
+  print_newline ();
+  print_int (check 21);
+  print_newline ();
+  print_int (try check 0 with Assert_failure _ -> 0);
+  print_newline ();
+  (* Handlers are restored after the body returns *)
+  let r = ref 0 in
+  for i = 1 to 10 do
+    r := !r + (try if i mod 3 = 0 then raise (Invalid_argument "i") else i with
+               Invalid_argument _ -> 100)
+  done;
+  print_int !r;
+  print_newline ();
+  let rec describe e =
+    match e with Found n -> n | Not_found -> -1 | _ -> -2 in
+  print_int (describe (Found 5) + describe Not_found + describe Empty);
+  print_newline ();
+  (* Runtime errors are exceptions too *)
+  let rec div x y = x / y in
+  print_int (try div 5 0 with Division_by_zero -> 7);
+  print_newline ();
+  let rec sign n = match n with 0 -> 0 | 1 -> 1 in
+  print_int (try sign 2 with Match_failure (_, line, _) -> line);
+  print_newline ()
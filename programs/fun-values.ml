@@ -0,0 +1,23 @@
+(* Functions stored in lists, tuples, references and arrays, then called with a different number of
+   arguments than they were defined with *)
+let rec add x y = x + y in
+let rec use l = match l with [] -> 0 | f :: _ -> f 1 2 in
+print_int (use [fun x -> fun y -> x + y]);
+print_newline ();
+let rec call r = !r 1 2 in
+print_int (call (ref (fun x -> fun y -> x - y)));
+print_newline ();
+let rec each l =
+  match l with
+  | [] -> ()
+  | (k, f) :: l -> print_int (f k 10); print_newline (); each l in
+each [(1, add); (2, fun x -> fun y -> x - y)];
+let fs = [add; (fun x -> fun y -> x * y)] in
+List.iter (fun f -> print_int (f 6 7); print_newline ()) fs;
+let a = Array.make 1 add in
+a.(0) <- (fun x -> fun y -> x * y);
+print_int (a.(0) 3 4);
+print_newline ();
+let rec twice f x = f (f x) in
+print_int (List.fold_left (fun acc f -> acc + twice f 1) 0 [add 1; (fun x -> x * 3)]);
+print_newline ()
@@ -0,0 +1,23 @@
+let rec apply f x = f x in
+let rec twice f x = f (f x) in
+let rec fold f acc n = if n = 0 then acc else fold f (f acc n) (n - 1) in
+let k = 10 in
+print_int (apply (fun x -> x + k) 5);
+print_newline ();
+print_int (twice (fun x -> x * 3) 2);
+print_newline ();
+print_int (fold (fun acc n -> acc + n * k) 0 4);
+print_newline ();
+let add = fun x y -> x + y in
+print_int (add 20 22);
+print_newline ();
+let scale = fun x -> x *. 2.5 in
+print_int (truncate (apply scale 4.0));
+print_newline ();
+let id = fun x -> x in
+print_int (if id true then id 7 else 0);
+print_newline ();
+let rec make_adder n = fun x -> x + n in
+let add3 = make_adder 3 in
+print_int (add3 (apply (fun _ -> 1) ()));
+print_newline ()
@@ -0,0 +1,8 @@
+let x = 7 in
+let y = 0 - 2 in
+print_int (x * y + 100 / 7 - x mod 3 * 2); print_newline ();
+print_int ((0 - 7) / 2); print_newline ();
+print_int ((0 - 7) mod 2); print_newline ();
+print_int (7 mod (0 - 2)); print_newline ();
+print_int (2 * 3 * 4 / 5); print_newline ();
+print_int (- 4611686018427387903 / (0 - 1)); print_newline ()
@@ -0,0 +1,34 @@
+let p = ((1, 2.5), (true, 'x'))
+
+let ((a, b), (_, c)) = p
+
+let rec swap (x, y) = (y, x)
+
+let rec sum3 ((x, y), z) = x + y + z
+
+let rec greet () = print_string "hello"; print_newline ()
+
+let _ = swap (1, 2)
+
+let () =
+  greet ();
+  print_int a;
+  print_newline ();
+  print_int (truncate (b *. 2.0));
+  print_newline ();
+  print_char c;
+  print_newline ();
+  let (x, (y, _)) = (10, swap (20, 30)) in
+  print_int (x + y);
+  print_newline ();
+  let (u, v) = swap (3, 4) in
+  print_int (u * 10 + v);
+  print_newline ();
+  print_int (sum3 ((1, 2), 3));
+  print_newline ();
+  let f = fun (x, _) () -> x * 2 in
+  print_int (f (21, "unused") ());
+  print_newline ();
+  let (_, (s, ())) = (1, ("nested", ())) in
+  print_string s;
+  print_newline ()
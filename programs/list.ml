@@ -0,0 +1,35 @@
+let rec print_list l =
+  match l with
+  | [] -> print_newline ()
+  | [x] -> print_int x; print_newline ()
+  | x :: rest -> print_int x; print_string "; "; print_list rest in
+let l = [1; 2; 3; 4; 5] in
+print_list l;
+print_list (List.rev l);
+print_list (List.map (fun x -> x * x) l);
+print_int (List.length l); print_newline ();
+print_int (List.fold_left (fun acc x -> acc + x) 0 l); print_newline ();
+List.iter (fun s -> print_string s; print_string " ") ["a"; "b"; "c"];
+print_newline ();
+let fl = List.map (fun x -> float_of_int x /. 2.0) l in
+List.iter (fun f -> Printf.printf "%.1f " f) fl;
+print_newline ();
+Printf.printf "%.1f\n" (List.fold_left (fun acc x -> acc +. x) 0.0 fl);
+let rec sum_pairs l =
+  match l with
+  | [] -> 0
+  | (a, b) :: rest -> a * b + sum_pairs rest in
+print_int (sum_pairs [(1, 2); (3, 4)]); print_newline ();
+let nested = [[1; 2]; []; [3]] in
+print_int (List.length nested); print_newline ();
+List.iter print_list nested;
+print_int (List.length (List.map List.length nested)); print_newline ();
+let rec first_two l =
+  match l with
+  | a :: b :: _ -> a + b
+  | [a] -> a
+  | [] -> 0 in
+print_int (first_two [10; 20; 30]); print_newline ();
+print_int (first_two (0 :: [])); print_newline ();
+let e = [] in
+print_int (List.length e); print_newline ()
@@ -0,0 +1,54 @@
+let sum = ref 0 in
+for i = 1 to 10 do
+  sum := !sum + i
+done;
+print_int !sum;
+print_newline ();
+for i = 3 downto 1 do
+  print_int i
+done;
+print_newline ();
+for i = 1 to 0 do
+  print_int 42
+done;
+let n = ref 100 in
+let steps = ref 0 in
+while !n <> 1 do
+  (if !n mod 2 = 0 then n := !n / 2 else n := 3 * !n + 1);
+  steps := !steps + 1
+done;
+print_int !steps;
+print_newline ();
+let s = ref 0.0 in
+let d = ref 1.0 in
+for i = 1 to 10000000 do
+  s := !s +. 1.0 /. !d;
+  d := !d +. 1.0
+done;
+print_int (truncate (1000.0 *. !s));
+print_newline ();
+let fs = Array.make 3 (fun x -> x) in
+for i = 0 to 2 do
+  fs.(i) <- (fun x -> x + i)
+done;
+print_int (fs.(0) 10 + fs.(1) 10 + fs.(2) 10);
+print_newline ();
+let total = ref 0 in
+for i = 1 to 3 do
+  for j = i to 3 do
+    total := !total + i * j
+  done
+done;
+print_int !total;
+print_newline ();
+(* Loops up to `max_int` and down to `min_int` *)
+let max = (0 - 1) lsr 1 in
+let count = ref 0 in
+for i = max - 2 to max do
+  count := !count + 1
+done;
+for i = 0 - max downto 0 - max - 1 do
+  count := !count + 1
+done;
+print_int !count;
+print_newline ()
@@ -0,0 +1,46 @@
+(* Pattern matching on ints, bools, unit and tuples *)
+let rec describe n =
+  match n with
+  | 0 -> 100
+  | 1 -> 101
+  | -1 -> 99
+  | n -> n * 2
+in
+print_int (describe 0); print_newline ();
+print_int (describe 1); print_newline ();
+print_int (describe (-1)); print_newline ();
+print_int (describe 7); print_newline ();
+
+let rec both a b =
+  match (a, b) with
+  | (true, true) -> 3
+  | (true, false) -> 2
+  | (false, true) -> 1
+  | (false, false) -> 0
+in
+print_int (both true false + 10 * both false true); print_newline ();
+
+let rec nested p =
+  match p with
+  | ((0, x), y) -> x + y
+  | ((a, _), 0) -> a
+  | ((a, b), c) -> a * b * c
+in
+print_int (nested ((0, 2), 3)); print_newline ();
+print_int (nested ((5, 2), 0)); print_newline ();
+print_int (nested ((5, 2), 3)); print_newline ();
+
+let rec fact n = match n with 0 -> 1 | n -> n * fact (n - 1) in
+print_int (fact 10); print_newline ();
+
+let k = 5 in
+let rec add_k p = match p with (x, ()) -> x + k in
+print_int (add_k (1, ())); print_newline ();
+
+let f = 1.5 in
+let r =
+  match (1, f) with
+  | (1, g) -> truncate (g *. 2.0)
+  | _ -> 0
+in
+print_int r; print_newline ()
@@ -0,0 +1,21 @@
+let t = 123 in
+let f = 456 in
+let rec even x =
+  if x > 0 then odd (x - 1) else
+  if x < 0 then odd (x + 1) else
+  t
+and odd x =
+  if x > 0 then even (x - 1) else
+  if x < 0 then even (x + 1) else
+  f in
+print_int (even 789); print_newline ();
+print_int (odd 789); print_newline ();
+let rec make n =
+  let rec a x = if x = 0 then n else b (x - 1)
+  and b x = if x = 0 then 0 - n else c (x - 1)
+  and c x = if x = 0 then n * 100 else a (x - 1) in
+  a in
+let g = make 7 in
+print_int (g 0); print_newline ();
+print_int (g 1); print_newline ();
+print_int (g 5); print_newline ()
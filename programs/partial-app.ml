@@ -0,0 +1,63 @@
+let rec apply f x = f x in
+let rec g x = let rec h y = x + y in h in
+print_int ((apply g 1) 2);
+print_newline ();
+print_int (apply g 3 4);
+print_newline ();
+let rec add x y = x + y in
+let inc = add 1 in
+print_int (inc 41);
+print_newline ();
+let rec add3 x y z = x * 100 + y * 10 + z in
+let p = add3 1 in
+let q = p 2 in
+print_int (q 3 + p 4 5);
+print_newline ();
+let rec compose f g = fun x -> g (f x) in
+print_int (compose inc inc 5);
+print_newline ();
+let rec map_arr f a =
+  let rec go i = if i < 0 then () else (a.(i) <- f a.(i); go (i - 1)) in
+  go 2 in
+let arr = Array.make 3 1.5 in
+let rec fmul x y = x *. y in
+map_arr (fmul 2.0) arr;
+print_int (truncate (arr.(0) +. arr.(2)));
+print_newline ();
+let rec twice f = fun x -> f (f x) in
+print_int (twice (add 10) 1);
+print_newline ();
+let rec curry f x y = f (x, y) in
+let rec sum p = let (a, b) = p in a + b in
+print_int (curry sum 3 4);
+print_newline ();
+let rec mk x = fun y z -> x + y * z in
+print_int (mk 1 2 3);
+print_newline ();
+let h = mk 5 in
+let h2 = h 6 in
+print_int (h2 7);
+print_newline ();
+(* Functions passed where functions with a different number of arguments are expected *)
+let rec app f x = f x in
+print_int (app add 1 2);
+print_newline ();
+List.iter (fun f -> print_int (f 10); print_newline ()) (List.map add [1; 2]);
+let rec compose2 f g x = f (g x) in
+print_int (compose2 add (fun x -> x * 2) 3 4);
+print_newline ();
+List.iter (fun f -> print_int (f 10); print_newline ()) (List.map (fun x y -> x + y) [1; 2]);
+let rec sub2 (f : int -> int -> int) = f 10 3 in
+print_int (sub2 (fun x -> fun y -> x - y));
+print_newline ();
+(match [add; (fun x -> fun y -> x * y)] with
+ | f :: g :: _ -> print_int (f 6 7 + g 6 7)
+ | _ -> ());
+print_newline ();
+let r = ref add in
+r := (fun x -> fun y -> x - y);
+print_int (!r 6 7);
+print_newline ();
+let p = if true then (fun x -> fun y -> x * y) else add in
+print_int (p 6 7);
+print_newline ()
@@ -0,0 +1,30 @@
+let rec id x = x in
+let rec compose f g =
+  let rec composed x = g (f x) in
+  composed in
+let rec twice f x = f (f x) in
+let rec fst p = let (a, b) = p in a in
+let rec snd p = let (a, b) = p in b in
+let g = id in
+let rec inc x = x + 1 in
+let rec half x = x /. 2.0 in
+print_int (id 1);
+print_newline ();
+print_int (truncate (id 2.5 +. 0.5));
+print_newline ();
+print_int (if g true then g 4 else 0);
+print_newline ();
+let h1 = compose inc inc in
+print_int (h1 5);
+print_newline ();
+let h2 = compose half half in
+print_int (truncate (h2 20.0));
+print_newline ();
+print_int (twice inc 10);
+print_newline ();
+print_int (truncate (twice half 100.0));
+print_newline ();
+print_int (fst (7, 1.5) + truncate (snd (7, 1.5) *. 2.0));
+print_newline ();
+print_int (truncate (fst (2.5, 1) *. 2.0) + snd (2.5, 9));
+print_newline ()
@@ -0,0 +1,17 @@
+let n = 3 in
+let avg = 2.5 in
+Printf.printf "%d items, %f avg\n" n avg;
+Printf.printf "[%5d] [%-5d] [%05d] [%+d]\n" 42 42 42 42;
+Printf.printf "[%.2f] [%8.3f] [%-8.1f]\n" 3.14159 2.0 (-1.25);
+Printf.printf "%s and %c, %b/%b, 100%%\n" "str" 'c' true false;
+Printf.printf "[%6s] [%-6s]\n" "ab" "cd";
+Printf.printf "[%s] [%5s] [%-5s] [%.2s]\n" "a\000b" "a\000b" "a\000b" "a\000b";
+Printf.printf "no directives\n";
+let print_pair = Printf.printf "(%d, %s)\n" in
+print_pair 1 "one";
+print_pair 2 "two";
+let rec iter f i n = if i < n then (f i; iter f (i + 1) n) else () in
+iter (Printf.printf "%d;") 0 5;
+Printf.printf "\n";
+let p = Printf.printf "%d %d\n" 7 in
+p 8
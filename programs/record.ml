@@ -0,0 +1,36 @@
+type point = { x : float; y : float }
+
+type counter = { name : int; mutable count : int; mutable total : float }
+
+let origin = { x = 0.0; y = 0.0 }
+
+let rec dist2 p q =
+  let dx = p.x -. q.x in
+  let dy = p.y -. q.y in
+  dx *. dx +. dy *. dy
+
+let rec incr c v =
+  c.count <- c.count + 1;
+  c.total <- c.total +. v
+
+let c = { count = 0; total = 0.0; name = 42 }
+
+let () =
+  incr c 1.5;
+  incr c 2.5;
+  incr c 3.0
+
+let { name; count = n; _ } = c
+
+let () =
+  print_int (truncate (dist2 origin { y = 4.0; x = 3.0 }));
+  print_newline ();
+  print_int name;
+  print_newline ();
+  print_int n;
+  print_newline ();
+  print_int (truncate c.total);
+  print_newline ();
+  let { x; y = b } = { x = 10.0; y = 20.0 } in
+  print_int (truncate (x +. b));
+  print_newline ()
@@ -0,0 +1,28 @@
+let counter = ref 0 in
+let rec incr r = r := !r + 1 in
+let rec loop n = if n = 0 then () else (incr counter; loop (n - 1)) in
+loop 10;
+print_int !counter;
+print_newline ();
+let sum = ref 0.0 in
+let rec add x = sum := !sum +. x in
+add 1.5; add 2.5;
+print_int (truncate !sum);
+print_newline ();
+let rr = ref (ref 3) in
+!rr := !(!rr) * 2;
+print_int !(!rr);
+print_newline ();
+let rec make_counter start =
+  let c = ref start in
+  fun step -> c := !c + step; !c in
+let next = make_counter 100 in
+let a = next 1 in
+let b = next 10 in
+print_int (a + b);
+print_newline ();
+let pair = ref (1, 2.0) in
+pair := (3, 4.0);
+let (x, y) = !pair in
+print_int (x + truncate y);
+print_newline ()
@@ -0,0 +1,16 @@
+let greeting = "Hello" ^ ", " ^ "world!" in
+print_string greeting; print_newline ();
+print_int (String.length greeting); print_newline ();
+print_char (String.get greeting 4); print_newline ();
+print_string (String.sub greeting 7 5); print_newline ();
+print_string "tab\tquote\"backslash\\ \065\x42\o103\n";
+print_string "line \
+    continued\n";
+let rec repeat s n = if n = 0 then "" else s ^ repeat s (n - 1) in
+print_string (repeat "ab" 3); print_newline ();
+print_int (String.length ""); print_newline ();
+print_string "done\n";
+(try print_string (String.sub "abc" 2 2) with Invalid_argument msg -> print_string msg);
+print_newline ();
+(try print_char (String.get "abc" 3) with Invalid_argument msg -> print_string msg);
+print_newline ()
@@ -0,0 +1,30 @@
+(* Top-level definitions *)
+let n = 10
+let scale = 2.5
+
+let rec fib x = if x <= 1 then x else fib (x - 1) + fib (x - 2)
+
+let rec even x = if x = 0 then true else odd (x - 1)
+and odd x = if x = 0 then false else even (x - 1)
+
+let () = print_int (fib n); print_newline ()
+let (a, b) = (fib 5, 7) ;;
+let rec show_bool b = if b then print_int 1 else print_int 0
+let () =
+  show_bool (even 10);
+  show_bool (odd 10);
+  print_newline ()
+
+(* Globals captured by local closures *)
+let total = a + b
+let rec add_total x = x + total
+let () = print_int (add_total 100); print_newline ()
+
+let f = scale *. 4.0 ;;
+
+print_int (truncate f);;
+print_newline ();;
+
+let x = 1 in
+let y = x + n in
+print_int y; print_newline ()
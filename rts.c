@@ -1,11 +1,24 @@
 #include <inttypes.h>
 #include <math.h>
+#include <setjmp.h>
 #include <stdio.h>
+#include <stdlib.h>
+#include <string.h>
 
 typedef struct FunctionClosure_ {
     void *function;
 } FunctionClosure;
 
+// Strings are length-prefixed and NUL-terminated
+typedef struct String_ {
+    int64_t len;
+    char data[];
+} String;
+
+// A string known at compile time
+#define STRING(var, str) \
+    struct { int64_t len; char data[sizeof(str)]; } var = { sizeof(str) - 1, str }
+
 // int return type because we don't support not returning! Unit is 0.
 int64_t mc_print_int_f(FunctionClosure *self, int64_t i) {
     printf("%" PRId64, i);
@@ -21,6 +34,13 @@ int64_t mc_print_newline_f(FunctionClosure *self, int64_t i) {
 
 FunctionClosure mc_print_newline = { .function = &mc_print_newline_f };
 
+int64_t mc_print_string_f(FunctionClosure *self, String *s) {
+    fwrite(s->data, 1, s->len, stdout);
+    return 0;
+}
+
+FunctionClosure mc_print_string = { .function = &mc_print_string_f };
+
 double mc_float_of_int_f(FunctionClosure *self, int64_t i) {
     return (double)i;
 }
@@ -59,3 +79,295 @@ double mc_cos_f(FunctionClosure *self, double d) {
 }
 
 FunctionClosure mc_cos = { .function = &mc_cos_f };
+
+// Names of the predefined exceptions. Exceptions are tuples with the address of the name in field
+// 0 and the arguments in the rest. The name is followed by a NUL and a character per argument
+// saying how to print it: 'i' for integers, 'f' for floats, 's' for strings, '_' for the rest.
+STRING(mc_exn_failure, "Failure\0s");
+STRING(mc_exn_not_found, "Not_found\0");
+STRING(mc_exn_invalid_argument, "Invalid_argument\0s");
+STRING(mc_exn_assert_failure, "Assert_failure\0sii");
+STRING(mc_exn_division_by_zero, "Division_by_zero\0");
+STRING(mc_exn_match_failure, "Match_failure\0sii");
+
+// Innermost `try` being evaluated
+typedef struct Handler_ {
+    jmp_buf buf;
+    struct Handler_ *prev;
+    int64_t *exn;
+} Handler;
+
+static Handler *mc_handler = NULL;
+
+// Call the closure with a handler installed. Returns 0 when the closure returns, or the exception
+// it raises.
+int64_t mc_try_f(FunctionClosure *self, FunctionClosure *body) {
+    Handler handler;
+    handler.prev = mc_handler;
+    mc_handler = &handler;
+    if (setjmp(handler.buf) == 0) {
+        int64_t (*f)(FunctionClosure*, int64_t) = body->function;
+        f(body, 0);
+        mc_handler = handler.prev;
+        return 0;
+    }
+    // mc_raise popped the handler
+    return (int64_t)handler.exn;
+}
+
+FunctionClosure mc_try = { .function = &mc_try_f };
+
+// Print a string as OCaml's `%S` does
+static void mc_print_quoted(FILE *out, String *s) {
+    fputc('"', out);
+    for (int64_t i = 0; i < s->len; i++) {
+        unsigned char c = s->data[i];
+        switch (c) {
+        case '"': fputs("\\\"", out); break;
+        case '\\': fputs("\\\\", out); break;
+        case '\n': fputs("\\n", out); break;
+        case '\t': fputs("\\t", out); break;
+        case '\r': fputs("\\r", out); break;
+        case '\b': fputs("\\b", out); break;
+        default:
+            if (c < ' ' || c > '~') {
+                fprintf(out, "\\%03d", c);
+            } else {
+                fputc(c, out);
+            }
+        }
+    }
+    fputc('"', out);
+}
+
+// Print an exception argument as OCaml does, `kind` is as in the exception names
+static void mc_print_exn_arg(FILE *out, char kind, int64_t arg) {
+    switch (kind) {
+    case 'i':
+        fprintf(out, "%" PRId64, arg);
+        break;
+    case 'f': {
+        // As `string_of_float`
+        double d;
+        memcpy(&d, &arg, sizeof(d));
+        char buf[32];
+        snprintf(buf, sizeof(buf), "%.12g", d);
+        fputs(buf, out);
+        if (strspn(buf, "-0123456789") == strlen(buf)) {
+            fputc('.', out);
+        }
+        break;
+    }
+    case 's':
+        mc_print_quoted(out, (String*)arg);
+        break;
+    default:
+        fputc('_', out);
+    }
+}
+
+// Jump to the innermost handler, or print the exception and exit when there isn't one
+void mc_raise(int64_t *exn) {
+    if (mc_handler != NULL) {
+        Handler *handler = mc_handler;
+        mc_handler = handler->prev;
+        handler->exn = exn;
+        longjmp(handler->buf, 1);
+    }
+
+    fflush(stdout);
+    String *name = (String*)exn[0];
+    fprintf(stderr, "Fatal error: exception %s", name->data);
+    const char *arg_kinds = name->data + strlen(name->data) + 1;
+    for (int64_t i = 0; arg_kinds[i] != 0; i++) {
+        fputs(i == 0 ? "(" : ", ", stderr);
+        mc_print_exn_arg(stderr, arg_kinds[i], exn[i + 1]);
+    }
+    if (arg_kinds[0] != 0) {
+        fputc(')', stderr);
+    }
+    fprintf(stderr, "\n");
+    exit(2);
+}
+
+// Called by the generated code on integer division by zero
+void mc_division_by_zero() {
+    int64_t *exn = malloc(sizeof(int64_t));
+    exn[0] = (int64_t)&mc_exn_division_by_zero;
+    mc_raise(exn);
+}
+
+// Called by the generated code when no case of a `match` matches the value
+void mc_match_failure(String *file, int64_t line, int64_t col) {
+    int64_t *exn = malloc(4 * sizeof(int64_t));
+    exn[0] = (int64_t)&mc_exn_match_failure;
+    exn[1] = (int64_t)file;
+    exn[2] = line;
+    exn[3] = col;
+    mc_raise(exn);
+}
+
+// Raise `Invalid_argument msg`
+static void mc_invalid_argument(String *msg) {
+    int64_t *exn = malloc(2 * sizeof(int64_t));
+    exn[0] = (int64_t)&mc_exn_invalid_argument;
+    exn[1] = (int64_t)msg;
+    mc_raise(exn);
+}
+
+static String *mc_alloc_string(int64_t len) {
+    String *s = malloc(sizeof(String) + len + 1);
+    s->len = len;
+    s->data[len] = 0;
+    return s;
+}
+
+String *mc_string_concat_f(FunctionClosure *self, String *s1, String *s2) {
+    String *s = mc_alloc_string(s1->len + s2->len);
+    memcpy(s->data, s1->data, s1->len);
+    memcpy(s->data + s1->len, s2->data, s2->len);
+    return s;
+}
+
+FunctionClosure mc_string_concat = { .function = &mc_string_concat_f };
+
+int64_t mc_string_length_f(FunctionClosure *self, String *s) {
+    return s->len;
+}
+
+FunctionClosure mc_string_length = { .function = &mc_string_length_f };
+
+STRING(mc_index_out_of_bounds, "index out of bounds");
+
+int64_t mc_string_get_f(FunctionClosure *self, String *s, int64_t i) {
+    if (i < 0 || i >= s->len) {
+        mc_invalid_argument((String*)&mc_index_out_of_bounds);
+    }
+    return (unsigned char)s->data[i];
+}
+
+FunctionClosure mc_string_get = { .function = &mc_string_get_f };
+
+STRING(mc_string_sub_msg, "String.sub / Bytes.sub");
+
+String *mc_string_sub_f(FunctionClosure *self, String *s, int64_t start, int64_t len) {
+    if (start < 0 || len < 0 || start > s->len - len) {
+        mc_invalid_argument((String*)&mc_string_sub_msg);
+    }
+    String *sub = mc_alloc_string(len);
+    memcpy(sub->data, s->data + start, len);
+    return sub;
+}
+
+FunctionClosure mc_string_sub = { .function = &mc_string_sub_f };
+
+// Compare strings, for structural comparisons of values with strings
+int64_t mc_compare_strings(String *s1, String *s2) {
+    int64_t len = s1->len < s2->len ? s1->len : s2->len;
+    int res = memcmp(s1->data, s2->data, len);
+    if (res == 0) {
+        return (s1->len > s2->len) - (s1->len < s2->len);
+    }
+    return res < 0 ? -1 : 1;
+}
+
+STRING(mc_compare_functional_value_msg, "compare: functional value");
+
+// Called by the generated code when comparing functions
+void mc_compare_functional_value() {
+    mc_invalid_argument((String*)&mc_compare_functional_value_msg);
+}
+
+int64_t mc_print_char_f(FunctionClosure *self, int64_t c) {
+    putchar((int)c);
+    return 0;
+}
+
+FunctionClosure mc_print_char = { .function = &mc_print_char_f };
+
+// Chars are represented as their codes
+int64_t mc_char_code_f(FunctionClosure *self, int64_t c) {
+    return c;
+}
+
+FunctionClosure mc_char_code = { .function = &mc_char_code_f };
+
+STRING(mc_char_chr_msg, "Char.chr");
+
+int64_t mc_char_chr_f(FunctionClosure *self, int64_t i) {
+    if (i < 0 || i > 255) {
+        mc_invalid_argument((String*)&mc_char_chr_msg);
+    }
+    return i;
+}
+
+FunctionClosure mc_char_chr = { .function = &mc_char_chr_f };
+
+// Printf.printf directives. `spec` is the flags, width, and precision of the directive, `conv` the
+// C conversion specifier.
+#define PRINTF_FORMAT(fmt, spec, conv) \
+    char fmt[(spec)->len + sizeof(conv) + 1]; \
+    sprintf(fmt, "%%%s%s", (spec)->data, conv)
+
+int64_t mc_printf_int_f(FunctionClosure *self, String *spec, int64_t i) {
+    PRINTF_FORMAT(fmt, spec, PRId64);
+    printf(fmt, i);
+    return 0;
+}
+
+FunctionClosure mc_printf_int = { .function = &mc_printf_int_f };
+
+int64_t mc_printf_float_f(FunctionClosure *self, String *spec, double d) {
+    PRINTF_FORMAT(fmt, spec, "f");
+    printf(fmt, d);
+    return 0;
+}
+
+FunctionClosure mc_printf_float = { .function = &mc_printf_float_f };
+
+// Strings can contain NUL bytes, so apply the flags, width, and precision here and write the
+// bytes with fwrite instead of passing the string to printf.
+int64_t mc_printf_string_f(FunctionClosure *self, String *spec, String *s) {
+    const char *p = spec->data;
+    int left = 0;
+    for (; *p == '-' || *p == '0' || *p == '+' || *p == ' '; p++) {
+        if (*p == '-') {
+            left = 1;
+        }
+    }
+    int64_t width = strtoll(p, (char **)&p, 10);
+    int64_t len = s->len;
+    if (*p == '.') {
+        int64_t prec = strtoll(p + 1, NULL, 10);
+        if (prec < len) {
+            len = prec;
+        }
+    }
+    int64_t pad = width > len ? width - len : 0;
+    if (!left) {
+        printf("%*s", (int)pad, "");
+    }
+    fwrite(s->data, 1, len, stdout);
+    if (left) {
+        printf("%*s", (int)pad, "");
+    }
+    return 0;
+}
+
+FunctionClosure mc_printf_string = { .function = &mc_printf_string_f };
+
+int64_t mc_printf_char_f(FunctionClosure *self, String *spec, int64_t c) {
+    PRINTF_FORMAT(fmt, spec, "c");
+    printf(fmt, (int)c);
+    return 0;
+}
+
+FunctionClosure mc_printf_char = { .function = &mc_printf_char_f };
+
+int64_t mc_printf_bool_f(FunctionClosure *self, String *spec, int64_t b) {
+    PRINTF_FORMAT(fmt, spec, "s");
+    printf(fmt, b ? "true" : "false");
+    return 0;
+}
+
+FunctionClosure mc_printf_bool = { .function = &mc_printf_bool_f };
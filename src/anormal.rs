@@ -1,6 +1,9 @@
+use crate::cg_types::RepType;
 use crate::common::*;
 use crate::ctx::{Ctx, TypeId, VarId};
-use crate::parser;
+use crate::format::{self, Conv};
+use crate::parser::{self, Pat};
+use crate::span::{self, Span};
 use crate::type_check::Type;
 use crate::var::CompilerPhase;
 
@@ -13,19 +16,20 @@ pub enum Expr {
     FBinOp(BinOp<FloatBinOp>),
     Neg(VarId),
     FNeg(VarId),
+    // Structural comparison, see `StructCmp`
+    StructCmp(StructCmp, VarId, VarId),
     If(VarId, VarId, Cmp, Box<Expr>, Box<Expr>),
     Let {
         id: VarId,
+        // Type of `id`, also available in `Ctx`
+        #[allow(dead_code)]
         ty_id: TypeId,
         rhs: Box<Expr>,
         body: Box<Expr>,
     },
     Var(VarId),
     LetRec {
-        name: VarId,
-        ty_id: TypeId,
-        args: Vec<VarId>,
-        rhs: Box<Expr>,
+        funs: Vec<FunDef>,
         body: Box<Expr>,
     },
     App(VarId, Vec<VarId>),
@@ -33,6 +37,8 @@ pub enum Expr {
     Tuple(Vec<VarId>),
     // Tuple field read
     TupleGet(VarId, usize),
+    // Tuple field write, for mutable record fields
+    TuplePut(VarId, usize, VarId),
     // Array allocation
     ArrayAlloc {
         len: VarId,
@@ -42,6 +48,60 @@ pub enum Expr {
     ArrayGet(VarId, VarId),
     // Array field write
     ArrayPut(VarId, VarId, VarId),
+    // Loops, compiled to back-edges in `lower`
+    For {
+        var: VarId,
+        from: VarId,
+        to: VarId,
+        dir: ForDir,
+        body: Box<Expr>,
+    },
+    // `cond` is evaluated in each iteration
+    While {
+        cond: Box<Expr>,
+        body: Box<Expr>,
+    },
+    // Pattern matching, compiled to a decision tree in `lower`
+    Match {
+        scrut: VarId,
+        cases: Vec<Case>,
+        // Span of the whole `match`, for warnings
+        span: Span,
+        // Whether this is the handler of a `try`. Handlers re-raise the exception when no case
+        // matches.
+        handler: bool,
+    },
+    // Raise the exception. Does not return.
+    Raise(VarId),
+}
+
+#[derive(Debug)]
+pub struct Case {
+    pub pat: Pat,
+    // Span of the pattern
+    pub span: Span,
+    pub rhs: Expr,
+}
+
+// Top-level phrases. Binders of top-level definitions are globals, see `Ctx::add_global`.
+#[derive(Debug)]
+pub enum Phrase {
+    // Evaluate RHS and bind the value to `id`
+    Let { id: VarId, rhs: Expr },
+    // Top-level functions
+    LetRec(Vec<FunDef>),
+    // Evaluate for side effects
+    Expr(Expr),
+}
+
+// A function in a `LetRec` group
+#[derive(Debug)]
+pub struct FunDef {
+    pub name: VarId,
+    // Type of the function
+    pub ty_id: TypeId,
+    pub args: Vec<VarId>,
+    pub rhs: Box<Expr>,
 }
 
 enum TmpLet {
@@ -85,43 +145,378 @@ fn mk_let(ctx: &mut Ctx, e: Expr, ty_id: TypeId) -> (TmpLet, VarId) {
     }
 }
 
-pub fn anormal(ctx: &mut Ctx, expr: parser::Expr) -> Expr {
+// Bindings of the variables of a `let` pattern to the parts of `var`, in binding order. Parts
+// matched by nested tuple patterns are first bound to generated variables, which are marked with
+// `false`.
+fn let_pat_bndrs(ctx: &mut Ctx, pat: Pat, var: VarId, bndrs: &mut Vec<(VarId, Expr, bool)>) {
+    let pats = match pat {
+        Pat::Unit | Pat::Wildcard => return,
+        Pat::Var(bndr) => {
+            bndrs.push((bndr, Expr::Var(var), true));
+            return;
+        }
+        Pat::Tuple(pats) => pats,
+        other => panic!("let_pat_bndrs: refutable pattern {:?}", other),
+    };
+    let elem_tys = match &*ctx.get_type(ctx.var_type_id(var)) {
+        Type::Tuple(elem_tys) => elem_tys.clone(),
+        other => panic!("let_pat_bndrs: non-tuple type {:?}", other),
+    };
+    for (elem_idx, (pat, elem_ty)) in pats.into_iter().zip(elem_tys).enumerate() {
+        match pat {
+            Pat::Unit | Pat::Wildcard => {}
+            Pat::Var(bndr) => bndrs.push((bndr, Expr::TupleGet(var, elem_idx), true)),
+            pat => {
+                let id = ctx.fresh_generated_var(CompilerPhase::ANormal);
+                let ty_id = ctx.intern_type(elem_ty);
+                ctx.set_var_type(id, ty_id);
+                bndrs.push((id, Expr::TupleGet(var, elem_idx), false));
+                let_pat_bndrs(ctx, pat, id, bndrs);
+            }
+        }
+    }
+}
+
+pub fn anormal_pgm(ctx: &mut Ctx, pgm: Vec<parser::Phrase>) -> Vec<Phrase> {
+    let mut phrases: Vec<Phrase> = Vec::with_capacity(pgm.len());
+
+    for phrase in pgm {
+        match phrase {
+            parser::Phrase::Let { bndr, rhs } => {
+                ctx.add_global(bndr);
+                let rhs = anormal(ctx, rhs);
+                phrases.push(Phrase::Let { id: bndr, rhs });
+            }
+
+            parser::Phrase::LetRec { defs } => {
+                for def in &defs {
+                    ctx.add_global(def.bndr);
+                }
+                phrases.push(Phrase::LetRec(anormal_fun_defs(ctx, defs)));
+            }
+
+            parser::Phrase::LetTuple { pat, rhs } => {
+                let (rhs, rhs_ty) = anormal_(ctx, rhs);
+                if let Pat::Unit = pat {
+                    // let () = ...
+                    phrases.push(Phrase::Expr(rhs));
+                    continue;
+                }
+
+                // The tuple and its nested tuples are only used in the phrases below, so they
+                // don't need to be globals
+                let (rhs_tmp, rhs_id) = mk_let(ctx, rhs, rhs_ty);
+                if let TmpLet::TmpLet { id, rhs, .. } = rhs_tmp {
+                    phrases.push(Phrase::Let { id, rhs: *rhs });
+                }
+
+                let mut bndrs = vec![];
+                let_pat_bndrs(ctx, pat, rhs_id, &mut bndrs);
+                for (bndr, rhs, is_pat_var) in bndrs {
+                    if is_pat_var {
+                        ctx.add_global(bndr);
+                    }
+                    phrases.push(Phrase::Let { id: bndr, rhs });
+                }
+            }
+
+            parser::Phrase::LetRecord { fields, rhs } => {
+                let (rhs, rhs_ty) = anormal_(ctx, rhs);
+                let (rhs_tmp, rhs_id) = mk_let(ctx, rhs, rhs_ty);
+                if let TmpLet::TmpLet { id, rhs, .. } = rhs_tmp {
+                    phrases.push(Phrase::Let { id, rhs: *rhs });
+                }
+
+                for (field, bndr) in fields {
+                    ctx.add_global(bndr);
+                    let field_idx = ctx.field_info(field).idx;
+                    phrases.push(Phrase::Let {
+                        id: bndr,
+                        rhs: Expr::TupleGet(rhs_id, field_idx),
+                    });
+                }
+            }
+
+            parser::Phrase::Expr(expr) => {
+                phrases.push(Phrase::Expr(anormal(ctx, expr)));
+            }
+
+            // Constructors are in `Ctx`
+            parser::Phrase::Type { .. } | parser::Phrase::Exception(_) => {}
+        }
+    }
+
+    phrases
+}
+
+fn anormal_fun_defs(ctx: &mut Ctx, defs: Vec<parser::FunDef>) -> Vec<FunDef> {
+    let mut funs: Vec<FunDef> = Vec::with_capacity(defs.len());
+
+    for def in &defs {
+        ctx.add_fun_arity(def.bndr, def.args.len());
+    }
+
+    for parser::FunDef { bndr, args, rhs } in defs {
+        let mut arg_tys: Vec<Type> = Vec::with_capacity(args.len());
+        for arg in &args {
+            arg_tys.push((*ctx.var_type(*arg)).clone());
+        }
+
+        let (rhs, rhs_ty_id) = anormal_(ctx, *rhs);
+        let rhs_ty = (*ctx.get_type(rhs_ty_id)).clone();
+        let fun_ty = Type::Fun {
+            args: arg_tys,
+            ret: Box::new(rhs_ty),
+        };
+
+        funs.push(FunDef {
+            name: bndr,
+            ty_id: ctx.intern_type(fun_ty),
+            args,
+            rhs: Box::new(rhs),
+        });
+    }
+
+    funs
+}
+
+// Apply `fun` to `args`. Functions defined with `let rec` and built-ins are called with the
+// number of arguments they take: with fewer arguments this allocates closures that take the rest of
+// the arguments, with more arguments the function returns a function that is applied to the rest.
+// Other functions are function values, which take one argument at a time (see `fun_value`), as
+// the number of arguments in the type of a function value can be different from the number of
+// arguments of the function.
+fn anormal_app(
+    ctx: &mut Ctx, fun: VarId, fun_ty_id: TypeId, mut args: Vec<VarId>,
+) -> (Expr, TypeId) {
+    let (arg_tys, ret_ty): (Vec<Type>, Type) = match &*ctx.get_type(fun_ty_id) {
+        Type::Fun { args, ret } => (args.clone(), (**ret).clone()),
+        other => panic!("Non-function in function position: {:?} : {:?}", fun, other),
+    };
+
+    let arity = ctx.fun_arity(fun).unwrap_or(1);
+    if args.len() < arity {
+        let n_args = args.len();
+        return curry_fun(ctx, fun, args, &arg_tys[n_args..], &ret_ty);
+    }
+
+    // Function value with more than one argument in the type: rebind it with the type of a
+    // function of one argument, which is used to find the type of the return value
+    let (fun_tmp, fun, ret_ty) = if arity < arg_tys.len() {
+        let ret_ty = Type::Fun {
+            args: arg_tys[arity..].to_vec(),
+            ret: Box::new(ret_ty),
+        };
+        let fun_ty_id = ctx.intern_type(Type::Fun {
+            args: arg_tys[..arity].to_vec(),
+            ret: Box::new(ret_ty.clone()),
+        });
+        let id = ctx.fresh_generated_var(CompilerPhase::ANormal);
+        ctx.set_var_type(id, fun_ty_id);
+        let fun_tmp = TmpLet::TmpLet {
+            id,
+            ty_id: fun_ty_id,
+            rhs: Box::new(Expr::Var(fun)),
+        };
+        (fun_tmp, id, ret_ty)
+    } else {
+        (TmpLet::NoNeed, fun, ret_ty)
+    };
+
+    let ret_ty_id = ctx.intern_type(ret_ty);
+    let rest_args = args.split_off(arity);
+    if rest_args.is_empty() {
+        return (fun_tmp.finish(Expr::App(fun, args)), ret_ty_id);
+    }
+
+    let (ret_tmp, ret_id) = mk_let(ctx, Expr::App(fun, args), ret_ty_id);
+    let (e, ty_id) = anormal_app(ctx, ret_id, ret_ty_id, rest_args);
+    (fun_tmp.finish(ret_tmp.finish(e)), ty_id)
+}
+
+// A closure that takes the arguments with types `arg_tys` one at a time, then applies `fun` to
+// `args` and the arguments
+fn curry_fun(
+    ctx: &mut Ctx, fun: VarId, mut args: Vec<VarId>, arg_tys: &[Type], ret_ty: &Type,
+) -> (Expr, TypeId) {
+    let (arg_ty, rest_tys) = match arg_tys.split_first() {
+        None => return (Expr::App(fun, args), ctx.intern_type(ret_ty.clone())),
+        Some(split) => split,
+    };
+
+    let arg = ctx.fresh_generated_var(CompilerPhase::ANormal);
+    let arg_ty_id = ctx.intern_type(arg_ty.clone());
+    ctx.set_var_type(arg, arg_ty_id);
+    args.push(arg);
+    let (body, body_ty_id) = curry_fun(ctx, fun, args, rest_tys, ret_ty);
+
+    let closure_ty_id = ctx.intern_type(Type::Fun {
+        args: vec![arg_ty.clone()],
+        ret: Box::new((*ctx.get_type(body_ty_id)).clone()),
+    });
+    let closure = ctx.fresh_generated_var(CompilerPhase::ANormal);
+    ctx.set_var_type(closure, closure_ty_id);
+    let e = Expr::LetRec {
+        funs: vec![FunDef {
+            name: closure,
+            ty_id: closure_ty_id,
+            args: vec![arg],
+            rhs: Box::new(body),
+        }],
+        body: Box::new(Expr::Var(closure)),
+    };
+    (e, closure_ty_id)
+}
+
+// `fun` as a value. Functions that take more than one argument are wrapped in closures that take
+// one argument at a time, see `anormal_app`.
+fn fun_value(ctx: &mut Ctx, fun: VarId) -> (Expr, TypeId) {
+    let fun_ty_id = ctx.var_type_id(fun);
+    match (ctx.fun_arity(fun), &*ctx.get_type(fun_ty_id)) {
+        (Some(arity), Type::Fun { args, ret }) if arity > 1 => {
+            curry_fun(ctx, fun, vec![], args, ret)
+        }
+        _ => (Expr::Var(fun), fun_ty_id),
+    }
+}
+
+fn anormal(ctx: &mut Ctx, expr: parser::Expr) -> Expr {
     anormal_(ctx, expr).0
 }
 
-pub fn anormal_(ctx: &mut Ctx, expr: parser::Expr) -> (Expr, TypeId) {
+// `e1 && e2` is `if e1 then e2 else false`, `e1 || e2` is `if e1 then true else e2`
+fn anormal_and_or(ctx: &mut Ctx, e1: parser::Expr, e2: parser::Expr, is_and: bool) -> Expr {
+    let int = ctx.int_type_id();
+    let e1 = anormal(ctx, e1);
+    let (tmp, var) = mk_let(ctx, e1, int);
+    let (true_tmp, true_var) = mk_let(ctx, Expr::Int(1), int);
+    let e2 = anormal(ctx, e2);
+    let (then_, else_) = if is_and { (e2, Expr::Int(0)) } else { (Expr::Int(1), e2) };
+    tmp.finish(true_tmp.finish(Expr::If(
+        var,
+        true_var,
+        Cmp::Equal,
+        Box::new(then_),
+        Box::new(else_),
+    )))
+}
+
+// `if e1 <cmp> e2 then then_ else else_`. Values of types other than int, float, bool, char, and
+// unit are compared with a comparison function generated in `codegen`, unless `phys`: `=` and
+// `<>` use the equality function, other comparisons the `compare` function.
+fn anormal_cmp(
+    ctx: &mut Ctx, e1: parser::Expr, cmp: Cmp, e2: parser::Expr, phys: bool, then_: Expr,
+    else_: Expr,
+) -> Expr {
+    let int = ctx.int_type_id();
+    let (e1, e1_ty) = anormal_(ctx, e1);
+    let (tmp1, var1) = mk_let(ctx, e1, e1_ty);
+    let (e2, e2_ty) = anormal_(ctx, e2);
+    let (tmp2, var2) = mk_let(ctx, e2, e2_ty);
+    let structural = !matches!(
+        &*ctx.get_type(e1_ty),
+        Type::Int | Type::Float | Type::Bool | Type::Char | Type::Unit | Type::Var(_)
+    );
+    if phys || !structural {
+        return tmp1.finish(tmp2.finish(Expr::If(
+            var1,
+            var2,
+            cmp,
+            Box::new(then_),
+            Box::new(else_),
+        )));
+    }
+
+    // `StructCmp::Equal` returns 1 for equal values, `StructCmp::Compare` returns 0
+    let (kind, expected) = match cmp {
+        Cmp::Equal | Cmp::NotEqual => (StructCmp::Equal, 1),
+        _ => (StructCmp::Compare, 0),
+    };
+    let (res_tmp, res_var) = mk_let(ctx, Expr::StructCmp(kind, var1, var2), int);
+    let (expected_tmp, expected_var) = mk_let(ctx, Expr::Int(expected), int);
+    tmp1.finish(tmp2.finish(res_tmp.finish(expected_tmp.finish(Expr::If(
+        res_var,
+        expected_var,
+        cmp,
+        Box::new(then_),
+        Box::new(else_),
+    )))))
+}
+
+// Print the text and directives of the format string, with `args` for the directives
+fn anormal_printf(ctx: &mut Ctx, fmt: &str, args: &[VarId]) -> Expr {
+    let unit = ctx.unit_type_id();
+    let mut args = args.iter();
+    let mut calls: Vec<Expr> = vec![];
+    // Type checker validated the format string
+    for piece in format::parse(fmt).unwrap() {
+        let (spec, conv, arg) = match piece {
+            format::Piece::Text(text) => {
+                (String::new(), Conv::String, ctx.add_str_lit(CompilerPhase::ANormal, &text))
+            }
+            format::Piece::Directive { spec, conv } => (spec, conv, *args.next().unwrap()),
+        };
+        let spec = ctx.add_str_lit(CompilerPhase::ANormal, &spec);
+        calls.push(Expr::App(ctx.printf_var(conv), vec![spec, arg]));
+    }
+    calls.into_iter().rev().fold(Expr::Unit, |rest, call| {
+        let id = ctx.fresh_generated_var(CompilerPhase::ANormal);
+        ctx.set_var_type(id, unit);
+        Expr::Let {
+            id,
+            ty_id: unit,
+            rhs: Box::new(call),
+            body: Box::new(rest),
+        }
+    })
+}
+
+fn anormal_(ctx: &mut Ctx, expr: parser::Expr) -> (Expr, TypeId) {
     let int = ctx.int_type_id();
     let float = ctx.float_type_id();
     let unit = ctx.unit_type_id();
 
-    match expr {
-        parser::Expr::Unit => (Expr::Unit, unit),
-        parser::Expr::Bool(b) => (Expr::Int(if b { 1 } else { 0 }), int),
-        parser::Expr::Int(i) => (Expr::Int(i), int),
-        parser::Expr::Float(f) => (Expr::Float(f), float),
-
-        parser::Expr::Not(e) => anormal_(
-            ctx,
-            parser::Expr::If(
-                e,
-                Box::new(parser::Expr::Bool(false)),
-                Box::new(parser::Expr::Bool(true)),
-            ),
-        ),
-
-        parser::Expr::Neg(e) => {
+    match expr.kind {
+        parser::ExprKind::Unit => (Expr::Unit, unit),
+        parser::ExprKind::Bool(b) => (Expr::Int(if b { 1 } else { 0 }), int),
+        parser::ExprKind::Int(i) => (Expr::Int(i), int),
+        parser::ExprKind::Float(f) => (Expr::Float(f), float),
+        parser::ExprKind::Char(c) => (Expr::Int(i64::from(c)), int),
+        parser::ExprKind::String(s) => {
+            let lit = ctx.add_str_lit(CompilerPhase::ANormal, &s);
+            (Expr::Var(lit), ctx.string_type_id())
+        }
+
+        parser::ExprKind::Not(e) => {
+            let e = anormal(ctx, *e);
+            let (tmp, var) = mk_let(ctx, e, int);
+            let (true_tmp, true_var) = mk_let(ctx, Expr::Int(1), int);
+            let e = tmp.finish(true_tmp.finish(Expr::If(
+                var,
+                true_var,
+                Cmp::Equal,
+                Box::new(Expr::Int(0)),
+                Box::new(Expr::Int(1)),
+            )));
+            (e, int)
+        }
+
+        parser::ExprKind::And(e1, e2) => (anormal_and_or(ctx, *e1, *e2, true), int),
+        parser::ExprKind::Or(e1, e2) => (anormal_and_or(ctx, *e1, *e2, false), int),
+
+        parser::ExprKind::Neg(e) => {
             let e = anormal(ctx, *e);
             let (tmp, var) = mk_let(ctx, e, int);
             (tmp.finish(Expr::Neg(var)), int)
         }
 
-        parser::Expr::FNeg(e) => {
+        parser::ExprKind::FNeg(e) => {
             let e = anormal(ctx, *e);
             let (tmp, var) = mk_let(ctx, e, float);
             (tmp.finish(Expr::FNeg(var)), float)
         }
 
-        parser::Expr::IntBinOp(e1, op, e2) => {
+        parser::ExprKind::IntBinOp(e1, op, e2) => {
             let e1 = anormal(ctx, *e1);
             let (tmp1, arg1) = mk_let(ctx, e1, int);
             let e2 = anormal(ctx, *e2);
@@ -130,7 +525,7 @@ pub fn anormal_(ctx: &mut Ctx, expr: parser::Expr) -> (Expr, TypeId) {
             (e, int)
         }
 
-        parser::Expr::FloatBinOp(e1, op, e2) => {
+        parser::ExprKind::FloatBinOp(e1, op, e2) => {
             let e1 = anormal(ctx, *e1);
             let (tmp1, arg1) = mk_let(ctx, e1, float);
             let e2 = anormal(ctx, *e2);
@@ -139,45 +534,55 @@ pub fn anormal_(ctx: &mut Ctx, expr: parser::Expr) -> (Expr, TypeId) {
             (e, float)
         }
 
-        parser::Expr::Cmp(e1, cmp, e2) => {
-            let (e1, e1_ty) = anormal_(ctx, *e1);
-            let (tmp1, var1) = mk_let(ctx, e1, e1_ty);
-            let (e2, e2_ty) = anormal_(ctx, *e2);
-            // assert_eq!(e1_ty, e2_ty);
-            let (tmp2, var2) = mk_let(ctx, e2, e2_ty);
-            let e = tmp1.finish(tmp2.finish(Expr::If(
-                var1,
-                var2,
-                cmp,
-                Box::new(Expr::Int(1)),
-                Box::new(Expr::Int(0)),
-            )));
+        parser::ExprKind::Cmp(e1, cmp, e2) => {
+            let e = anormal_cmp(ctx, *e1, cmp, *e2, false, Expr::Int(1), Expr::Int(0));
             (e, int)
         }
 
-        parser::Expr::If(box parser::Expr::Cmp(e1, cmp, e2), then_, else_) => {
+        parser::ExprKind::PhysCmp(e1, cmp, e2) => {
+            let e = anormal_cmp(ctx, *e1, cmp, *e2, true, Expr::Int(1), Expr::Int(0));
+            (e, int)
+        }
+
+        parser::ExprKind::Compare(e1, e2) => {
             let (e1, e1_ty) = anormal_(ctx, *e1);
             let (tmp1, var1) = mk_let(ctx, e1, e1_ty);
             let (e2, e2_ty) = anormal_(ctx, *e2);
-            // assert_eq!(e1_ty, e2_ty);
             let (tmp2, var2) = mk_let(ctx, e2, e2_ty);
+            let e = Expr::StructCmp(StructCmp::Compare, var1, var2);
+            (tmp1.finish(tmp2.finish(e)), int)
+        }
+
+        parser::ExprKind::If(
+            box parser::Expr {
+                kind: parser::ExprKind::Cmp(e1, cmp, e2),
+                ..
+            },
+            then_,
+            else_,
+        ) => {
             let (then_, ty) = anormal_(ctx, *then_);
             let else_ = anormal(ctx, *else_);
-            let e = tmp1.finish(tmp2.finish(Expr::If(
-                var1,
-                var2,
-                cmp,
-                Box::new(then_),
-                Box::new(else_),
-            )));
-            (e, ty)
+            (anormal_cmp(ctx, *e1, cmp, *e2, false, then_, else_), ty)
+        }
+
+        parser::ExprKind::If(
+            box parser::Expr {
+                kind: parser::ExprKind::PhysCmp(e1, cmp, e2),
+                ..
+            },
+            then_,
+            else_,
+        ) => {
+            let (then_, ty) = anormal_(ctx, *then_);
+            let else_ = anormal(ctx, *else_);
+            (anormal_cmp(ctx, *e1, cmp, *e2, true, then_, else_), ty)
         }
 
-        parser::Expr::If(cond, then_, else_) => {
+        parser::ExprKind::If(cond, then_, else_) => {
             let cond = anormal(ctx, *cond);
             let (cond_tmp, cond_var) = mk_let(ctx, cond, int);
-            let true_ = anormal(ctx, parser::Expr::Bool(true));
-            let (true_tmp, true_var) = mk_let(ctx, true_, int);
+            let (true_tmp, true_var) = mk_let(ctx, Expr::Int(1), int);
             let (then_, ty) = anormal_(ctx, *then_);
             let else_ = anormal(ctx, *else_);
             let e = cond_tmp.finish(true_tmp.finish(Expr::If(
@@ -190,7 +595,7 @@ pub fn anormal_(ctx: &mut Ctx, expr: parser::Expr) -> (Expr, TypeId) {
             (e, ty)
         }
 
-        parser::Expr::Let { bndr, rhs, body } => {
+        parser::ExprKind::Let { bndr, rhs, body } => {
             let (rhs, rhs_ty) = anormal_(ctx, *rhs);
             let (body, body_ty) = anormal_(ctx, *body);
             let e = Expr::Let {
@@ -202,47 +607,129 @@ pub fn anormal_(ctx: &mut Ctx, expr: parser::Expr) -> (Expr, TypeId) {
             (e, body_ty)
         }
 
-        parser::Expr::Var(var) => (Expr::Var(var), ctx.var_type_id(var)),
-
-        parser::Expr::LetRec {
-            bndr,
-            args,
-            rhs,
-            body,
-        } => {
-            let mut arg_tys: Vec<Type> = Vec::with_capacity(args.len());
-            for arg in &args {
-                arg_tys.push((&*ctx.var_type(*arg)).clone());
-            }
+        parser::ExprKind::Var(var) => fun_value(ctx, var),
 
-            let (rhs, rhs_ty_id) = anormal_(ctx, *rhs);
-            let rhs_ty = (&*ctx.get_type(rhs_ty_id)).clone();
-            let fun_ty = Type::Fun {
-                args: arg_tys,
-                ret: Box::new(rhs_ty),
-            };
+        parser::ExprKind::LetRec { defs, body } => {
+            let funs = anormal_fun_defs(ctx, defs);
             let (body, body_ty) = anormal_(ctx, *body);
 
             let e = Expr::LetRec {
-                name: bndr,
-                ty_id: ctx.intern_type(fun_ty),
-                args,
-                rhs: Box::new(rhs),
+                funs,
                 body: Box::new(body),
             };
 
             (e, body_ty)
         }
 
-        parser::Expr::App { fun, args } => {
-            let (fun, fun_ty_id) = anormal_(ctx, *fun);
-            let fun_ty = (&*ctx.get_type(fun_ty_id)).clone();
-            let ret_ty: Type = match &fun_ty {
-                Type::Fun { args: _, ret } => (**ret).clone(),
-                other => panic!("Non-function in function position: {:?} : {:?}", fun, other),
+        // `fun x y -> e` is `fun x -> fun y -> e`, as function values take one argument at a time
+        parser::ExprKind::Fun { mut args, body } if args.len() > 1 => {
+            let rest_args = args.split_off(1);
+            let body = parser::Expr {
+                kind: parser::ExprKind::Fun {
+                    args: rest_args,
+                    body,
+                },
+                span: expr.span,
+            };
+            anormal_(
+                ctx,
+                parser::Expr {
+                    kind: parser::ExprKind::Fun {
+                        args,
+                        body: Box::new(body),
+                    },
+                    span: expr.span,
+                },
+            )
+        }
+
+        parser::ExprKind::Fun { args, body } => {
+            // A `fun` is a `let rec` of a function that is not referred to in its body
+            let name = ctx.fresh_generated_var(CompilerPhase::ANormal);
+            let def = parser::FunDef {
+                bndr: name,
+                args,
+                rhs: body,
+            };
+            let funs = anormal_fun_defs(ctx, vec![def]);
+            let fun_ty = funs[0].ty_id;
+            ctx.set_var_type(name, fun_ty);
+
+            let e = Expr::LetRec {
+                funs,
+                body: Box::new(Expr::Var(name)),
+            };
+
+            (e, fun_ty)
+        }
+
+        // `Printf.printf` applied to an argument for each directive. Other uses are compiled to a
+        // function that takes the arguments, below.
+        parser::ExprKind::App {
+            fun:
+                box parser::Expr {
+                    kind: parser::ExprKind::Printf(fmt),
+                    ..
+                },
+            args,
+        } if format::arg_tys(&format::parse(&fmt).unwrap()).len() == args.len() => {
+            let mut arg_ids: Vec<VarId> = Vec::with_capacity(args.len());
+            let mut arg_tmps: Vec<TmpLet> = Vec::with_capacity(args.len());
+            for arg in args {
+                let (arg, arg_ty) = anormal_(ctx, arg);
+                let (arg_tmp, arg_id) = mk_let(ctx, arg, arg_ty);
+                arg_ids.push(arg_id);
+                arg_tmps.push(arg_tmp);
+            }
+
+            let printf = anormal_printf(ctx, &fmt, &arg_ids);
+            let e = arg_tmps
+                .into_iter()
+                .rev()
+                .fold(printf, |acc, arg_tmp| arg_tmp.finish(acc));
+
+            (e, unit)
+        }
+
+        parser::ExprKind::Printf(fmt) => {
+            let arg_tys = format::arg_tys(&format::parse(&fmt).unwrap());
+            if arg_tys.is_empty() {
+                return (anormal_printf(ctx, &fmt, &[]), unit);
+            }
+            let fun_ty_id = ctx.intern_type(Type::Fun {
+                args: arg_tys.clone(),
+                ret: Box::new(Type::Unit),
+            });
+            let fun = ctx.fresh_generated_var(CompilerPhase::ANormal);
+            ctx.set_var_type(fun, fun_ty_id);
+            let mut args: Vec<VarId> = Vec::with_capacity(arg_tys.len());
+            for arg_ty in arg_tys {
+                let arg = ctx.fresh_generated_var(CompilerPhase::ANormal);
+                let arg_ty_id = ctx.intern_type(arg_ty);
+                ctx.set_var_type(arg, arg_ty_id);
+                args.push(arg);
+            }
+            ctx.add_fun_arity(fun, args.len());
+            let rhs = anormal_printf(ctx, &fmt, &args);
+            let (value, value_ty_id) = fun_value(ctx, fun);
+            let e = Expr::LetRec {
+                funs: vec![FunDef {
+                    name: fun,
+                    ty_id: fun_ty_id,
+                    args,
+                    rhs: Box::new(rhs),
+                }],
+                body: Box::new(value),
             };
-            let ret_ty_id = ctx.intern_type(ret_ty);
+            (e, value_ty_id)
+        }
 
+        parser::ExprKind::App { fun, args } => {
+            // Functions defined with `let rec` are applied directly, not as values
+            let (fun, fun_ty_id) = match fun.kind {
+                parser::ExprKind::Var(var) => (Expr::Var(var), ctx.var_type_id(var)),
+                _ => anormal_(ctx, *fun),
+            };
             let (fun_tmp, fun_id) = mk_let(ctx, fun, fun_ty_id);
 
             let mut arg_ids: Vec<VarId> = Vec::with_capacity(args.len());
@@ -254,7 +741,8 @@ pub fn anormal_(ctx: &mut Ctx, expr: parser::Expr) -> (Expr, TypeId) {
                 arg_tmps.push(arg_tmp);
             }
 
-            let fun_call = fun_tmp.finish(Expr::App(fun_id, arg_ids));
+            let (fun_call, ret_ty_id) = anormal_app(ctx, fun_id, fun_ty_id, arg_ids);
+            let fun_call = fun_tmp.finish(fun_call);
 
             let e = arg_tmps
                 .into_iter()
@@ -264,14 +752,14 @@ pub fn anormal_(ctx: &mut Ctx, expr: parser::Expr) -> (Expr, TypeId) {
             (e, ret_ty_id)
         }
 
-        parser::Expr::Tuple(args) => {
+        parser::ExprKind::Tuple(args) => {
             let mut arg_ids: Vec<VarId> = Vec::with_capacity(args.len());
             let mut arg_tmps: Vec<TmpLet> = Vec::with_capacity(args.len());
             let mut arg_tys: Vec<Type> = Vec::with_capacity(args.len());
 
             for arg in args {
                 let (arg, arg_ty_id) = anormal_(ctx, arg);
-                let arg_ty = (&*ctx.get_type(arg_ty_id)).clone();
+                let arg_ty = (*ctx.get_type(arg_ty_id)).clone();
                 let (arg_tmp, arg_id) = mk_let(ctx, arg, arg_ty_id);
                 arg_ids.push(arg_id);
                 arg_tmps.push(arg_tmp);
@@ -288,34 +776,45 @@ pub fn anormal_(ctx: &mut Ctx, expr: parser::Expr) -> (Expr, TypeId) {
             (e, ctx.intern_type(Type::Tuple(arg_tys)))
         }
 
-        parser::Expr::LetTuple { bndrs, rhs, body } => {
+        parser::ExprKind::Annot(e, _) => anormal_(ctx, *e),
+
+        // Empty list is 0, a cons cell is a tuple of the head and the tail
+        parser::ExprKind::Nil { ty } => (Expr::Int(0), ctx.var_type_id(ty)),
+
+        parser::ExprKind::Cons(hd, tl) => {
+            let (hd, hd_ty_id) = anormal_(ctx, *hd);
+            let (hd_tmp, hd_id) = mk_let(ctx, hd, hd_ty_id);
+            let (tl, tl_ty_id) = anormal_(ctx, *tl);
+            let (tl_tmp, tl_id) = mk_let(ctx, tl, tl_ty_id);
+            (hd_tmp.finish(tl_tmp.finish(Expr::Tuple(vec![hd_id, tl_id]))), tl_ty_id)
+        }
+
+        parser::ExprKind::LetTuple { pat, rhs, body } => {
             let (body, body_ty) = anormal_(ctx, *body);
 
             let (rhs, rhs_ty) = anormal_(ctx, *rhs);
             let (rhs_tmp, rhs_id) = mk_let(ctx, rhs, rhs_ty);
 
-            let e = bndrs
-                .into_iter()
-                .enumerate()
-                .rev()
-                .fold(body, |expr, (bndr_idx, bndr)| {
-                    let ty_id = ctx.var_type_id(bndr);
-                    Expr::Let {
-                        id: bndr,
-                        ty_id,
-                        rhs: Box::new(Expr::TupleGet(rhs_id, bndr_idx)),
-                        body: Box::new(expr),
-                    }
-                });
+            let mut bndrs = vec![];
+            let_pat_bndrs(ctx, pat, rhs_id, &mut bndrs);
+            let e = bndrs.into_iter().rev().fold(body, |expr, (bndr, rhs, _)| {
+                let ty_id = ctx.var_type_id(bndr);
+                Expr::Let {
+                    id: bndr,
+                    ty_id,
+                    rhs: Box::new(rhs),
+                    body: Box::new(expr),
+                }
+            });
 
             (rhs_tmp.finish(e), body_ty)
         }
 
-        parser::Expr::Array { len, elem } => {
+        parser::ExprKind::Array { len, elem } => {
             let (len, len_ty_id) = anormal_(ctx, *len);
             assert_eq!(len_ty_id, int);
             let (elem, elem_ty_id) = anormal_(ctx, *elem);
-            let elem_ty = (&*ctx.get_type(elem_ty_id)).clone();
+            let elem_ty = (*ctx.get_type(elem_ty_id)).clone();
             let (len_tmp, len_id) = mk_let(ctx, len, len_ty_id);
             let (elem_tmp, elem_id) = mk_let(ctx, elem, elem_ty_id);
 
@@ -328,7 +827,7 @@ pub fn anormal_(ctx: &mut Ctx, expr: parser::Expr) -> (Expr, TypeId) {
             )
         }
 
-        parser::Expr::Get(e1, e2) => {
+        parser::ExprKind::Get(e1, e2) => {
             let (e1, e1_ty_id) = anormal_(ctx, *e1);
             let elem_ty = match &*ctx.get_type(e1_ty_id) {
                 Type::Array(elem) => (**elem).clone(),
@@ -344,7 +843,7 @@ pub fn anormal_(ctx: &mut Ctx, expr: parser::Expr) -> (Expr, TypeId) {
             (e, ctx.intern_type(elem_ty))
         }
 
-        parser::Expr::Put(e1, e2, e3) => {
+        parser::ExprKind::Put(e1, e2, e3) => {
             let (e1, e1_ty_id) = anormal_(ctx, *e1);
             // assert!(e1_ty.is_array());
             let (e2, e2_ty_id) = anormal_(ctx, *e2);
@@ -360,5 +859,316 @@ pub fn anormal_(ctx: &mut Ctx, expr: parser::Expr) -> (Expr, TypeId) {
 
             (e, unit)
         }
+
+        parser::ExprKind::For {
+            var,
+            from,
+            to,
+            dir,
+            body,
+        } => {
+            let (from, from_ty_id) = anormal_(ctx, *from);
+            let (to, to_ty_id) = anormal_(ctx, *to);
+            let (from_tmp, from_id) = mk_let(ctx, from, from_ty_id);
+            let (to_tmp, to_id) = mk_let(ctx, to, to_ty_id);
+            let body = anormal(ctx, *body);
+            let e = Expr::For {
+                var,
+                from: from_id,
+                to: to_id,
+                dir,
+                body: Box::new(body),
+            };
+            (from_tmp.finish(to_tmp.finish(e)), unit)
+        }
+
+        parser::ExprKind::While { cond, body } => {
+            let cond = anormal(ctx, *cond);
+            let body = anormal(ctx, *body);
+            let e = Expr::While {
+                cond: Box::new(cond),
+                body: Box::new(body),
+            };
+            (e, unit)
+        }
+
+        // References are tuples with one field
+        parser::ExprKind::Ref(e) => {
+            let (e, e_ty_id) = anormal_(ctx, *e);
+            let e_ty = (*ctx.get_type(e_ty_id)).clone();
+            let (e_tmp, e_id) = mk_let(ctx, e, e_ty_id);
+            (
+                e_tmp.finish(Expr::Tuple(vec![e_id])),
+                ctx.intern_type(Type::Ref(Box::new(e_ty))),
+            )
+        }
+
+        parser::ExprKind::Deref(e) => {
+            let (e, e_ty_id) = anormal_(ctx, *e);
+            let elem_ty = match &*ctx.get_type(e_ty_id) {
+                Type::Ref(elem) => (**elem).clone(),
+                other => panic!("Non-ref type in Deref: {:?}", other),
+            };
+            let (e_tmp, e_id) = mk_let(ctx, e, e_ty_id);
+            (e_tmp.finish(Expr::TupleGet(e_id, 0)), ctx.intern_type(elem_ty))
+        }
+
+        parser::ExprKind::Assign(e1, e2) => {
+            let (e1, e1_ty_id) = anormal_(ctx, *e1);
+            let (e2, e2_ty_id) = anormal_(ctx, *e2);
+            let (e1_tmp, e1_id) = mk_let(ctx, e1, e1_ty_id);
+            let (e2_tmp, e2_id) = mk_let(ctx, e2, e2_ty_id);
+            (e1_tmp.finish(e2_tmp.finish(Expr::TuplePut(e1_id, 0, e2_id))), unit)
+        }
+
+        parser::ExprKind::Match { scrut, cases } => {
+            let (scrut, scrut_ty_id) = anormal_(ctx, *scrut);
+            let (scrut_tmp, scrut_id) = mk_let(ctx, scrut, scrut_ty_id);
+
+            // Parser doesn't allow empty matches
+            let mut ty_id = unit;
+            let mut cases_: Vec<Case> = Vec::with_capacity(cases.len());
+            for parser::Case { pat, span, rhs } in cases {
+                let (rhs, rhs_ty_id) = anormal_(ctx, rhs);
+                ty_id = rhs_ty_id;
+                cases_.push(Case { pat, span, rhs });
+            }
+
+            let e = Expr::Match {
+                scrut: scrut_id,
+                cases: cases_,
+                span: expr.span,
+                handler: false,
+            };
+
+            (scrut_tmp.finish(e), ty_id)
+        }
+
+        parser::ExprKind::Raise { exn, ty } => {
+            let (exn, exn_ty_id) = anormal_(ctx, *exn);
+            let (exn_tmp, exn_id) = mk_let(ctx, exn, exn_ty_id);
+            (exn_tmp.finish(Expr::Raise(exn_id)), ctx.var_type_id(ty))
+        }
+
+        // The body is evaluated in a function called by the RTS built-in for `try`, which returns
+        // 0 when the function returns, or the exception raised by the function. Value of the body
+        // is passed in a reference. Roughly:
+        //
+        //     let cell = ref <default value> in
+        //     let body () = cell := <body> in
+        //     let exn = %try body in
+        //     if exn = 0 then !cell else match exn with <cases> | _ -> raise exn
+        parser::ExprKind::Try { body, cases } => {
+            let (body, body_ty_id) = anormal_(ctx, *body);
+            let body_ty = ctx.get_type(body_ty_id);
+
+            let init = match RepType::from(&*body_ty) {
+                RepType::Word => Expr::Int(0),
+                RepType::Float => Expr::Float(0.0),
+            };
+            let (init_tmp, init_id) = mk_let(ctx, init, body_ty_id);
+            let cell_ty_id = ctx.intern_type(Type::Ref(Box::new((*body_ty).clone())));
+            let (cell_tmp, cell_id) = mk_let(ctx, Expr::Tuple(vec![init_id]), cell_ty_id);
+
+            let body_fun_ty_id = ctx.intern_type(Type::Fun {
+                args: vec![Type::Unit],
+                ret: Box::new(Type::Unit),
+            });
+            let body_fun = ctx.fresh_generated_var(CompilerPhase::ANormal);
+            ctx.set_var_type(body_fun, body_fun_ty_id);
+            let body_fun_arg = ctx.fresh_generated_var(CompilerPhase::ANormal);
+            ctx.set_var_type(body_fun_arg, unit);
+            let (body_tmp, body_id) = mk_let(ctx, body, body_ty_id);
+            let body_fun_def = FunDef {
+                name: body_fun,
+                ty_id: body_fun_ty_id,
+                args: vec![body_fun_arg],
+                rhs: Box::new(body_tmp.finish(Expr::TuplePut(cell_id, 0, body_id))),
+            };
+
+            let exn_word = ctx.fresh_generated_var(CompilerPhase::ANormal);
+            ctx.set_var_type(exn_word, int);
+            let (zero_tmp, zero_id) = mk_let(ctx, Expr::Int(0), int);
+
+            let exn_ty_id = ctx.intern_type(Type::Con(ctx.exn_ty_con()));
+            let exn = ctx.fresh_generated_var(CompilerPhase::ANormal);
+            ctx.set_var_type(exn, exn_ty_id);
+            let cases: Vec<Case> = cases
+                .into_iter()
+                .map(|parser::Case { pat, span, rhs }| Case {
+                    pat,
+                    span,
+                    rhs: anormal(ctx, rhs),
+                })
+                .collect();
+            let handler = Expr::Let {
+                id: exn,
+                ty_id: exn_ty_id,
+                rhs: Box::new(Expr::Var(exn_word)),
+                body: Box::new(Expr::Match {
+                    scrut: exn,
+                    cases,
+                    span: expr.span,
+                    handler: true,
+                }),
+            };
+
+            let e = Expr::LetRec {
+                funs: vec![body_fun_def],
+                body: Box::new(Expr::Let {
+                    id: exn_word,
+                    ty_id: int,
+                    rhs: Box::new(Expr::App(ctx.try_var(), vec![body_fun])),
+                    body: Box::new(zero_tmp.finish(Expr::If(
+                        exn_word,
+                        zero_id,
+                        Cmp::Equal,
+                        Box::new(Expr::TupleGet(cell_id, 0)),
+                        Box::new(handler),
+                    ))),
+                }),
+            };
+
+            (init_tmp.finish(cell_tmp.finish(e)), body_ty_id)
+        }
+
+        // `assert e` is `if e then () else raise (Assert_failure (<file>, <line>, <column>))`,
+        // with OCaml's 0-based column
+        parser::ExprKind::Assert(cond) => {
+            let cond = anormal(ctx, *cond);
+            let (cond_tmp, cond_var) = mk_let(ctx, cond, int);
+            let (true_tmp, true_var) = mk_let(ctx, Expr::Int(1), int);
+
+            let loc = span::line_col(&ctx.source(), expr.span.start);
+            let file = ctx.add_str_lit(CompilerPhase::ANormal, &ctx.source_file());
+            let (line_tmp, line) = mk_let(ctx, Expr::Int(i64::from(loc.line)), int);
+            let (col_tmp, col) = mk_let(ctx, Expr::Int(i64::from(loc.col) - 1), int);
+            let exn_name = ctx.con_info(ctx.assert_failure_con()).exn_name.unwrap();
+            let exn_ty_id = ctx.intern_type(Type::Con(ctx.exn_ty_con()));
+            let (exn_tmp, exn) =
+                mk_let(ctx, Expr::Tuple(vec![exn_name, file, line, col]), exn_ty_id);
+            let raise = line_tmp.finish(col_tmp.finish(exn_tmp.finish(Expr::Raise(exn))));
+
+            let e = cond_tmp.finish(true_tmp.finish(Expr::If(
+                cond_var,
+                true_var,
+                Cmp::Equal,
+                Box::new(Expr::Unit),
+                Box::new(raise),
+            )));
+            (e, unit)
+        }
+
+        parser::ExprKind::Con { con, arg } => {
+            let info = ctx.con_info(con).clone();
+            let ty_id = ctx.intern_type(Type::Con(info.ty_con));
+
+            let args: Vec<parser::Expr> = match arg {
+                None if info.exn_name.is_none() => {
+                    return (Expr::Int(info.tag), ty_id);
+                }
+                None => vec![],
+                Some(arg) => {
+                    if info.args.len() > 1 {
+                        // Checked in the type checker
+                        match arg.kind {
+                            parser::ExprKind::Tuple(args) => args,
+                            _ => panic!("Non-tuple argument of constructor {:?}", con),
+                        }
+                    } else {
+                        vec![*arg]
+                    }
+                }
+            };
+
+            // Tag or exception name in field 0, arguments in the rest
+            let (tag_tmp, tag_id) = match info.exn_name {
+                None => mk_let(ctx, Expr::Int(info.tag), int),
+                Some(exn_name) => (TmpLet::NoNeed, exn_name),
+            };
+            let mut field_ids: Vec<VarId> = Vec::with_capacity(args.len() + 1);
+            let mut field_tmps: Vec<TmpLet> = Vec::with_capacity(args.len() + 1);
+            field_ids.push(tag_id);
+            field_tmps.push(tag_tmp);
+            for arg in args {
+                let (arg, arg_ty_id) = anormal_(ctx, arg);
+                let (arg_tmp, arg_id) = mk_let(ctx, arg, arg_ty_id);
+                field_ids.push(arg_id);
+                field_tmps.push(arg_tmp);
+            }
+
+            let e = field_tmps
+                .into_iter()
+                .rev()
+                .fold(Expr::Tuple(field_ids), |acc, field_tmp| field_tmp.finish(acc));
+
+            (e, ty_id)
+        }
+
+        parser::ExprKind::Record(fields) => {
+            let ty_con = ctx.field_info(fields[0].0).ty_con.clone();
+            let ty_id = ctx.intern_type(Type::Con(ty_con));
+
+            // Fields are evaluated in the order they appear, and stored in declaration order
+            let mut field_ids: Vec<Option<VarId>> = vec![None; fields.len()];
+            let mut field_tmps: Vec<TmpLet> = Vec::with_capacity(fields.len());
+            for (field, expr) in fields {
+                let field_idx = ctx.field_info(field).idx;
+                let (expr, expr_ty_id) = anormal_(ctx, expr);
+                let (expr_tmp, expr_id) = mk_let(ctx, expr, expr_ty_id);
+                field_ids[field_idx] = Some(expr_id);
+                field_tmps.push(expr_tmp);
+            }
+
+            let tuple = Expr::Tuple(field_ids.into_iter().map(Option::unwrap).collect());
+
+            let e = field_tmps
+                .into_iter()
+                .rev()
+                .fold(tuple, |acc, field_tmp| field_tmp.finish(acc));
+
+            (e, ty_id)
+        }
+
+        parser::ExprKind::GetField(record, field) => {
+            let info = ctx.field_info(field).clone();
+            let (record, record_ty_id) = anormal_(ctx, *record);
+            let (record_tmp, record_id) = mk_let(ctx, record, record_ty_id);
+            (
+                record_tmp.finish(Expr::TupleGet(record_id, info.idx)),
+                ctx.intern_type(info.ty),
+            )
+        }
+
+        parser::ExprKind::SetField(record, field, val) => {
+            let field_idx = ctx.field_info(field).idx;
+            let (record, record_ty_id) = anormal_(ctx, *record);
+            let (val, val_ty_id) = anormal_(ctx, *val);
+            let (record_tmp, record_id) = mk_let(ctx, record, record_ty_id);
+            let (val_tmp, val_id) = mk_let(ctx, val, val_ty_id);
+            (
+                record_tmp.finish(val_tmp.finish(Expr::TuplePut(record_id, field_idx, val_id))),
+                unit,
+            )
+        }
+
+        parser::ExprKind::LetRecord { fields, rhs, body } => {
+            let (body, body_ty) = anormal_(ctx, *body);
+
+            let (rhs, rhs_ty) = anormal_(ctx, *rhs);
+            let (rhs_tmp, rhs_id) = mk_let(ctx, rhs, rhs_ty);
+
+            let e = fields.into_iter().rev().fold(body, |expr, (field, bndr)| {
+                let ty_id = ctx.var_type_id(bndr);
+                Expr::Let {
+                    id: bndr,
+                    ty_id,
+                    rhs: Box::new(Expr::TupleGet(rhs_id, ctx.field_info(field).idx)),
+                    body: Box::new(expr),
+                }
+            });
+
+            (rhs_tmp.finish(e), body_ty)
+        }
     }
 }
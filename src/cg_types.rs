@@ -22,6 +22,7 @@ impl From<&Type> for RepType {
         match ty {
             Type::Var(_) => panic!("Type variable in RepType::from"),
             Type::Float => RepType::Float,
+            Type::Alias { ty, .. } => RepType::from(&**ty),
             _ => RepType::Word,
         }
     }
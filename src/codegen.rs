@@ -4,18 +4,18 @@ use cranelift_codegen::ir::condcodes::{FloatCC, IntCC};
 use cranelift_codegen::ir::entities::{Block, FuncRef, SigRef, Value};
 use cranelift_codegen::ir::types::*;
 use cranelift_codegen::ir::MemFlags;
-use cranelift_codegen::ir::{AbiParam, InstBuilder, Signature};
+use cranelift_codegen::ir::{AbiParam, Function, InstBuilder, Signature, TrapCode};
 use cranelift_codegen::isa::CallConv;
 use cranelift_codegen::settings;
 use cranelift_codegen::verifier::verify_function;
 use cranelift_frontend::{FunctionBuilder, FunctionBuilderContext, Variable};
-use cranelift_module::{default_libcall_names, DataId, FuncId, Linkage, Module};
+use cranelift_module::{default_libcall_names, DataContext, DataId, FuncId, Linkage, Module};
 use cranelift_object::{ObjectBackend, ObjectBuilder, ObjectProduct};
 
 use fxhash::{FxHashMap, FxHashSet};
 
 use crate::cg_types::RepType;
-use crate::common::{BinOp, Cmp, FloatBinOp, IntBinOp};
+use crate::common::{BinOp, Cmp, FloatBinOp, IntBinOp, StructCmp};
 use crate::ctx::{Ctx, VarId};
 use crate::lower;
 use crate::type_check;
@@ -36,9 +36,9 @@ pub fn codegen(ctx: &mut Ctx, funs: &[lower::Fun], main_id: VarId, dump: bool) -
 
     let mut fn_builder_ctx: FunctionBuilderContext = FunctionBuilderContext::new();
 
-    // Declare malloc at module-level and pass the id to code gen to be able to generate malloc
-    // calls.
-    let malloc_id = declare_malloc(&mut module);
+    // Declare RTS functions at module-level and pass the ids to code gen to be able to generate
+    // calls to them.
+    let rts_ids = declare_rts_funs(&mut module);
 
     // Global env is not mutable as we never add anything to it. Declarations in basic blocks are
     // done directly using the FunctionBuilder. When a variable isn't bound in 'env' it assumes
@@ -48,19 +48,38 @@ pub fn codegen(ctx: &mut Ctx, funs: &[lower::Fun], main_id: VarId, dump: bool) -
     // it in an immutable way.
     let (env, main_fun_id) = init_module_env(ctx, &mut module, funs, main_id);
 
+    let mut cmp_funs = CmpFuns::new();
+
     // Generate code for functions
     for fun in funs {
         codegen_fun(
             ctx,
             &mut module,
             &env,
-            malloc_id,
+            rts_ids,
+            &mut cmp_funs,
             fun,
             &mut fn_builder_ctx,
             dump,
         );
     }
 
+    // Generate comparison functions used by the functions above, and the ones used by those
+    while let Some((kind, ty, fun_id)) = cmp_funs.undefined.pop() {
+        codegen_cmp_fun(
+            ctx,
+            &mut module,
+            &env,
+            rts_ids,
+            &mut cmp_funs,
+            kind,
+            &ty,
+            fun_id,
+            &mut fn_builder_ctx,
+            dump,
+        );
+    }
+
     // Generate main
     make_main(&mut module, &mut fn_builder_ctx, main_fun_id, dump);
 
@@ -88,6 +107,9 @@ enum VarVal {
     // Variable is a reference to a data object (i.e. a closure). Get a reference to it using
     // `declare_data_in_func` and a value of it using `global_value`.
     Data(DataId),
+    // Variable is a global, stored in a data object. Unlike `Data` we load the variable from the
+    // data object, and assignments to the variable are stores to the data object.
+    Global(DataId, Type),
 }
 
 impl Env {
@@ -107,6 +129,17 @@ impl Env {
         self.0.insert(var, VarVal::Data(val));
     }
 
+    fn add_global(&mut self, var: VarId, val: DataId, ty: Type) {
+        self.0.insert(var, VarVal::Global(val, ty));
+    }
+
+    fn get_global(&self, var: VarId) -> Option<DataId> {
+        match self.0.get(&var) {
+            Some(VarVal::Global(data_id, _)) => Some(*data_id),
+            _ => None,
+        }
+    }
+
     fn get_fun(&self, var: VarId) -> Option<FuncId> {
         match self.0.get(&var) {
             Some(VarVal::Fun(fun_id)) => Some(*fun_id),
@@ -144,6 +177,11 @@ impl Env {
                 // self.0.insert(var, VarVal::Known(val));
                 val
             }
+            Some(VarVal::Global(data_id, ty)) => {
+                let data_ref = module.declare_data_in_func(data_id, builder.func);
+                let addr = builder.ins().global_value(I64, data_ref);
+                builder.ins().load(ty, MemFlags::new(), addr, 0)
+            }
             None => {
                 // Should be a variable declared and defined before.
                 let var = Variable::new(ctx.get_var(var).get_uniq().0.get() as usize);
@@ -153,8 +191,25 @@ impl Env {
     }
 }
 
-fn declare_malloc(module: &mut Module<ObjectBackend>) -> FuncId {
-    module
+// Functions from libc and rts.c called by the generated code. `F` is `FuncId` at module level,
+// `FuncRef` in a function.
+#[derive(Clone, Copy)]
+struct RtsFuns<F> {
+    malloc: F,
+    // Reports division by zero and exits. Does not return.
+    div_by_zero: F,
+    // Reports a `match` failure and exits. Does not return.
+    match_failure: F,
+    // Raises the exception. Does not return.
+    raise: F,
+    // Compares two strings, returns -1, 0, or 1
+    compare_strings: F,
+    // Raises `Invalid_argument`, called when comparing different closures. Does not return.
+    compare_functional_value: F,
+}
+
+fn declare_rts_funs(module: &mut Module<ObjectBackend>) -> RtsFuns<FuncId> {
+    let malloc = module
         .declare_function(
             "malloc",
             Linkage::Import,
@@ -164,7 +219,92 @@ fn declare_malloc(module: &mut Module<ObjectBackend>) -> FuncId {
                 call_conv: CallConv::SystemV,
             },
         )
-        .unwrap()
+        .unwrap();
+
+    let div_by_zero = module
+        .declare_function(
+            "mc_division_by_zero",
+            Linkage::Import,
+            &Signature {
+                params: vec![],
+                returns: vec![],
+                call_conv: CallConv::SystemV,
+            },
+        )
+        .unwrap();
+
+    let match_failure = module
+        .declare_function(
+            "mc_match_failure",
+            Linkage::Import,
+            &Signature {
+                params: vec![AbiParam::new(I64), AbiParam::new(I64), AbiParam::new(I64)],
+                returns: vec![],
+                call_conv: CallConv::SystemV,
+            },
+        )
+        .unwrap();
+
+    let raise = module
+        .declare_function(
+            "mc_raise",
+            Linkage::Import,
+            &Signature {
+                params: vec![AbiParam::new(I64)],
+                returns: vec![],
+                call_conv: CallConv::SystemV,
+            },
+        )
+        .unwrap();
+
+    let compare_strings = module
+        .declare_function(
+            "mc_compare_strings",
+            Linkage::Import,
+            &Signature {
+                params: vec![AbiParam::new(I64), AbiParam::new(I64)],
+                returns: vec![AbiParam::new(I64)],
+                call_conv: CallConv::SystemV,
+            },
+        )
+        .unwrap();
+
+    let compare_functional_value = module
+        .declare_function(
+            "mc_compare_functional_value",
+            Linkage::Import,
+            &Signature {
+                params: vec![],
+                returns: vec![],
+                call_conv: CallConv::SystemV,
+            },
+        )
+        .unwrap();
+
+    RtsFuns {
+        malloc,
+        div_by_zero,
+        match_failure,
+        raise,
+        compare_strings,
+        compare_functional_value,
+    }
+}
+
+impl RtsFuns<FuncId> {
+    fn declare_in_func(
+        &self, module: &mut Module<ObjectBackend>, func: &mut Function,
+    ) -> RtsFuns<FuncRef> {
+        RtsFuns {
+            malloc: module.declare_func_in_func(self.malloc, func),
+            div_by_zero: module.declare_func_in_func(self.div_by_zero, func),
+            match_failure: module.declare_func_in_func(self.match_failure, func),
+            raise: module.declare_func_in_func(self.raise, func),
+            compare_strings: module.declare_func_in_func(self.compare_strings, func),
+            compare_functional_value: module
+                .declare_func_in_func(self.compare_functional_value, func),
+        }
+    }
 }
 
 fn init_module_env(
@@ -179,11 +319,45 @@ fn init_module_env(
         let name = var.symbol_name();
 
         let id: DataId = module
-            .declare_data(&*name, Linkage::Import, false, false, None)
+            .declare_data(&name, Linkage::Import, false, false, None)
             .unwrap();
         env.add_data(*builtin_var_id, id);
     }
 
+    // Define string literals: length in the first word, then the bytes, NUL-terminated for the
+    // RTS
+    let mut str_lits: Vec<(VarId, &str)> =
+        ctx.str_lits().map(|(var, s)| (*var, &**s)).collect();
+    str_lits.sort();
+    for (var, s) in str_lits {
+        let name = ctx.get_var(var).to_string();
+        let id: DataId = module
+            .declare_data(&name, Linkage::Local, false, false, Some(WORD_SIZE))
+            .unwrap();
+        let mut bytes: Vec<u8> = Vec::with_capacity(usize::from(WORD_SIZE) + s.len() + 1);
+        bytes.extend_from_slice(&(s.len() as i64).to_le_bytes());
+        bytes.extend_from_slice(s.as_bytes());
+        bytes.push(0);
+        let mut data_ctx = DataContext::new();
+        data_ctx.define(bytes.into_boxed_slice());
+        module.define_data(id, &data_ctx).unwrap();
+        env.add_data(var, id);
+    }
+
+    // Declare and define globals. Globals are initialized by main.
+    let mut globals: Vec<VarId> = ctx.globals().copied().collect();
+    globals.sort();
+    for global in globals {
+        let name = ctx.get_var(global).to_string();
+        let id: DataId = module
+            .declare_data(&name, Linkage::Local, true, false, None)
+            .unwrap();
+        let mut data_ctx = DataContext::new();
+        data_ctx.define_zeroinit(usize::from(WORD_SIZE));
+        module.define_data(id, &data_ctx).unwrap();
+        env.add_global(global, id, rep_type_abi(ctx.var_rep_type(global)));
+    }
+
     // Declare functions
     for lower::Fun {
         name,
@@ -206,7 +380,7 @@ fn init_module_env(
         };
 
         let id: FuncId = module
-            .declare_function(&*ctx.get_var(*name).name(), Linkage::Local, &sig)
+            .declare_function(&ctx.get_var(*name).name(), Linkage::Local, &sig)
             .unwrap();
 
         if *name == main_id {
@@ -223,9 +397,11 @@ fn init_module_env(
     (env, main_fun_id)
 }
 
+#[allow(clippy::too_many_arguments)]
 fn codegen_fun(
-    ctx: &mut Ctx, module: &mut Module<ObjectBackend>, global_env: &Env, malloc_id: FuncId,
-    fun: &lower::Fun, fn_builder_ctx: &mut FunctionBuilderContext, dump: bool,
+    ctx: &mut Ctx, module: &mut Module<ObjectBackend>, global_env: &Env, rts_ids: RtsFuns<FuncId>,
+    cmp_funs: &mut CmpFuns, fun: &lower::Fun, fn_builder_ctx: &mut FunctionBuilderContext,
+    dump: bool,
 ) {
     let lower::Fun {
         name,
@@ -253,8 +429,8 @@ fn codegen_fun(
         .get_fun(*name)
         .expect("Can't find FuncId of function");
 
-    // TODO: Only do this for functions that allocate
-    let malloc: FuncRef = module.declare_func_in_func(malloc_id, &mut context.func);
+    // TODO: Only declare the functions used
+    let rts: RtsFuns<FuncRef> = rts_ids.declare_in_func(module, &mut context.func);
 
     let mut builder: FunctionBuilder = FunctionBuilder::new(&mut context.func, fn_builder_ctx);
 
@@ -285,8 +461,8 @@ fn codegen_fun(
             // println!("stmt: {}", s);
 
             match stmt {
-                lower::Stmt::Asgn(lower::Asgn { lhs, rhs }) => {
-                    if !declared.contains(lhs) {
+                lower::Stmt::Asgn(lower::Asgn { lhs, rhs: _ }) => {
+                    if !declared.contains(lhs) && env.get_global(*lhs).is_none() {
                         declared.insert(*lhs);
                         let lhs_cl_var =
                             Variable::new(ctx.get_var(*lhs).get_uniq().0.get() as usize);
@@ -307,7 +483,7 @@ fn codegen_fun(
             exit,
         } = block;
 
-        let mut cl_block = *label_to_block.get(&idx).unwrap();
+        let mut cl_block = *label_to_block.get(idx).unwrap();
         builder.switch_to_block(cl_block);
 
         for stmt in stmts {
@@ -317,16 +493,42 @@ fn codegen_fun(
 
             match stmt {
                 lower::Stmt::Asgn(lower::Asgn { lhs, rhs }) => {
-                    let (block, val) =
-                        codegen_expr(ctx, &module, cl_block, &mut builder, &mut env, malloc, rhs);
+                    let (block, val) = codegen_expr(
+                        ctx,
+                        module,
+                        cl_block,
+                        &mut builder,
+                        &mut env,
+                        rts,
+                        cmp_funs,
+                        rhs,
+                    );
                     cl_block = block;
 
-                    let lhs_cl_var = Variable::new(ctx.get_var(*lhs).get_uniq().0.get() as usize);
-                    builder.def_var(lhs_cl_var, val.unwrap());
+                    match env.get_global(*lhs) {
+                        Some(data_id) => {
+                            let data_ref = module.declare_data_in_func(data_id, builder.func);
+                            let addr = builder.ins().global_value(I64, data_ref);
+                            builder.ins().store(MemFlags::new(), val.unwrap(), addr, 0);
+                        }
+                        None => {
+                            let lhs_cl_var =
+                                Variable::new(ctx.get_var(*lhs).get_uniq().0.get() as usize);
+                            builder.def_var(lhs_cl_var, val.unwrap());
+                        }
+                    }
                 }
                 lower::Stmt::Expr(expr) => {
-                    let (block, _) =
-                        codegen_expr(ctx, &module, cl_block, &mut builder, &mut env, malloc, expr);
+                    let (block, _) = codegen_expr(
+                        ctx,
+                        module,
+                        cl_block,
+                        &mut builder,
+                        &mut env,
+                        rts,
+                        cmp_funs,
+                        expr,
+                    );
                     cl_block = block;
                 }
             }
@@ -334,7 +536,7 @@ fn codegen_fun(
 
         match exit {
             lower::Exit::Return(var) => {
-                let var = env.use_var(ctx, &module, &mut builder, *var);
+                let var = env.use_var(ctx, module, &mut builder, *var);
                 builder.ins().return_(&[var]);
             }
             lower::Exit::Branch {
@@ -345,8 +547,8 @@ fn codegen_fun(
                 else_block,
             } => {
                 let comp_type = ctx.var_rep_type(*v1);
-                let v1 = env.use_var(ctx, &module, &mut builder, *v1);
-                let v2 = env.use_var(ctx, &module, &mut builder, *v2);
+                let v1 = env.use_var(ctx, module, &mut builder, *v1);
+                let v2 = env.use_var(ctx, module, &mut builder, *v2);
 
                 let then_block = *label_to_block.get(then_block).unwrap();
                 let else_block = *label_to_block.get(else_block).unwrap();
@@ -374,6 +576,18 @@ fn codegen_fun(
                 // Not sure about the arguments here...
                 builder.ins().jump(cl_block, &[]);
             }
+            lower::Exit::MatchFailure { file, line, col } => {
+                let file = env.use_var(ctx, module, &mut builder, *file);
+                let line = builder.ins().iconst(I64, *line);
+                let col = builder.ins().iconst(I64, *col);
+                builder.ins().call(rts.match_failure, &[file, line, col]);
+                builder.ins().trap(TrapCode::UnreachableCodeReached);
+            }
+            lower::Exit::Raise(exn) => {
+                let exn = env.use_var(ctx, module, &mut builder, *exn);
+                builder.ins().call(rts.raise, &[exn]);
+                builder.ins().trap(TrapCode::UnreachableCodeReached);
+            }
         }
     }
 
@@ -410,9 +624,10 @@ fn codegen_fun(
     module.clear_context(&mut context);
 }
 
+#[allow(clippy::too_many_arguments)]
 fn codegen_expr(
-    ctx: &mut Ctx, module: &Module<ObjectBackend>, block: Block, builder: &mut FunctionBuilder,
-    env: &mut Env, malloc: FuncRef, rhs: &lower::Expr,
+    ctx: &mut Ctx, module: &mut Module<ObjectBackend>, block: Block, builder: &mut FunctionBuilder,
+    env: &mut Env, rts: RtsFuns<FuncRef>, cmp_funs: &mut CmpFuns, rhs: &lower::Expr,
 ) -> (Block, Option<Value>) {
     match rhs {
         lower::Expr::Atom(lower::Atom::Unit) => (block, Some(builder.ins().iconst(I64, 0))),
@@ -425,13 +640,47 @@ fn codegen_expr(
         lower::Expr::IBinOp(BinOp { op, arg1, arg2 }) => {
             let arg1 = env.use_var(ctx, module, builder, *arg1);
             let arg2 = env.use_var(ctx, module, builder, *arg2);
-            let val = match op {
-                IntBinOp::Add => builder.ins().iadd(arg1, arg2),
-                IntBinOp::Sub => builder.ins().isub(arg1, arg2),
-                // IntBinOp::Mul => builder.ins().imul(arg1, arg2),
-                // IntBinOp::Div => builder.ins().sdiv(arg1, arg2),
-            };
-            (block, Some(val))
+            match op {
+                IntBinOp::Add => (block, Some(builder.ins().iadd(arg1, arg2))),
+                IntBinOp::Sub => (block, Some(builder.ins().isub(arg1, arg2))),
+                IntBinOp::Mul => (block, Some(builder.ins().imul(arg1, arg2))),
+                IntBinOp::And => (block, Some(builder.ins().band(arg1, arg2))),
+                IntBinOp::Or => (block, Some(builder.ins().bor(arg1, arg2))),
+                IntBinOp::Xor => (block, Some(builder.ins().bxor(arg1, arg2))),
+                IntBinOp::Lsl => (block, Some(builder.ins().ishl(arg1, arg2))),
+                IntBinOp::Lsr => (block, Some(builder.ins().ushr(arg1, arg2))),
+                IntBinOp::Asr => (block, Some(builder.ins().sshr(arg1, arg2))),
+                IntBinOp::Div | IntBinOp::Mod => {
+                    // Check for zero before dividing, `sdiv` and `srem` trap with SIGFPE
+                    let div_by_zero_block = builder.create_block();
+                    let cont_block = builder.create_block();
+                    builder.ins().brz(arg2, div_by_zero_block, &[]);
+                    builder.ins().jump(cont_block, &[]);
+
+                    builder.switch_to_block(div_by_zero_block);
+                    builder.seal_block(div_by_zero_block);
+                    builder.ins().call(rts.div_by_zero, &[]);
+                    builder.ins().trap(TrapCode::IntegerDivisionByZero);
+
+                    builder.switch_to_block(cont_block);
+                    builder.seal_block(cont_block);
+
+                    // `min_int / -1` also traps. Divide by 1 instead when the divisor is -1 and
+                    // negate the result. `x mod -1` is always 0, which is also `x mod 1`.
+                    let minus_one = builder.ins().icmp_imm(IntCC::Equal, arg2, -1);
+                    let one = builder.ins().iconst(I64, 1);
+                    let divisor = builder.ins().select(minus_one, one, arg2);
+                    let val = match op {
+                        IntBinOp::Div => {
+                            let quot = builder.ins().sdiv(arg1, divisor);
+                            let neg = builder.ins().ineg(arg1);
+                            builder.ins().select(minus_one, neg, quot)
+                        }
+                        _ => builder.ins().srem(arg1, divisor),
+                    };
+                    (cont_block, Some(val))
+                }
+            }
         }
 
         lower::Expr::FBinOp(BinOp { op, arg1, arg2 }) => {
@@ -456,6 +705,15 @@ fn codegen_expr(
             (block, Some(builder.ins().fneg(arg)))
         }
 
+        lower::Expr::StructCmp(kind, arg1, arg2) => {
+            let fun_id = cmp_funs.get(module, *kind, &ctx.var_type(*arg1));
+            let fun_ref = module.declare_func_in_func(fun_id, builder.func);
+            let arg1 = env.use_var(ctx, module, builder, *arg1);
+            let arg2 = env.use_var(ctx, module, builder, *arg2);
+            let call = builder.ins().call(fun_ref, &[arg1, arg2]);
+            (block, Some(builder.inst_results(call)[0]))
+        }
+
         lower::Expr::App(fun, args, ret_type) => {
             let params: Vec<AbiParam> = args
                 .iter()
@@ -492,7 +750,7 @@ fn codegen_expr(
             let malloc_arg = builder
                 .ins()
                 .iconst(I64, *len as i64 * i64::from(WORD_SIZE));
-            let malloc_call = builder.ins().call(malloc, &[malloc_arg]);
+            let malloc_call = builder.ins().call(rts.malloc, &[malloc_arg]);
             let tuple = builder.inst_results(malloc_call)[0];
             (block, Some(tuple))
         }
@@ -513,6 +771,13 @@ fn codegen_expr(
             let tuple_type = ctx.var_type(*tuple);
             let elem_type = match &*tuple_type {
                 type_check::Type::Tuple(args) => rep_type_abi(RepType::from(&args[*idx])),
+                // Records
+                type_check::Type::Con(ty_con) => {
+                    let field = ctx.record_fields(ty_con)[*idx];
+                    rep_type_abi(RepType::from(&ctx.field_info(field).ty))
+                }
+                // References
+                type_check::Type::Ref(ty) => rep_type_abi(RepType::from(&**ty)),
                 type_check::Type::Fun { .. } => {
                     // NOTE DISGUSTING HACK: This case happens after closure conversion where we
                     // turn functions into tuples (closures) and in application code when we see
@@ -540,12 +805,16 @@ fn codegen_expr(
             (block, Some(val))
         }
 
+        // Arrays have the length in the first word, then the elements
         lower::Expr::ArrayAlloc { len } => {
             let len_val = env.use_var(ctx, module, builder, *len);
             let word_size = builder.ins().iconst(I64, i64::from(WORD_SIZE));
-            let size_val = builder.ins().imul(len_val, word_size);
-            let malloc_call = builder.ins().call(malloc, &[size_val]);
-            (block, Some(builder.inst_results(malloc_call)[0]))
+            let n_words = builder.ins().iadd_imm(len_val, 1);
+            let size_val = builder.ins().imul(n_words, word_size);
+            let malloc_call = builder.ins().call(rts.malloc, &[size_val]);
+            let array = builder.inst_results(malloc_call)[0];
+            builder.ins().store(MemFlags::new(), len_val, array, 0);
+            (block, Some(array))
         }
 
         lower::Expr::ArrayGet(array, idx) => {
@@ -564,7 +833,12 @@ fn codegen_expr(
                 Some(
                     builder
                         .ins()
-                        .load_complex(elem_type, MemFlags::new(), &[array, offset], 0),
+                        .load_complex(
+                            elem_type,
+                            MemFlags::new(),
+                            &[array, offset],
+                            i32::from(WORD_SIZE),
+                        ),
                 ),
             )
         }
@@ -575,15 +849,465 @@ fn codegen_expr(
             let val = env.use_var(ctx, module, builder, *val);
             let word_size = builder.ins().iconst(I64, 8);
             let offset = builder.ins().imul(idx, word_size);
-            builder
-                .ins()
-                .store_complex(MemFlags::new(), val, &[array, offset], 0);
+            builder.ins().store_complex(
+                MemFlags::new(),
+                val,
+                &[array, offset],
+                i32::from(WORD_SIZE),
+            );
             let ret = builder.ins().iconst(I64, 0);
             (block, Some(ret))
         }
     }
 }
 
+// Comparison functions for `lower::Expr::StructCmp`, one for each kind of comparison and type.
+// Functions are declared when first used, and defined after the functions of the program.
+struct CmpFuns {
+    ids: FxHashMap<(StructCmp, type_check::Type), FuncId>,
+    // Declared but not defined yet
+    undefined: Vec<(StructCmp, type_check::Type, FuncId)>,
+}
+
+impl CmpFuns {
+    fn new() -> Self {
+        CmpFuns {
+            ids: Default::default(),
+            undefined: vec![],
+        }
+    }
+
+    fn get(
+        &mut self, module: &mut Module<ObjectBackend>, kind: StructCmp, ty: &type_check::Type,
+    ) -> FuncId {
+        if let Some(id) = self.ids.get(&(kind, ty.clone())) {
+            return *id;
+        }
+        let name = match kind {
+            StructCmp::Equal => format!("%equal{}", self.ids.len()),
+            StructCmp::Compare => format!("%compare{}", self.ids.len()),
+        };
+        let id = module
+            .declare_function(&name, Linkage::Local, &cmp_fun_sig(ty))
+            .unwrap();
+        self.ids.insert((kind, ty.clone()), id);
+        self.undefined.push((kind, ty.clone(), id));
+        id
+    }
+}
+
+// Comparison functions take the compared values and return an int
+fn cmp_fun_sig(ty: &type_check::Type) -> Signature {
+    let param = AbiParam::new(rep_type_abi(RepType::from(ty)));
+    Signature {
+        params: vec![param, param],
+        returns: vec![AbiParam::new(I64)],
+        call_conv: CallConv::SystemV,
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
+fn codegen_cmp_fun(
+    ctx: &Ctx, module: &mut Module<ObjectBackend>, global_env: &Env, rts_ids: RtsFuns<FuncId>,
+    cmp_funs: &mut CmpFuns, kind: StructCmp, ty: &type_check::Type, fun_id: FuncId,
+    fn_builder_ctx: &mut FunctionBuilderContext, dump: bool,
+) {
+    let mut context = module.make_context();
+    context.func.signature = cmp_fun_sig(ty);
+    let rts: RtsFuns<FuncRef> = rts_ids.declare_in_func(module, &mut context.func);
+    let mut builder: FunctionBuilder = FunctionBuilder::new(&mut context.func, fn_builder_ctx);
+
+    let entry_block = builder.create_block();
+    builder.append_block_params_for_function_params(entry_block);
+    let ret_block = builder.create_block();
+    let ret_val = builder.append_block_param(ret_block, I64);
+
+    builder.switch_to_block(entry_block);
+    let v1 = builder.block_params(entry_block)[0];
+    let v2 = builder.block_params(entry_block)[1];
+
+    let mut gen = CmpGen {
+        ctx,
+        module,
+        env: global_env.clone(),
+        rts,
+        cmp_funs,
+        builder,
+        kind,
+        ret_block,
+    };
+    gen.cmp_fun_body(ty, v1, v2);
+
+    let mut builder = gen.builder;
+    builder.switch_to_block(ret_block);
+    builder.ins().return_(&[ret_val]);
+    builder.seal_all_blocks();
+    builder.finalize();
+
+    let flags = settings::Flags::new(settings::builder());
+    let res = verify_function(&context.func, &flags);
+
+    if dump {
+        println!("{}", context.func.display(None));
+    }
+    if let Err(errors) = res {
+        println!("{}", errors);
+    }
+
+    module
+        .define_function(fun_id, &mut context, &mut NullTrapSink {})
+        .unwrap();
+    module.clear_context(&mut context);
+}
+
+// Generates the body of a comparison function. Code comparing parts of the values jumps to
+// `ret_block` with the result when the parts are different, and continues with the next part
+// otherwise.
+struct CmpGen<'a> {
+    ctx: &'a Ctx,
+    module: &'a mut Module<ObjectBackend>,
+    env: Env,
+    rts: RtsFuns<FuncRef>,
+    cmp_funs: &'a mut CmpFuns,
+    builder: FunctionBuilder<'a>,
+    kind: StructCmp,
+    ret_block: Block,
+}
+
+impl<'a> CmpGen<'a> {
+    // Compare values of type `ty`. Types without a comparison function (int, float etc.) are
+    // compared with `cmp_vals`: these have a function only for `compare`.
+    fn cmp_fun_body(&mut self, ty: &type_check::Type, v1: Value, v2: Value) {
+        use type_check::Type;
+        match ty {
+            Type::Unit | Type::Bool | Type::Int | Type::Char | Type::Float => {
+                self.cmp_vals(ty, v1, v2);
+            }
+            Type::Tuple(tys) => {
+                for (idx, ty) in tys.iter().enumerate() {
+                    self.cmp_fields(ty, v1, v2, idx);
+                }
+            }
+            Type::Ref(ty) => {
+                self.cmp_fields(ty, v1, v2, 0);
+            }
+            Type::String => {
+                let call = self.builder.ins().call(self.rts.compare_strings, &[v1, v2]);
+                let res = self.builder.inst_results(call)[0];
+                self.exit_if_cmp_nonzero(res);
+            }
+            // Arrays are compared by length first, then by elements
+            Type::Array(elem_ty) => {
+                let len1 = self.builder.ins().load(I64, MemFlags::new(), v1, 0);
+                let len2 = self.builder.ins().load(I64, MemFlags::new(), v2, 0);
+                self.cmp_vals(&Type::Int, len1, len2);
+
+                let loop_block = self.builder.create_block();
+                let idx = self.builder.append_block_param(loop_block, I64);
+                let body_block = self.builder.create_block();
+                let done_block = self.builder.create_block();
+                let zero = self.builder.ins().iconst(I64, 0);
+                self.builder.ins().jump(loop_block, &[zero]);
+
+                self.builder.switch_to_block(loop_block);
+                self.builder
+                    .ins()
+                    .br_icmp(IntCC::Equal, idx, len1, done_block, &[]);
+                self.builder.ins().jump(body_block, &[]);
+
+                self.builder.switch_to_block(body_block);
+                let elem_type = rep_type_abi(RepType::from(&**elem_ty));
+                let word_size = self.builder.ins().iconst(I64, i64::from(WORD_SIZE));
+                let offset = self.builder.ins().imul(idx, word_size);
+                let elem1 = self.builder.ins().load_complex(
+                    elem_type,
+                    MemFlags::new(),
+                    &[v1, offset],
+                    i32::from(WORD_SIZE),
+                );
+                let elem2 = self.builder.ins().load_complex(
+                    elem_type,
+                    MemFlags::new(),
+                    &[v2, offset],
+                    i32::from(WORD_SIZE),
+                );
+                self.cmp_vals(elem_ty, elem1, elem2);
+                let next_idx = self.builder.ins().iadd_imm(idx, 1);
+                self.builder.ins().jump(loop_block, &[next_idx]);
+
+                self.builder.switch_to_block(done_block);
+            }
+            // Lists are compared element by element in a loop. `[]` is smaller than other lists.
+            Type::List(elem_ty) => {
+                let loop_block = self.builder.create_block();
+                let l1 = self.builder.append_block_param(loop_block, I64);
+                let l2 = self.builder.append_block_param(loop_block, I64);
+                let nil_block = self.builder.create_block();
+                let cons_block = self.builder.create_block();
+                self.builder.ins().jump(loop_block, &[v1, v2]);
+
+                self.builder.switch_to_block(loop_block);
+                for l in &[l1, l2] {
+                    let is_nil = self.builder.ins().icmp_imm(IntCC::Equal, *l, 0);
+                    self.branch_if(is_nil, nil_block);
+                }
+                self.builder.ins().jump(cons_block, &[]);
+
+                // One of the lists is empty, compare the words
+                self.builder.switch_to_block(nil_block);
+                let res = self.word_cmp_result(l1, l2);
+                self.builder.ins().jump(self.ret_block, &[res]);
+
+                self.builder.switch_to_block(cons_block);
+                self.cmp_fields(elem_ty, l1, l2, 0);
+                let tl1 = self.load_field(I64, l1, 1);
+                let tl2 = self.load_field(I64, l2, 1);
+                self.builder.ins().jump(loop_block, &[tl1, tl2]);
+                // Loop exits to `ret_block`
+                return;
+            }
+            Type::Con(ty_con) if !self.ctx.record_fields(ty_con).is_empty() => {
+                for field in self.ctx.record_fields(ty_con) {
+                    let info = self.ctx.field_info(*field);
+                    self.cmp_fields(&info.ty, v1, v2, info.idx);
+                }
+            }
+            Type::Con(ty_con) => self.cmp_cons(ty_con, v1, v2),
+            Type::Fun { .. } => self.cmp_closures(v1, v2),
+            other => panic!("Comparison function for type {:?}", other),
+        }
+        self.ret_equal();
+    }
+
+    // Compare values of a variant type or `exn`. Values of constant constructors are smaller than
+    // values of other constructors. Constant constructors are compared by tag, other constructors
+    // by field 0 (the tag, or the address of the exception name), then the arguments.
+    fn cmp_cons(&mut self, ty_con: &type_check::TyCon, v1: Value, v2: Value) {
+        let (consts, blocks): (Vec<VarId>, Vec<VarId>) =
+            self.ctx.ty_con_cons(ty_con).iter().partition(|con| {
+                let info = self.ctx.con_info(**con);
+                info.args.is_empty() && info.exn_name.is_none()
+            });
+
+        if blocks.is_empty() {
+            self.cmp_vals(&type_check::Type::Int, v1, v2);
+            return;
+        }
+
+        if !consts.is_empty() {
+            // Constant constructor values are smaller than pointers, so comparing the words gives
+            // the right result when one of the values is a constant constructor
+            let n_consts = consts.len() as i64;
+            let words_block = self.builder.create_block();
+            let blocks_block = self.builder.create_block();
+            for v in &[v1, v2] {
+                let is_const = self
+                    .builder
+                    .ins()
+                    .icmp_imm(IntCC::SignedLessThan, *v, n_consts);
+                self.branch_if(is_const, words_block);
+            }
+            self.builder.ins().jump(blocks_block, &[]);
+
+            self.builder.switch_to_block(words_block);
+            let res = self.word_cmp_result(v1, v2);
+            self.builder.ins().jump(self.ret_block, &[res]);
+
+            self.builder.switch_to_block(blocks_block);
+        }
+
+        let tag1 = self.load_field(I64, v1, 0);
+        let tag2 = self.load_field(I64, v2, 0);
+        self.cmp_vals(&type_check::Type::Int, tag1, tag2);
+
+        for (con_idx, con) in blocks.iter().enumerate() {
+            let info = self.ctx.con_info(*con).clone();
+            let next_block = if con_idx + 1 == blocks.len() {
+                // Tag is known
+                None
+            } else {
+                let tag = match info.exn_name {
+                    None => self.builder.ins().iconst(I64, info.tag),
+                    Some(exn_name) => {
+                        self.env
+                            .use_var(self.ctx, self.module, &mut self.builder, exn_name)
+                    }
+                };
+                let con_block = self.builder.create_block();
+                let next_block = self.builder.create_block();
+                self.builder
+                    .ins()
+                    .br_icmp(IntCC::Equal, tag1, tag, con_block, &[]);
+                self.builder.ins().jump(next_block, &[]);
+                self.builder.switch_to_block(con_block);
+                Some(next_block)
+            };
+
+            for (arg_idx, arg_ty) in info.args.iter().enumerate() {
+                self.cmp_fields(arg_ty, v1, v2, arg_idx + 1);
+            }
+            self.ret_equal();
+
+            if let Some(next_block) = next_block {
+                self.builder.switch_to_block(next_block);
+            }
+        }
+
+        // All constructors return above, continue in an unreachable block
+        let cont_block = self.builder.create_block();
+        self.builder.switch_to_block(cont_block);
+    }
+
+    fn cmp_fields(&mut self, ty: &type_check::Type, v1: Value, v2: Value, idx: usize) {
+        let field_type = rep_type_abi(RepType::from(ty));
+        let field1 = self.load_field(field_type, v1, idx);
+        let field2 = self.load_field(field_type, v2, idx);
+        self.cmp_vals(ty, field1, field2);
+    }
+
+    fn load_field(&mut self, ty: Type, tuple: Value, idx: usize) -> Value {
+        self.builder.ins().load(
+            ty,
+            MemFlags::new(),
+            tuple,
+            (idx * usize::from(WORD_SIZE)) as i32,
+        )
+    }
+
+    // Compare values of type `ty`: words and floats directly, others with their comparison
+    // functions
+    fn cmp_vals(&mut self, ty: &type_check::Type, v1: Value, v2: Value) {
+        use type_check::Type;
+        match ty {
+            Type::Unit | Type::Bool | Type::Int | Type::Char | Type::Var(_) => {
+                match self.kind {
+                    StructCmp::Equal => {
+                        let ne = self.builder.ins().icmp(IntCC::NotEqual, v1, v2);
+                        self.exit_if(ne, 0);
+                    }
+                    StructCmp::Compare => {
+                        let res = self.word_cmp_result(v1, v2);
+                        self.exit_if_cmp_nonzero(res);
+                    }
+                }
+            }
+            Type::Float => match self.kind {
+                StructCmp::Equal => {
+                    // NaN is not equal to itself
+                    let ne = self.builder.ins().fcmp(FloatCC::NotEqual, v1, v2);
+                    self.exit_if(ne, 0);
+                }
+                StructCmp::Compare => {
+                    // NaN is equal to itself and smaller than other floats, as in OCaml's
+                    // `compare`
+                    let gt = self.builder.ins().fcmp(FloatCC::GreaterThan, v1, v2);
+                    let lt = self.builder.ins().fcmp(FloatCC::LessThan, v1, v2);
+                    let ord1 = self.builder.ins().fcmp(FloatCC::Ordered, v1, v1);
+                    let ord2 = self.builder.ins().fcmp(FloatCC::Ordered, v2, v2);
+                    let mut res = self.bool_diff(gt, lt);
+                    let ord_diff = self.bool_diff(ord1, ord2);
+                    res = self.builder.ins().iadd(res, ord_diff);
+                    self.exit_if_cmp_nonzero(res);
+                }
+            },
+            Type::Fun { .. } => self.cmp_closures(v1, v2),
+            _ => {
+                let fun_id = self.cmp_funs.get(self.module, self.kind, ty);
+                let fun_ref = self.module.declare_func_in_func(fun_id, self.builder.func);
+                let call = self.builder.ins().call(fun_ref, &[v1, v2]);
+                let res = self.builder.inst_results(call)[0];
+                match self.kind {
+                    StructCmp::Equal => {
+                        let ne = self.builder.ins().icmp_imm(IntCC::Equal, res, 0);
+                        self.exit_if(ne, 0);
+                    }
+                    StructCmp::Compare => self.exit_if_cmp_nonzero(res),
+                }
+            }
+        }
+    }
+
+    // Closures are equal when they're the same closure, comparing different closures raises
+    // `Invalid_argument`
+    fn cmp_closures(&mut self, v1: Value, v2: Value) {
+        let ne_block = self.builder.create_block();
+        let cont_block = self.builder.create_block();
+        let ne = self.builder.ins().icmp(IntCC::NotEqual, v1, v2);
+        self.builder.ins().brnz(ne, ne_block, &[]);
+        self.builder.ins().jump(cont_block, &[]);
+
+        self.builder.switch_to_block(ne_block);
+        self.builder
+            .ins()
+            .call(self.rts.compare_functional_value, &[]);
+        self.builder.ins().jump(cont_block, &[]);
+
+        self.builder.switch_to_block(cont_block);
+    }
+
+    // Result of the comparison function for two words
+    fn word_cmp_result(&mut self, v1: Value, v2: Value) -> Value {
+        match self.kind {
+            StructCmp::Equal => {
+                let eq = self.builder.ins().icmp(IntCC::Equal, v1, v2);
+                self.builder.ins().bint(I64, eq)
+            }
+            StructCmp::Compare => {
+                let gt = self.builder.ins().icmp(IntCC::SignedGreaterThan, v1, v2);
+                let lt = self.builder.ins().icmp(IntCC::SignedLessThan, v1, v2);
+                self.bool_diff(gt, lt)
+            }
+        }
+    }
+
+    // `b1 - b2` as an integer
+    fn bool_diff(&mut self, b1: Value, b2: Value) -> Value {
+        let i1 = self.builder.ins().bint(I64, b1);
+        let i2 = self.builder.ins().bint(I64, b2);
+        self.builder.ins().isub(i1, i2)
+    }
+
+    // Return `ret` if `cond` holds
+    fn exit_if(&mut self, cond: Value, ret: i64) {
+        let ret = self.builder.ins().iconst(I64, ret);
+        self.exit_if_(cond, ret);
+    }
+
+    // Return `res` if it's not 0 (the compared values are not equal)
+    fn exit_if_cmp_nonzero(&mut self, res: Value) {
+        match self.kind {
+            StructCmp::Equal => self.exit_if(res, 0),
+            StructCmp::Compare => self.exit_if_(res, res),
+        }
+    }
+
+    fn exit_if_(&mut self, cond: Value, ret: Value) {
+        let cont_block = self.builder.create_block();
+        self.builder.ins().brnz(cond, self.ret_block, &[ret]);
+        self.builder.ins().jump(cont_block, &[]);
+        self.builder.switch_to_block(cont_block);
+    }
+
+    // Jump to `block` if `cond` holds, continue in a new block otherwise. Cranelift doesn't allow
+    // instructions other than a `jump` after a branch.
+    fn branch_if(&mut self, cond: Value, block: Block) {
+        let cont_block = self.builder.create_block();
+        self.builder.ins().brnz(cond, block, &[]);
+        self.builder.ins().jump(cont_block, &[]);
+        self.builder.switch_to_block(cont_block);
+    }
+
+    // Return the result for equal values
+    fn ret_equal(&mut self) {
+        let equal = match self.kind {
+            StructCmp::Equal => 1,
+            StructCmp::Compare => 0,
+        };
+        let equal = self.builder.ins().iconst(I64, equal);
+        self.builder.ins().jump(self.ret_block, &[equal]);
+    }
+}
+
 fn make_main(
     module: &mut Module<ObjectBackend>, fun_ctx: &mut FunctionBuilderContext, main_id: FuncId,
     dump: bool,
@@ -10,6 +10,24 @@ pub enum Cmp {
     GreaterThanOrEqual,
 }
 
+// Structural comparison of values of the same type. `codegen` generates a comparison function for
+// each kind and type compared.
+#[derive(Debug, PartialEq, Eq, Hash, Clone, Copy)]
+pub enum StructCmp {
+    // 1 if the values are equal, 0 otherwise. Used for `=` and `<>`.
+    Equal,
+    // -1, 0, or 1 if the first value is smaller, equal, or larger. Used for the other comparisons
+    // and `compare`.
+    Compare,
+}
+
+// Direction of a `for` loop: `to` or `downto`
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum ForDir {
+    Up,
+    Down,
+}
+
 #[derive(Debug, Clone)]
 pub struct BinOp<A> {
     pub op: A,
@@ -29,6 +47,13 @@ pub enum FloatBinOp {
 pub enum IntBinOp {
     Add,
     Sub,
-    // Mul,
-    // Div,
+    Mul,
+    Div,
+    Mod,
+    And,
+    Or,
+    Xor,
+    Lsl,
+    Lsr,
+    Asr,
 }
@@ -1,9 +1,11 @@
 use crate::cg_types::RepType;
+use crate::diagnostics::Diagnostic;
+use crate::format::Conv;
 use crate::interner::{InternId, InternTable};
-use crate::type_check::{TyVar, Type};
+use crate::type_check::{ConInfo, FieldInfo, TyCon, TyVar, Type};
 use crate::var::{CompilerPhase, Uniq, Var};
 
-use fxhash::FxHashMap;
+use fxhash::{FxHashMap, FxHashSet};
 use std::num::NonZeroU32;
 use std::rc::Rc;
 
@@ -20,10 +22,52 @@ pub struct Ctx {
     ty_env: FxHashMap<VarId, TypeId>,
     rep_ty_env: FxHashMap<VarId, RepType>,
     builtins: Vec<(VarId, TypeId)>,
+    // Binders of top-level definitions
+    globals: FxHashSet<VarId>,
+    // Numbers of arguments of functions defined with `let rec` and built-ins. These are called
+    // with all of their arguments, other functions take one argument at a time, see `anormal`.
+    fun_arities: FxHashMap<VarId, usize>,
+    // Type constructors declared with `type`, by name. Later declarations shadow earlier ones.
+    ty_cons: FxHashMap<Rc<str>, TyCon>,
+    // Type abbreviations declared with `type`, mapped to their expansions. Shares the namespace
+    // with `ty_cons`.
+    ty_aliases: FxHashMap<Rc<str>, Type>,
+    // Constructors of type constructors, in declaration order
+    ty_con_cons: FxHashMap<TyCon, Vec<VarId>>,
+    cons: FxHashMap<VarId, ConInfo>,
+    // Record fields declared with `type`, by name. Later declarations shadow earlier ones.
+    field_names: FxHashMap<Rc<str>, VarId>,
+    // Fields of record types, in declaration order
+    ty_con_fields: FxHashMap<TyCon, Vec<VarId>>,
+    fields: FxHashMap<VarId, FieldInfo>,
+    // Type variables generalized in types of `let` and `let rec` binders
+    poly_vars: FxHashMap<VarId, Vec<TyVar>>,
+    // Uses of the binders in `poly_vars`, mapped to the binders. Type of a use is the instantiated
+    // type of the binder.
+    poly_uses: FxHashMap<VarId, VarId>,
+    // Type variables in `poly_vars` of values compared structurally
+    compared_tyvars: FxHashSet<TyVar>,
+    // String literals, and names of exception constructors declared with `exception`. Each one is
+    // a data object, the variable is the address of the object.
+    str_lits: FxHashMap<VarId, Rc<str>>,
+    // Type of exceptions. Exception constructors are added to it as they are declared.
+    exn_ty_con: TyCon,
+    // Predefined exception constructor raised by `assert`
+    assert_failure_con: VarId,
+    // Built-in for `try`, see `anormal`
+    try_var: VarId,
+    // Built-ins for `Printf.printf` directives, see `anormal`
+    printf_vars: FxHashMap<Conv, VarId>,
+    // Name and contents of the source file, for locations of `assert`s
+    file: Rc<str>,
+    src: Rc<str>,
+    // Warnings reported by the passes so far
+    warnings: Vec<Diagnostic>,
     // Ids for widely used types
     int_id: TypeId,
     float_id: TypeId,
     unit_id: TypeId,
+    string_id: TypeId,
 }
 
 impl Default for Ctx {
@@ -32,18 +76,51 @@ impl Default for Ctx {
         let int_id = TypeId(tys.intern(Type::Int));
         let float_id = TypeId(tys.intern(Type::Float));
         let unit_id = TypeId(tys.intern(Type::Unit));
+        let string_id = TypeId(tys.intern(Type::String));
+        // Uniqs 1 to 3 are used for variables and type constructors referred to by `Ctx`, which
+        // are added to the tables in `add_builtin_vars` and `add_exn_cons`
+        let uniq = |i| Uniq(NonZeroU32::new(i).unwrap());
+        let mut vars: InternTable<Var> = Default::default();
+        let exn_ty_con = TyCon {
+            name: "exn".into(),
+            uniq: uniq(1),
+        };
+        let assert_failure_con = VarId(vars.intern(Var::new_user("Assert_failure", uniq(2))));
+        let try_var = VarId(vars.intern(Var::new_builtin("%try", "mc_try", uniq(3))));
         let mut ctx = Ctx {
-            next_uniq: Uniq(unsafe { NonZeroU32::new_unchecked(1) }),
+            next_uniq: uniq(4),
             tys,
-            vars: Default::default(),
+            vars,
             ty_env: Default::default(),
             rep_ty_env: Default::default(),
             builtins: vec![],
+            globals: Default::default(),
+            fun_arities: Default::default(),
+            ty_cons: Default::default(),
+            ty_aliases: Default::default(),
+            ty_con_cons: Default::default(),
+            cons: Default::default(),
+            field_names: Default::default(),
+            ty_con_fields: Default::default(),
+            fields: Default::default(),
+            poly_vars: Default::default(),
+            poly_uses: Default::default(),
+            compared_tyvars: Default::default(),
+            str_lits: Default::default(),
+            exn_ty_con,
+            assert_failure_con,
+            try_var,
+            printf_vars: Default::default(),
+            file: "".into(),
+            src: "".into(),
+            warnings: vec![],
             int_id,
             float_id,
             unit_id,
+            string_id,
         };
         ctx.add_builtin_vars();
+        ctx.add_exn_cons();
         ctx
     }
 }
@@ -61,6 +138,10 @@ impl Ctx {
         self.unit_id
     }
 
+    pub fn string_type_id(&self) -> TypeId {
+        self.string_id
+    }
+
     pub fn fresh_uniq(&mut self) -> Uniq {
         let uniq = self.next_uniq;
         self.next_uniq.0 = unsafe { NonZeroU32::new_unchecked(self.next_uniq.0.get() + 1) };
@@ -160,7 +241,191 @@ impl Ctx {
         self.get_var(id).is_builtin()
     }
 
+    /// Record the number of arguments of a function defined with `let rec`
+    pub fn add_fun_arity(&mut self, fun: VarId, arity: usize) {
+        self.fun_arities.insert(fun, arity);
+    }
+
+    /// Number of arguments of a function defined with `let rec` or a built-in, `None` for other
+    /// variables
+    pub fn fun_arity(&self, var: VarId) -> Option<usize> {
+        self.fun_arities.get(&var).copied()
+    }
+
+    /// Make the variable a global. Globals are stored in data objects, so they are not captured by
+    /// closures.
+    pub fn add_global(&mut self, id: VarId) {
+        self.globals.insert(id);
+    }
+
+    pub fn is_global_var(&self, id: VarId) -> bool {
+        self.globals.contains(&id)
+    }
+
+    pub fn globals(&self) -> impl Iterator<Item = &VarId> {
+        self.globals.iter()
+    }
+
+    /// Declare a new type constructor. Shadows type constructors with the same name.
+    pub fn add_ty_con(&mut self, name: &str) -> TyCon {
+        let ty_con = TyCon {
+            name: name.into(),
+            uniq: self.fresh_uniq(),
+        };
+        self.add_ty_con_(ty_con.clone());
+        ty_con
+    }
+
+    fn add_ty_con_(&mut self, ty_con: TyCon) {
+        self.ty_aliases.remove(&ty_con.name);
+        self.ty_cons.insert(ty_con.name.clone(), ty_con.clone());
+        self.ty_con_cons.insert(ty_con.clone(), vec![]);
+        self.ty_con_fields.insert(ty_con, vec![]);
+    }
+
+    pub fn get_ty_con(&self, name: &str) -> Option<TyCon> {
+        self.ty_cons.get(name).cloned()
+    }
+
+    /// Declare a type abbreviation. Shadows type constructors and abbreviations with the same
+    /// name.
+    pub fn add_ty_alias(&mut self, name: &str, ty: Type) {
+        self.ty_cons.remove(name);
+        self.ty_aliases.insert(name.into(), ty);
+    }
+
+    /// Expansion of a type abbreviation
+    pub fn get_ty_alias(&self, name: &str) -> Option<&Type> {
+        self.ty_aliases.get(name)
+    }
+
+    /// Add a constructor of a type constructor added with `add_ty_con`
+    pub fn add_con(&mut self, con: VarId, info: ConInfo) {
+        self.ty_con_cons.get_mut(&info.ty_con).unwrap().push(con);
+        self.cons.insert(con, info);
+    }
+
+    pub fn con_info(&self, con: VarId) -> &ConInfo {
+        &self.cons[&con]
+    }
+
+    pub fn ty_con_cons(&self, ty_con: &TyCon) -> &[VarId] {
+        &self.ty_con_cons[ty_con]
+    }
+
+    /// Add a field of a record type constructor added with `add_ty_con`. Shadows fields with the
+    /// same name.
+    pub fn add_field(&mut self, field: VarId, info: FieldInfo) {
+        self.field_names.insert(self.var_name(field), field);
+        self.ty_con_fields.get_mut(&info.ty_con).unwrap().push(field);
+        self.fields.insert(field, info);
+    }
+
+    pub fn get_field(&self, name: &str) -> Option<VarId> {
+        self.field_names.get(name).copied()
+    }
+
+    pub fn field_info(&self, field: VarId) -> &FieldInfo {
+        &self.fields[&field]
+    }
+
+    pub fn record_fields(&self, ty_con: &TyCon) -> &[VarId] {
+        &self.ty_con_fields[ty_con]
+    }
+
+    /// Make the binder polymorphic in the type variables
+    pub fn set_poly_vars(&mut self, var: VarId, tyvars: Vec<TyVar>) {
+        self.poly_vars.insert(var, tyvars);
+    }
+
+    /// Type variables generalized in the type of the binder. Empty for monomorphic binders.
+    pub fn poly_vars(&self, var: VarId) -> &[TyVar] {
+        self.poly_vars.get(&var).map(|tyvars| &tyvars[..]).unwrap_or(&[])
+    }
+
+    pub fn add_poly_use(&mut self, var: VarId, binder: VarId) {
+        self.poly_uses.insert(var, binder);
+    }
+
+    /// Record that values of the generalized type variable are compared structurally, directly
+    /// or by a use of another polymorphic binder. Monomorphization copies the definition for each
+    /// type the variable is instantiated with, as comparison code depends on the type.
+    pub fn add_compared_tyvar(&mut self, tyvar: TyVar) {
+        self.compared_tyvars.insert(tyvar);
+    }
+
+    pub fn is_compared_tyvar(&self, tyvar: TyVar) -> bool {
+        self.compared_tyvars.contains(&tyvar)
+    }
+
+    /// Binder of a use of a polymorphic binder
+    pub fn poly_use_binder(&self, var: VarId) -> Option<VarId> {
+        self.poly_uses.get(&var).copied()
+    }
+
+    /// Add a string literal, return the variable for its address
+    pub fn add_str_lit(&mut self, phase: CompilerPhase, s: &str) -> VarId {
+        let var = self.fresh_generated_var(phase);
+        self.ty_env.insert(var, self.string_id);
+        self.str_lits.insert(var, s.into());
+        var
+    }
+
+    pub fn is_str_lit_var(&self, var: VarId) -> bool {
+        self.str_lits.contains_key(&var)
+    }
+
+    pub fn str_lits(&self) -> impl Iterator<Item = (&VarId, &Rc<str>)> {
+        self.str_lits.iter()
+    }
+
+    pub fn exn_ty_con(&self) -> TyCon {
+        self.exn_ty_con.clone()
+    }
+
+    /// Constructors of `exn` declared so far, including the predefined ones
+    pub fn exn_cons(&self) -> &[VarId] {
+        &self.ty_con_cons[&self.exn_ty_con]
+    }
+
+    pub fn assert_failure_con(&self) -> VarId {
+        self.assert_failure_con
+    }
+
+    pub fn try_var(&self) -> VarId {
+        self.try_var
+    }
+
+    pub fn printf_var(&self, conv: Conv) -> VarId {
+        self.printf_vars[&conv]
+    }
+
+    /// Set the file being compiled
+    pub fn set_source(&mut self, file: &str, src: &str) {
+        self.file = file.into();
+        self.src = src.into();
+    }
+
+    pub fn source_file(&self) -> Rc<str> {
+        self.file.clone()
+    }
+
+    pub fn source(&self) -> Rc<str> {
+        self.src.clone()
+    }
+
+    pub fn add_warning(&mut self, warning: Diagnostic) {
+        self.warnings.push(warning);
+    }
+
+    pub fn take_warnings(&mut self) -> Vec<Diagnostic> {
+        ::std::mem::take(&mut self.warnings)
+    }
+
     fn add_builtin(&mut self, var: VarId, ty: TypeId) {
+        if let Type::Fun { args, .. } = &*self.get_type(ty) {
+            self.fun_arities.insert(var, args.len());
+        }
         self.ty_env.insert(var, ty);
         self.builtins.push((var, ty));
     }
@@ -200,6 +465,62 @@ impl Ctx {
         });
         self.add_builtin(print_newline_var, print_newline_ty);
 
+        let print_string_var = self.fresh_builtin_var("print_string", "mc_print_string");
+        let print_string_ty = self.intern_type(Type::Fun {
+            args: vec![Type::String],
+            ret: Box::new(Type::Unit),
+        });
+        self.add_builtin(print_string_var, print_string_ty);
+
+        let concat_var = self.fresh_builtin_var("^", "mc_string_concat");
+        let concat_ty = self.intern_type(Type::Fun {
+            args: vec![Type::String, Type::String],
+            ret: Box::new(Type::String),
+        });
+        self.add_builtin(concat_var, concat_ty);
+
+        let string_length_var = self.fresh_builtin_var("String.length", "mc_string_length");
+        let string_length_ty = self.intern_type(Type::Fun {
+            args: vec![Type::String],
+            ret: Box::new(Type::Int),
+        });
+        self.add_builtin(string_length_var, string_length_ty);
+
+        let string_get_var = self.fresh_builtin_var("String.get", "mc_string_get");
+        let string_get_ty = self.intern_type(Type::Fun {
+            args: vec![Type::String, Type::Int],
+            ret: Box::new(Type::Char),
+        });
+        self.add_builtin(string_get_var, string_get_ty);
+
+        let string_sub_var = self.fresh_builtin_var("String.sub", "mc_string_sub");
+        let string_sub_ty = self.intern_type(Type::Fun {
+            args: vec![Type::String, Type::Int, Type::Int],
+            ret: Box::new(Type::String),
+        });
+        self.add_builtin(string_sub_var, string_sub_ty);
+
+        let print_char_var = self.fresh_builtin_var("print_char", "mc_print_char");
+        let print_char_ty = self.intern_type(Type::Fun {
+            args: vec![Type::Char],
+            ret: Box::new(Type::Unit),
+        });
+        self.add_builtin(print_char_var, print_char_ty);
+
+        let char_code_var = self.fresh_builtin_var("Char.code", "mc_char_code");
+        let char_code_ty = self.intern_type(Type::Fun {
+            args: vec![Type::Char],
+            ret: Box::new(Type::Int),
+        });
+        self.add_builtin(char_code_var, char_code_ty);
+
+        let char_chr_var = self.fresh_builtin_var("Char.chr", "mc_char_chr");
+        let char_chr_ty = self.intern_type(Type::Fun {
+            args: vec![Type::Int],
+            ret: Box::new(Type::Char),
+        });
+        self.add_builtin(char_chr_var, char_chr_ty);
+
         let float_of_int_var = self.fresh_builtin_var("float_of_int", "mc_float_of_int");
         let float_of_int_ty = self.intern_type(Type::Fun {
             args: vec![Type::Int],
@@ -224,5 +545,80 @@ impl Ctx {
 
         let cos_var = self.fresh_builtin_var("cos", "mc_cos");
         self.add_builtin(cos_var, float_float);
+
+        // (unit -> unit) -> int, see `anormal`
+        let try_ty = self.intern_type(Type::Fun {
+            args: vec![Type::Fun {
+                args: vec![Type::Unit],
+                ret: Box::new(Type::Unit),
+            }],
+            ret: Box::new(Type::Int),
+        });
+        self.add_builtin(self.try_var, try_ty);
+
+        // string -> <arg> -> unit. The string is the flags, width, and precision of the
+        // directive.
+        for (conv, name) in &[
+            (Conv::Int, "int"),
+            (Conv::Float, "float"),
+            (Conv::String, "string"),
+            (Conv::Char, "char"),
+            (Conv::Bool, "bool"),
+        ] {
+            let var = self.fresh_builtin_var(
+                &format!("%printf_{}", name),
+                &format!("mc_printf_{}", name),
+            );
+            let ty = self.intern_type(Type::Fun {
+                args: vec![Type::String, conv.ty()],
+                ret: Box::new(Type::Unit),
+            });
+            self.add_builtin(var, ty);
+            self.printf_vars.insert(*conv, var);
+        }
+    }
+
+    // Add the type `exn` and the predefined exception constructors. Exception values are tuples
+    // with the address of the constructor name in field 0, see `ConInfo`. Names of the predefined
+    // constructors are defined in the RTS, so that the RTS can raise and print them.
+    fn add_exn_cons(&mut self) {
+        self.add_ty_con_(self.exn_ty_con.clone());
+
+        let cons = [
+            ("Failure", "mc_exn_failure", vec![Type::String]),
+            ("Not_found", "mc_exn_not_found", vec![]),
+            ("Invalid_argument", "mc_exn_invalid_argument", vec![Type::String]),
+            (
+                "Assert_failure",
+                "mc_exn_assert_failure",
+                vec![Type::String, Type::Int, Type::Int],
+            ),
+            ("Division_by_zero", "mc_exn_division_by_zero", vec![]),
+            (
+                "Match_failure",
+                "mc_exn_match_failure",
+                vec![Type::String, Type::Int, Type::Int],
+            ),
+        ];
+
+        for (name, symbol_name, args) in cons.iter().cloned() {
+            let con = if name == "Assert_failure" {
+                self.assert_failure_con
+            } else {
+                self.fresh_user_var(name)
+            };
+            // Names are not in the scope, builtins with a `%` can't be referred to
+            let exn_name = self.fresh_builtin_var(&format!("%{}", name), symbol_name);
+            self.add_builtin(exn_name, self.string_id);
+            self.add_con(
+                con,
+                ConInfo {
+                    ty_con: self.exn_ty_con.clone(),
+                    tag: 0,
+                    args,
+                    exn_name: Some(exn_name),
+                },
+            );
+        }
     }
 }
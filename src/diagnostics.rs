@@ -0,0 +1,373 @@
+// Rendering of compiler errors, with source snippets
+
+use crate::ctx::Ctx;
+use crate::lexer::LexErr;
+use crate::parser::ParseErr;
+use crate::span::{line_col, Span};
+use crate::type_check::{TyVarNames, TypeErr};
+
+use std::fmt::Write;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Severity {
+    Error,
+    Warning,
+}
+
+#[derive(Debug)]
+pub struct Diagnostic {
+    pub severity: Severity,
+    /// Headline of the diagnostic, e.g. "mismatched types"
+    pub message: String,
+    /// Location to underline in the source snippet
+    pub span: Span,
+    /// Shown next to the carets, can be empty
+    pub label: String,
+    /// Extra lines shown after the snippet as `= note: ...`
+    pub notes: Vec<String>,
+}
+
+// ANSI escapes used when rendering with colors
+const BOLD: &str = "\x1b[1m";
+const RED: &str = "\x1b[1;31m";
+const YELLOW: &str = "\x1b[1;33m";
+const BLUE: &str = "\x1b[1;34m";
+const RESET: &str = "\x1b[0m";
+
+impl Diagnostic {
+    pub fn error(message: String, span: Span, label: String) -> Diagnostic {
+        Diagnostic {
+            severity: Severity::Error,
+            message,
+            span,
+            label,
+            notes: vec![],
+        }
+    }
+
+    pub fn warning(message: String, span: Span, label: String) -> Diagnostic {
+        Diagnostic {
+            severity: Severity::Warning,
+            message,
+            span,
+            label,
+            notes: vec![],
+        }
+    }
+
+    pub fn from_lex_err(err: &LexErr) -> Diagnostic {
+        Diagnostic::error(err.to_string(), err.span().unwrap_or_default(), String::new())
+    }
+
+    pub fn from_parse_err(err: &ParseErr) -> Diagnostic {
+        match err {
+            ParseErr::EndOfInput { span } => {
+                Diagnostic::error("unexpected end of input".to_owned(), *span, String::new())
+            }
+            ParseErr::Unexpected {
+                seen,
+                expected,
+                span,
+            } => Diagnostic::error(
+                format!("unexpected {}", seen),
+                *span,
+                format!("expected {}", expected),
+            ),
+        }
+    }
+
+    pub fn from_type_err(ctx: &Ctx, err: &TypeErr) -> Diagnostic {
+        match err {
+            TypeErr::UnifyError {
+                expected,
+                found,
+                span,
+            } => {
+                // Share type variable names between the two types so that 'a means the same thing
+                let mut names = TyVarNames::default();
+                let mut label = "expected `".to_owned();
+                expected.pp_named(&mut names, &mut label).unwrap();
+                label.push_str("`, found `");
+                found.pp_named(&mut names, &mut label).unwrap();
+                label.push('`');
+                Diagnostic::error("mismatched types".to_owned(), *span, label)
+            }
+            TypeErr::InfiniteType {
+                expected,
+                found,
+                span,
+            } => {
+                let mut names = TyVarNames::default();
+                let mut label = String::new();
+                expected.pp_named(&mut names, &mut label).unwrap();
+                label.push_str(" occurs in ");
+                found.pp_named(&mut names, &mut label).unwrap();
+                let mut diag = Diagnostic::error("infinite type".to_owned(), *span, String::new());
+                diag.notes.push(label);
+                diag
+            }
+            TypeErr::UnboundVar { var, span } => Diagnostic::error(
+                format!("unbound variable `{}`", ctx.get_var(*var).name()),
+                *span,
+                "not found in this scope".to_owned(),
+            ),
+            TypeErr::UnboundCon { con, span } => Diagnostic::error(
+                format!("unbound constructor `{}`", ctx.get_var(*con).name()),
+                *span,
+                "not found in this scope".to_owned(),
+            ),
+            TypeErr::ConArity {
+                con,
+                expected,
+                found,
+                span,
+            } => Diagnostic::error(
+                format!(
+                    "constructor `{}` expects {} argument(s), but is applied here to {} argument(s)",
+                    ctx.get_var(*con).name(),
+                    expected,
+                    found
+                ),
+                *span,
+                String::new(),
+            ),
+            TypeErr::UnboundType { name, span } => Diagnostic::error(
+                format!("unbound type constructor `{}`", name),
+                *span,
+                "not found in this scope".to_owned(),
+            ),
+            TypeErr::CyclicAlias { name, span } => Diagnostic::error(
+                format!("the type abbreviation `{}` is cyclic", name),
+                *span,
+                "used in its own expansion".to_owned(),
+            ),
+            TypeErr::TyConArity {
+                name,
+                expected,
+                found,
+                span,
+            } => Diagnostic::error(
+                format!(
+                    "type constructor `{}` expects {} argument(s), but is applied here to {} \
+                     argument(s)",
+                    name, expected, found
+                ),
+                *span,
+                String::new(),
+            ),
+            TypeErr::UnboundField { field, span } => Diagnostic::error(
+                format!("unbound record field `{}`", ctx.get_var(*field).name()),
+                *span,
+                "not found in this scope".to_owned(),
+            ),
+            TypeErr::MissingField { field, span } => Diagnostic::error(
+                format!("record field `{}` is not defined", ctx.get_var(*field).name()),
+                *span,
+                String::new(),
+            ),
+            TypeErr::DuplicateField { field, span } => Diagnostic::error(
+                format!("record field `{}` is defined several times", ctx.get_var(*field).name()),
+                *span,
+                String::new(),
+            ),
+            TypeErr::ImmutableField { field, span } => Diagnostic::error(
+                format!("record field `{}` is not mutable", ctx.get_var(*field).name()),
+                *span,
+                String::new(),
+            ),
+            TypeErr::InvalidFormat { directive, span } => Diagnostic::error(
+                format!("invalid format directive `{}`", directive),
+                *span,
+                "supported directives are %d, %i, %f, %s, %c, %b and %%".to_owned(),
+            ),
+            TypeErr::AnnotMismatch { annot, found, span } => {
+                let mut names = TyVarNames::default();
+                let mut label = "expression has type `".to_owned();
+                found.pp_named(&mut names, &mut label).unwrap();
+                label.push_str("`, but is annotated with `");
+                annot.pp_named(&mut names, &mut label).unwrap();
+                label.push('`');
+                Diagnostic::error("annotation mismatch".to_owned(), *span, label)
+            }
+        }
+    }
+
+    /// Render the diagnostic. `file` is the file name shown in the location line, `src` is the
+    /// contents of the file. When `color` is true ANSI escape codes are used for highlighting.
+    pub fn render(&self, file: &str, src: &str, color: bool) -> String {
+        let paint = |code: &'static str| if color { code } else { "" };
+
+        let (severity, severity_color) = match self.severity {
+            Severity::Error => ("error", RED),
+            Severity::Warning => ("warning", YELLOW),
+        };
+
+        let loc = line_col(src, self.span.start);
+
+        // Source line of the span start
+        let start = (self.span.start as usize).min(src.len());
+        let line_start = src[..start].rfind('\n').map(|i| i + 1).unwrap_or(0);
+        let line_end = src[start..].find('\n').map(|i| start + i).unwrap_or(src.len());
+        let line = &src[line_start..line_end];
+
+        // Multi-line spans are underlined until the end of the first line. Empty spans get one
+        // caret.
+        let end = (self.span.end as usize).clamp(start, line_end);
+        let n_carets = src[start..end].chars().count().max(1);
+
+        // Copy tabs in the prefix so that carets line up with the source line
+        let caret_indent: String = src[line_start..start]
+            .chars()
+            .map(|c| if c == '\t' { '\t' } else { ' ' })
+            .collect();
+
+        let line_num = loc.line.to_string();
+        let gutter = " ".repeat(line_num.len());
+
+        let mut s = String::new();
+        writeln!(
+            s,
+            "{}{}{}: {}{}{}",
+            paint(severity_color),
+            severity,
+            paint(RESET),
+            paint(BOLD),
+            self.message,
+            paint(RESET)
+        )
+        .unwrap();
+        writeln!(
+            s,
+            "{}{}-->{} {}:{}:{}",
+            gutter,
+            paint(BLUE),
+            paint(RESET),
+            file,
+            loc.line,
+            loc.col
+        )
+        .unwrap();
+        writeln!(s, "{} {}|{}", gutter, paint(BLUE), paint(RESET)).unwrap();
+        writeln!(s, "{}{} |{} {}", paint(BLUE), line_num, paint(RESET), line).unwrap();
+        write!(
+            s,
+            "{} {}|{} {}{}{}",
+            gutter,
+            paint(BLUE),
+            paint(RESET),
+            caret_indent,
+            paint(severity_color),
+            "^".repeat(n_carets)
+        )
+        .unwrap();
+        if !self.label.is_empty() {
+            write!(s, " {}", self.label).unwrap();
+        }
+        writeln!(s, "{}", paint(RESET)).unwrap();
+        for note in &self.notes {
+            writeln!(s, "{} {}={} note: {}", gutter, paint(BLUE), paint(RESET), note).unwrap();
+        }
+
+        s
+    }
+}
+
+#[test]
+fn render_test() {
+    let src = "let x = 1 in\n\tprint_int (x +. 2.0)\n";
+    let diag = Diagnostic::error(
+        "mismatched types".to_owned(),
+        Span::new(25, 26),
+        "expected `float`, found `int`".to_owned(),
+    );
+    assert_eq!(
+        diag.render("test.ml", src, false),
+        "error: mismatched types\n \
+         --> test.ml:2:13\n  \
+         |\n\
+         2 | \tprint_int (x +. 2.0)\n  \
+         | \t           ^ expected `float`, found `int`\n"
+    );
+}
+
+// Error and warnings of compiling the program up to code generation, rendered without colors
+#[cfg(test)]
+fn program_diagnostics(src: &str) -> String {
+    let mut ctx: Ctx = Default::default();
+    ctx.set_source("test.ml", src);
+    let mut rendered = String::new();
+    if let Err(err) = crate::front_end(&mut ctx, src, &mut vec![]) {
+        rendered.push_str(&err.render("test.ml", src, false));
+    }
+    for warning in ctx.take_warnings() {
+        rendered.push_str(&warning.render("test.ml", src, false));
+    }
+    rendered
+}
+
+#[test]
+fn cyclic_alias_test() {
+    assert_eq!(program_diagnostics("type a = b and b = int\nlet x : a = 1"), "");
+    assert_eq!(
+        program_diagnostics("type a = b * int\nand b = a list"),
+        "error: the type abbreviation `a` is cyclic\n \
+         --> test.ml:2:9\n  \
+         |\n\
+         2 | and b = a list\n  \
+         |         ^ used in its own expansion\n"
+    );
+}
+
+#[test]
+fn match_warnings_test() {
+    assert_eq!(
+        program_diagnostics("let rec f n = match n with 0 -> 1 | 1 -> 2"),
+        "warning: this pattern-matching is not exhaustive\n \
+         --> test.ml:1:15\n  \
+         |\n\
+         1 | let rec f n = match n with 0 -> 1 | 1 -> 2\n  \
+         |               ^^^^^^^^^^^^^^^^^^^^^^^^^^^^\n  \
+         = note: here is an example of a case that is not matched: `2`\n"
+    );
+    assert_eq!(
+        program_diagnostics("let rec f b = match b with true -> 1 | false -> 2 | _ -> 3"),
+        "warning: this match case is unused\n \
+         --> test.ml:1:53\n  \
+         |\n\
+         1 | let rec f b = match b with true -> 1 | false -> 2 | _ -> 3\n  \
+         |                                                     ^\n"
+    );
+}
+
+#[test]
+fn printf_errors_test() {
+    assert_eq!(
+        program_diagnostics("let () = Printf.printf \"%d %q\" 1"),
+        "error: invalid format directive `%q`\n \
+         --> test.ml:1:10\n  \
+         |\n\
+         1 | let () = Printf.printf \"%d %q\" 1\n  \
+         |          ^^^^^^^^^^^^^^^^^^^^^ supported directives are %d, %i, %f, %s, %c, %b \
+         and %%\n"
+    );
+    assert_eq!(
+        program_diagnostics("let () = Printf.printf \"%d\\n\" \"a\""),
+        "error: mismatched types\n \
+         --> test.ml:1:31\n  \
+         |\n\
+         1 | let () = Printf.printf \"%d\\n\" \"a\"\n  \
+         |                               ^^^ expected `int`, found `string`\n"
+    );
+}
+
+#[test]
+fn annot_mismatch_test() {
+    assert_eq!(
+        program_diagnostics("let x : int = 1.0"),
+        "error: annotation mismatch\n \
+         --> test.ml:1:15\n  \
+         |\n\
+         1 | let x : int = 1.0\n  \
+         |               ^^^ expression has type `float`, but is annotated with `int`\n"
+    );
+}
@@ -0,0 +1,145 @@
+// Format strings of `Printf.printf`. The type checker derives the type of `Printf.printf "..."`
+// from the directives, and `anormal` compiles the call to a runtime call for each directive and
+// each piece of text between directives.
+
+use crate::type_check::Type;
+
+use std::iter::Peekable;
+use std::str::CharIndices;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Conv {
+    // %d, %i
+    Int,
+    // %f
+    Float,
+    // %s
+    String,
+    // %c
+    Char,
+    // %b
+    Bool,
+}
+
+impl Conv {
+    // Type of the argument
+    pub fn ty(self) -> Type {
+        match self {
+            Conv::Int => Type::Int,
+            Conv::Float => Type::Float,
+            Conv::String => Type::String,
+            Conv::Char => Type::Char,
+            Conv::Bool => Type::Bool,
+        }
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub enum Piece {
+    // Text printed as is, with `%%` replaced by `%`
+    Text(String),
+    // A directive. `spec` is the flags, width, and precision, e.g. `-5` in `%-5d` or `.2` in
+    // `%.2f`.
+    Directive { spec: String, conv: Conv },
+}
+
+// Split a format string into text and directives. Returns the invalid directive on error.
+pub fn parse(fmt: &str) -> Result<Vec<Piece>, String> {
+    let mut pieces: Vec<Piece> = vec![];
+    let mut text = String::new();
+    let mut chars = fmt.char_indices().peekable();
+    while let Some((start, c)) = chars.next() {
+        if c != '%' {
+            text.push(c);
+            continue;
+        }
+        if let Some((_, '%')) = chars.peek() {
+            chars.next();
+            text.push('%');
+            continue;
+        }
+
+        let mut spec = String::new();
+        // Flags, width, and precision
+        push_while(&mut chars, &mut spec, |c| matches!(c, '-' | '0' | '+' | ' '));
+        push_while(&mut chars, &mut spec, |c| c.is_ascii_digit());
+        if let Some((_, '.')) = chars.peek() {
+            chars.next();
+            spec.push('.');
+            push_while(&mut chars, &mut spec, |c| c.is_ascii_digit());
+        }
+        let conv = match chars.next() {
+            Some((_, 'd')) | Some((_, 'i')) => Conv::Int,
+            Some((_, 'f')) => Conv::Float,
+            Some((_, 's')) => Conv::String,
+            Some((_, 'c')) => Conv::Char,
+            Some((_, 'b')) => Conv::Bool,
+            other => {
+                let end = match other {
+                    Some((idx, c)) => idx + c.len_utf8(),
+                    None => fmt.len(),
+                };
+                return Err(fmt[start..end].to_owned());
+            }
+        };
+
+        if !text.is_empty() {
+            pieces.push(Piece::Text(std::mem::take(&mut text)));
+        }
+        pieces.push(Piece::Directive { spec, conv });
+    }
+    if !text.is_empty() {
+        pieces.push(Piece::Text(text));
+    }
+    Ok(pieces)
+}
+
+fn push_while(chars: &mut Peekable<CharIndices>, s: &mut String, pred: impl Fn(char) -> bool) {
+    while let Some((_, c)) = chars.peek() {
+        if !pred(*c) {
+            break;
+        }
+        s.push(*c);
+        chars.next();
+    }
+}
+
+// Types of the arguments of `Printf.printf` with the format string
+pub fn arg_tys(pieces: &[Piece]) -> Vec<Type> {
+    pieces
+        .iter()
+        .filter_map(|piece| match piece {
+            Piece::Text(_) => None,
+            Piece::Directive { conv, .. } => Some(conv.ty()),
+        })
+        .collect()
+}
+
+#[test]
+fn format_test() {
+    let directive = |spec: &str, conv| Piece::Directive {
+        spec: spec.to_owned(),
+        conv,
+    };
+    assert_eq!(
+        parse("%d items, %-8.2f avg 100%%\n"),
+        Ok(vec![
+            directive("", Conv::Int),
+            Piece::Text(" items, ".to_owned()),
+            directive("-8.2", Conv::Float),
+            Piece::Text(" avg 100%\n".to_owned()),
+        ])
+    );
+    assert_eq!(
+        parse("%5s%c%b%i"),
+        Ok(vec![
+            directive("5", Conv::String),
+            directive("", Conv::Char),
+            directive("", Conv::Bool),
+            directive("", Conv::Int),
+        ])
+    );
+    assert_eq!(parse("x %5q"), Err("%5q".to_owned()));
+    assert_eq!(parse("x %"), Err("%".to_owned()));
+    assert_eq!(parse("%1.2.3f"), Err("%1.2.".to_owned()));
+}
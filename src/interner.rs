@@ -13,13 +13,13 @@ pub struct InternId {
 
 impl InternId {
     fn from_u32(i: u32) -> InternId {
-        assert!(i < std::u32::MAX);
+        assert!(i < u32::MAX);
         InternId {
             value: unsafe { NonZeroU32::new_unchecked(i + 1) },
         }
     }
 
-    fn to_u32(&self) -> u32 {
+    fn to_u32(self) -> u32 {
         self.value.get() - 1
     }
 }
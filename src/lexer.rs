@@ -1,9 +1,16 @@
+use crate::span::{Span, Spanned};
+
+use std::fmt;
 use std::str::FromStr;
 
 #[derive(Debug, PartialEq, Clone)]
 pub enum Token {
     LParen,
     RParen,
+    LBrace,
+    RBrace,
+    LBracket,
+    RBracket,
     Bool(bool),
     Not,
     If,
@@ -11,14 +18,45 @@ pub enum Token {
     Else,
     Let,
     Rec,
+    And,
     In,
+    Fun,
+    For,
+    To,
+    Downto,
+    While,
+    Do,
+    Done,
+    Match,
+    With,
+    Type,
+    Of,
+    Mutable,
+    Ref,
+    Exception,
+    Raise,
+    Try,
+    Failwith,
+    Assert,
     Minus,
     MinusDot,
+    MinusGreater,
     Plus,
     PlusDot,
+    Ast,
     AstDot,
+    Slash,
     SlashDot,
+    Mod,
+    Land,
+    Lor,
+    Lxor,
+    Lnot,
+    Lsl,
+    Lsr,
+    Asr,
     Equal,
+    EqualEqual,
     LessGreater,
     LessEqual,
     LessMinus,
@@ -27,28 +65,171 @@ pub enum Token {
     Greater,
     Dot,
     Comma,
+    Colon,
+    ColonColon,
+    ColonEqual,
+    Bang,
+    BangEqual,
     Semicolon,
+    DoubleSemicolon,
+    Pipe,
+    DoublePipe,
+    DoubleAmp,
+    Caret,
     Underscore,
     ArrayCreate,
     Id(String),
+    // Capitalized identifier, e.g. a constructor
+    UId(String),
     Int(i64),
     Float(f64),
+    String(String),
+    Char(u8),
 }
 
 #[derive(Debug)]
 pub enum LexErr {
     EndOfInput,
-    // UnterminatedComment,
-    UnexpectedChar { expected: u8, found: u8 },
-    UnexpectedUppercaseChar { found: u8 },
-    InvalidFloat { found: String },
-    InvalidInt { found: String },
+    UnterminatedComment { span: Span },
+    UnterminatedString { span: Span },
+    // A quote not followed by a character and a closing quote
+    InvalidCharLit { span: Span },
+    // Unknown escape sequence, or one for a non-ASCII character, which we don't support yet
+    InvalidEscape { found: String, span: Span },
+    UnexpectedUppercaseChar { found: u8, span: Span },
+    InvalidFloat { found: String, span: Span },
+    InvalidInt { found: String, span: Span },
+}
+
+impl LexErr {
+    pub fn span(&self) -> Option<Span> {
+        use LexErr::*;
+        match self {
+            EndOfInput => None,
+            UnterminatedComment { span }
+            | UnterminatedString { span }
+            | InvalidCharLit { span }
+            | InvalidEscape { span, .. }
+            | UnexpectedUppercaseChar { span, .. }
+            | InvalidFloat { span, .. }
+            | InvalidInt { span, .. } => Some(*span),
+        }
+    }
+}
+
+impl fmt::Display for LexErr {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        use LexErr::*;
+        match self {
+            EndOfInput => write!(f, "unexpected end of input"),
+            UnterminatedComment { .. } => write!(f, "unterminated comment"),
+            UnterminatedString { .. } => write!(f, "unterminated string literal"),
+            InvalidCharLit { .. } => write!(f, "invalid character literal"),
+            InvalidEscape { found, .. } => write!(f, "invalid escape sequence `{}`", found),
+            UnexpectedUppercaseChar { found, .. } => {
+                write!(f, "unexpected character {:?}", char::from(*found))
+            }
+            InvalidFloat { found, .. } => write!(f, "invalid float literal `{}`", found),
+            InvalidInt { found, .. } => write!(f, "invalid integer literal `{}`", found),
+        }
+    }
+}
+
+impl fmt::Display for Token {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        use Token::*;
+        let s = match self {
+            LParen => "(",
+            RParen => ")",
+            LBrace => "{",
+            RBrace => "}",
+            LBracket => "[",
+            RBracket => "]",
+            Bool(true) => "true",
+            Bool(false) => "false",
+            Not => "not",
+            If => "if",
+            Then => "then",
+            Else => "else",
+            Let => "let",
+            Rec => "rec",
+            And => "and",
+            In => "in",
+            Fun => "fun",
+            For => "for",
+            To => "to",
+            Downto => "downto",
+            While => "while",
+            Do => "do",
+            Done => "done",
+            Match => "match",
+            With => "with",
+            Type => "type",
+            Of => "of",
+            Mutable => "mutable",
+            Ref => "ref",
+            Exception => "exception",
+            Raise => "raise",
+            Try => "try",
+            Failwith => "failwith",
+            Assert => "assert",
+            Minus => "-",
+            MinusDot => "-.",
+            MinusGreater => "->",
+            Plus => "+",
+            PlusDot => "+.",
+            Ast => "*",
+            AstDot => "*.",
+            Slash => "/",
+            SlashDot => "/.",
+            Mod => "mod",
+            Land => "land",
+            Lor => "lor",
+            Lxor => "lxor",
+            Lnot => "lnot",
+            Lsl => "lsl",
+            Lsr => "lsr",
+            Asr => "asr",
+            Equal => "=",
+            EqualEqual => "==",
+            LessGreater => "<>",
+            LessEqual => "<=",
+            LessMinus => "<-",
+            Less => "<",
+            GreaterEqual => ">=",
+            Greater => ">",
+            Dot => ".",
+            Comma => ",",
+            Colon => ":",
+            ColonColon => "::",
+            ColonEqual => ":=",
+            Bang => "!",
+            BangEqual => "!=",
+            Semicolon => ";",
+            DoubleSemicolon => ";;",
+            Pipe => "|",
+            DoublePipe => "||",
+            DoubleAmp => "&&",
+            Caret => "^",
+            Underscore => "_",
+            ArrayCreate => "Array.create",
+            Id(id) => return write!(f, "identifier `{}`", id),
+            UId(id) => return write!(f, "constructor `{}`", id),
+            Int(i) => return write!(f, "integer `{}`", i),
+            Float(d) => return write!(f, "float `{:?}`", d),
+            String(s) => return write!(f, "string {:?}", s),
+            Char(c) => return write!(f, "character {:?}", char::from(*c)),
+        };
+        write!(f, "'{}'", s)
+    }
 }
 
 pub struct Lexer<'a> {
     input: &'a [u8],
     // Current position in `input`
     byte_idx: usize,
+    // Start of the token being lexed
+    tok_start: usize,
     buf: String,
 }
 
@@ -58,7 +239,7 @@ static ARRAY_CREATE_LEN: usize = ARRAY_CREATE_STR.len();
 static ARRAY_MAKE_STR: &str = "Array.make";
 static ARRAY_MAKE_LEN: usize = ARRAY_MAKE_STR.len();
 
-pub fn tokenize(expr_str: &str) -> Result<Vec<Token>, LexErr> {
+pub fn tokenize(expr_str: &str) -> Result<Vec<Spanned<Token>>, LexErr> {
     let mut lexer = Lexer::new(expr_str.as_bytes());
     let mut tokens = vec![];
     loop {
@@ -78,16 +259,26 @@ pub fn tokenize(expr_str: &str) -> Result<Vec<Token>, LexErr> {
 }
 
 impl<'a> Lexer<'a> {
-    pub fn new(input: &[u8]) -> Lexer {
+    pub fn new(input: &[u8]) -> Lexer<'_> {
         Lexer {
             input,
             byte_idx: 0,
+            tok_start: 0,
             buf: String::with_capacity(20),
         }
     }
 
-    pub fn next(&mut self) -> Result<Token, LexErr> {
+    pub fn next(&mut self) -> Result<Spanned<Token>, LexErr> {
+        let token = self.next_token()?;
+        Ok(Spanned {
+            node: token,
+            span: Span::new(self.tok_start, self.byte_idx),
+        })
+    }
+
+    fn next_token(&mut self) -> Result<Token, LexErr> {
         loop {
+            self.tok_start = self.byte_idx;
             match self.next_byte()? {
                 next if next.is_ascii_whitespace() => {
                     self.consume();
@@ -108,13 +299,36 @@ impl<'a> Lexer<'a> {
                     self.consume();
                     return Ok(Token::RParen);
                 }
+                b'{' => {
+                    self.consume();
+                    return Ok(Token::LBrace);
+                }
+                b'}' => {
+                    self.consume();
+                    return Ok(Token::RBrace);
+                }
+                b'[' => {
+                    self.consume();
+                    return Ok(Token::LBracket);
+                }
+                b']' => {
+                    self.consume();
+                    return Ok(Token::RBracket);
+                }
                 b'-' => {
                     self.consume();
-                    if let Ok(b'.') = self.next_byte() {
-                        self.consume();
-                        return Ok(Token::MinusDot);
-                    } else {
-                        return Ok(Token::Minus);
+                    match self.next_byte() {
+                        Ok(b'.') => {
+                            self.consume();
+                            return Ok(Token::MinusDot);
+                        }
+                        Ok(b'>') => {
+                            self.consume();
+                            return Ok(Token::MinusGreater);
+                        }
+                        _ => {
+                            return Ok(Token::Minus);
+                        }
                     }
                 }
                 b'+' => {
@@ -128,19 +342,30 @@ impl<'a> Lexer<'a> {
                 }
                 b'*' => {
                     self.consume();
-                    self.expect_char(b'.')?;
-                    self.consume();
-                    return Ok(Token::AstDot);
+                    if let Ok(b'.') = self.next_byte() {
+                        self.consume();
+                        return Ok(Token::AstDot);
+                    } else {
+                        return Ok(Token::Ast);
+                    }
                 }
                 b'/' => {
                     self.consume();
-                    self.expect_char(b'.')?;
-                    self.consume();
-                    return Ok(Token::SlashDot);
+                    if let Ok(b'.') = self.next_byte() {
+                        self.consume();
+                        return Ok(Token::SlashDot);
+                    } else {
+                        return Ok(Token::Slash);
+                    }
                 }
                 b'=' => {
                     self.consume();
-                    return Ok(Token::Equal);
+                    if let Ok(b'=') = self.next_byte() {
+                        self.consume();
+                        return Ok(Token::EqualEqual);
+                    } else {
+                        return Ok(Token::Equal);
+                    }
                 }
                 b'<' => {
                     self.consume();
@@ -184,14 +409,69 @@ impl<'a> Lexer<'a> {
                     self.consume();
                     return Ok(Token::Comma);
                 }
+                b':' => {
+                    self.consume();
+                    match self.next_byte() {
+                        Ok(b'=') => {
+                            self.consume();
+                            return Ok(Token::ColonEqual);
+                        }
+                        Ok(b':') => {
+                            self.consume();
+                            return Ok(Token::ColonColon);
+                        }
+                        _ => {
+                            return Ok(Token::Colon);
+                        }
+                    }
+                }
+                b'!' => {
+                    self.consume();
+                    if let Ok(b'=') = self.next_byte() {
+                        self.consume();
+                        return Ok(Token::BangEqual);
+                    } else {
+                        return Ok(Token::Bang);
+                    }
+                }
                 b';' => {
                     self.consume();
-                    return Ok(Token::Semicolon);
+                    if let Ok(b';') = self.next_byte() {
+                        self.consume();
+                        return Ok(Token::DoubleSemicolon);
+                    } else {
+                        return Ok(Token::Semicolon);
+                    }
+                }
+                b'|' => {
+                    self.consume();
+                    if let Ok(b'|') = self.next_byte() {
+                        self.consume();
+                        return Ok(Token::DoublePipe);
+                    } else {
+                        return Ok(Token::Pipe);
+                    }
+                }
+                b'^' => {
+                    self.consume();
+                    return Ok(Token::Caret);
+                }
+                b'&' if self.input.get(self.byte_idx + 1) == Some(&b'&') => {
+                    self.byte_idx += 2;
+                    return Ok(Token::DoubleAmp);
                 }
                 b'_' => {
                     self.consume();
                     return Ok(Token::Underscore);
                 }
+                b'"' => {
+                    self.consume();
+                    return self.expect_string();
+                }
+                b'\'' => {
+                    self.consume();
+                    return self.expect_char();
+                }
                 _ => {
                     return self.expect_kw_or_id();
                 }
@@ -202,28 +482,8 @@ impl<'a> Lexer<'a> {
     fn expect_kw_or_id(&mut self) -> Result<Token, LexErr> {
         let next = self.next_byte()?;
         if next.is_ascii_lowercase() {
-            self.consume();
-            self.buf.push(char::from(next));
-            loop {
-                let next = self.next_byte();
-                match next {
-                    Err(_) => {
-                        return Ok(self.process_id());
-                    }
-                    Ok(next) => {
-                        if next.is_ascii_digit()
-                            || next.is_ascii_lowercase()
-                            || next.is_ascii_uppercase()
-                            || next == b'_'
-                        {
-                            self.consume();
-                            self.buf.push(char::from(next));
-                        } else {
-                            return Ok(self.process_id());
-                        }
-                    }
-                }
-            }
+            self.consume_id();
+            Ok(self.process_id())
         } else {
             // Array.create or Array.make
             let rest = &self.input[self.byte_idx..];
@@ -237,8 +497,35 @@ impl<'a> Lexer<'a> {
             {
                 self.byte_idx += ARRAY_MAKE_LEN;
                 Ok(Token::ArrayCreate)
+            } else if next.is_ascii_uppercase() {
+                self.consume_id();
+                // Qualified names of library functions, e.g. `String.length`
+                if let [b'.', next, ..] = self.input[self.byte_idx..] {
+                    if next.is_ascii_lowercase() {
+                        self.consume();
+                        self.buf.push('.');
+                        self.consume_id();
+                        return Ok(Token::Id(self.flush_buf()));
+                    }
+                }
+                Ok(Token::UId(self.flush_buf()))
+            } else {
+                Err(LexErr::UnexpectedUppercaseChar {
+                    found: next,
+                    span: Span::new(self.byte_idx, self.byte_idx + 1),
+                })
+            }
+        }
+    }
+
+    // Consume an identifier into `buf`. First character is a letter.
+    fn consume_id(&mut self) {
+        while let Ok(next) = self.next_byte() {
+            if next.is_ascii_alphanumeric() || next == b'_' {
+                self.consume();
+                self.buf.push(char::from(next));
             } else {
-                Err(LexErr::UnexpectedUppercaseChar { found: next })
+                break;
             }
         }
     }
@@ -253,9 +540,36 @@ impl<'a> Lexer<'a> {
             "let" => Token::Let,
             "in" => Token::In,
             "rec" => Token::Rec,
+            "and" => Token::And,
+            "fun" => Token::Fun,
+            "for" => Token::For,
+            "to" => Token::To,
+            "downto" => Token::Downto,
+            "while" => Token::While,
+            "do" => Token::Do,
+            "done" => Token::Done,
             "true" => Token::Bool(true),
             "false" => Token::Bool(false),
             "not" => Token::Not,
+            "mod" => Token::Mod,
+            "land" => Token::Land,
+            "lor" => Token::Lor,
+            "lxor" => Token::Lxor,
+            "lnot" => Token::Lnot,
+            "lsl" => Token::Lsl,
+            "lsr" => Token::Lsr,
+            "asr" => Token::Asr,
+            "match" => Token::Match,
+            "with" => Token::With,
+            "type" => Token::Type,
+            "of" => Token::Of,
+            "mutable" => Token::Mutable,
+            "ref" => Token::Ref,
+            "exception" => Token::Exception,
+            "raise" => Token::Raise,
+            "try" => Token::Try,
+            "failwith" => Token::Failwith,
+            "assert" => Token::Assert,
             _ => Token::Id(id),
         }
     }
@@ -271,7 +585,10 @@ impl<'a> Lexer<'a> {
                 Ok(b'.') => {
                     if dot_seen {
                         let invalid = self.flush_buf();
-                        return Err(LexErr::InvalidFloat { found: invalid });
+                        return Err(LexErr::InvalidFloat {
+                            found: invalid,
+                            span: self.tok_span(),
+                        });
                     } else {
                         self.buf.push('.');
                         self.consume();
@@ -299,7 +616,10 @@ impl<'a> Lexer<'a> {
         let str = self.flush_buf();
         match f64::from_str(&str) {
             Ok(f) => Ok(Token::Float(f)),
-            Err(_) => Err(LexErr::InvalidFloat { found: str }),
+            Err(_) => Err(LexErr::InvalidFloat {
+                found: str,
+                span: self.tok_span(),
+            }),
         }
     }
 
@@ -307,26 +627,20 @@ impl<'a> Lexer<'a> {
         let str = self.flush_buf();
         match i64::from_str(&str) {
             Ok(i) => Ok(Token::Int(i)),
-            Err(_) => Err(LexErr::InvalidInt { found: str }),
-        }
-    }
-
-    fn expect_char(&mut self, char: u8) -> Result<(), LexErr> {
-        let next = self.next_byte()?;
-        if next == char {
-            Ok(())
-        } else {
-            Err(LexErr::UnexpectedChar {
-                expected: char,
-                found: next,
-            })
+            Err(_) => Err(LexErr::InvalidInt {
+                found: str,
+                span: self.tok_span(),
+            }),
         }
     }
 
     fn consume_comment(&mut self) -> Result<(), LexErr> {
         let mut level = 1;
         while level != 0 {
-            match self.next_byte()? {
+            let next = self.next_byte().map_err(|_| LexErr::UnterminatedComment {
+                span: Span::new(self.tok_start, self.tok_start + 2),
+            })?;
+            match next {
                 b'(' => {
                     self.consume();
                     if let Ok(b'*') = self.next_byte() {
@@ -349,6 +663,119 @@ impl<'a> Lexer<'a> {
         Ok(())
     }
 
+    // After the opening '"'
+    fn expect_string(&mut self) -> Result<Token, LexErr> {
+        let mut bytes: Vec<u8> = vec![];
+        loop {
+            match self.next_byte() {
+                Err(_) => {
+                    return Err(LexErr::UnterminatedString {
+                        span: Span::new(self.tok_start, self.tok_start + 1),
+                    });
+                }
+                Ok(b'"') => {
+                    break;
+                }
+                Ok(b'\\') if self.input.get(self.byte_idx + 1) == Some(&b'\n') => {
+                    // Line continuation: skip the newline and leading blanks of the next line
+                    self.byte_idx += 2;
+                    while let Ok(b' ') | Ok(b'\t') = self.next_byte() {
+                        self.consume();
+                    }
+                }
+                Ok(b'\\') => {
+                    bytes.push(self.expect_escape()?);
+                }
+                Ok(byte) => {
+                    self.consume();
+                    bytes.push(byte);
+                }
+            }
+        }
+        // Input is a `str` and escapes are ASCII, so the contents are valid UTF-8
+        let s = String::from_utf8(bytes).unwrap();
+        self.consume();
+        Ok(Token::String(s))
+    }
+
+    // Lex a character literal. `byte_idx` is after the opening quote.
+    fn expect_char(&mut self) -> Result<Token, LexErr> {
+        let c = match self.next_byte() {
+            Ok(b'\\') => self.expect_escape()?,
+            Ok(c) if c.is_ascii() && c != b'\'' => {
+                self.consume();
+                c
+            }
+            _ => {
+                return Err(LexErr::InvalidCharLit {
+                    span: Span::new(self.tok_start, self.tok_start + 1),
+                })
+            }
+        };
+        match self.next_byte() {
+            Ok(b'\'') => {
+                self.consume();
+                Ok(Token::Char(c))
+            }
+            _ => Err(LexErr::InvalidCharLit {
+                span: Span::new(self.tok_start, self.tok_start + 1),
+            }),
+        }
+    }
+
+    // Lex an escape sequence in a string or character literal. `byte_idx` is at the backslash.
+    fn expect_escape(&mut self) -> Result<u8, LexErr> {
+        let start = self.byte_idx;
+        self.consume();
+        let escaped = match self.next_byte() {
+            Ok(b'\\') => Some(b'\\'),
+            Ok(b'"') => Some(b'"'),
+            Ok(b'\'') => Some(b'\''),
+            Ok(b'n') => Some(b'\n'),
+            Ok(b't') => Some(b'\t'),
+            Ok(b'b') => Some(8),
+            Ok(b'r') => Some(b'\r'),
+            Ok(b' ') => Some(b' '),
+            Ok(_) | Err(_) => None,
+        };
+        if let Some(escaped) = escaped {
+            self.consume();
+            return Ok(escaped);
+        }
+
+        // `\ddd` (decimal), `\xhh` (hexadecimal), or `\oooo` (octal)
+        let (radix, n_digits, digits_start) = match self.next_byte() {
+            Ok(b'x') => (16, 2, self.byte_idx + 1),
+            Ok(b'o') => (8, 3, self.byte_idx + 1),
+            _ => (10, 3, self.byte_idx),
+        };
+        let end = (digits_start + n_digits).min(self.input.len());
+        let digits = std::str::from_utf8(&self.input[digits_start..end]).unwrap_or("");
+        let code = if digits.len() == n_digits && digits.bytes().all(|b| b.is_ascii_hexdigit()) {
+            u32::from_str_radix(digits, radix).ok()
+        } else {
+            None
+        };
+        match code {
+            Some(code) if code < 128 => {
+                self.byte_idx = end;
+                Ok(code as u8)
+            }
+            _ => {
+                // Report the backslash and the next character
+                let next_len = std::str::from_utf8(&self.input[self.byte_idx..])
+                    .ok()
+                    .and_then(|s| s.chars().next())
+                    .map_or(0, char::len_utf8);
+                let end = if code.is_some() { end } else { self.byte_idx + next_len };
+                Err(LexErr::InvalidEscape {
+                    found: String::from_utf8_lossy(&self.input[start..end]).into_owned(),
+                    span: Span::new(start, end),
+                })
+            }
+        }
+    }
+
     fn next_byte(&self) -> Result<u8, LexErr> {
         match self.input.get(self.byte_idx) {
             None => Err(LexErr::EndOfInput),
@@ -356,6 +783,10 @@ impl<'a> Lexer<'a> {
         }
     }
 
+    fn tok_span(&self) -> Span {
+        Span::new(self.tok_start, self.byte_idx)
+    }
+
     fn consume(&mut self) {
         self.byte_idx += 1;
     }
@@ -367,15 +798,18 @@ impl<'a> Lexer<'a> {
 
 #[test]
 fn lexer_test() {
-    let input = "(* blah blah *) > < <> = +. - +";
+    let input = "(* blah blah *) > < <> = +. - + == != !";
     let mut lexer = Lexer::new(input.as_bytes());
-    assert_eq!(lexer.next().unwrap(), Token::Greater);
-    assert_eq!(lexer.next().unwrap(), Token::Less);
-    assert_eq!(lexer.next().unwrap(), Token::LessGreater);
-    assert_eq!(lexer.next().unwrap(), Token::Equal);
-    assert_eq!(lexer.next().unwrap(), Token::PlusDot);
-    assert_eq!(lexer.next().unwrap(), Token::Minus);
-    assert_eq!(lexer.next().unwrap(), Token::Plus);
+    assert_eq!(lexer.next().unwrap().node, Token::Greater);
+    assert_eq!(lexer.next().unwrap().node, Token::Less);
+    assert_eq!(lexer.next().unwrap().node, Token::LessGreater);
+    assert_eq!(lexer.next().unwrap().node, Token::Equal);
+    assert_eq!(lexer.next().unwrap().node, Token::PlusDot);
+    assert_eq!(lexer.next().unwrap().node, Token::Minus);
+    assert_eq!(lexer.next().unwrap().node, Token::Plus);
+    assert_eq!(lexer.next().unwrap().node, Token::EqualEqual);
+    assert_eq!(lexer.next().unwrap().node, Token::BangEqual);
+    assert_eq!(lexer.next().unwrap().node, Token::Bang);
     match lexer.next() {
         Err(LexErr::EndOfInput) => {}
         other => {
@@ -383,3 +817,66 @@ fn lexer_test() {
         }
     }
 }
+
+#[test]
+fn lexer_span_test() {
+    let tokens = tokenize("let x =\n  12.5 (* c *) in x").unwrap();
+    let spans: Vec<(u32, u32)> = tokens.iter().map(|t| (t.span.start, t.span.end)).collect();
+    assert_eq!(spans, vec![(0, 3), (4, 5), (6, 7), (10, 14), (23, 25), (26, 27)]);
+
+    match tokenize("1 + (* unterminated") {
+        Err(LexErr::UnterminatedComment { span }) => assert_eq!(span, Span::new(4, 6)),
+        other => panic!("{:#?}", other),
+    }
+}
+
+#[test]
+fn lexer_string_test() {
+    let tokens = tokenize("failwith \"no (* comment *)\"").unwrap();
+    assert_eq!(tokens[0].node, Token::Failwith);
+    assert_eq!(tokens[1].node, Token::String("no (* comment *)".to_owned()));
+    assert_eq!(tokens[1].span, Span::new(9, 27));
+
+    match tokenize("x \"unterminated") {
+        Err(LexErr::UnterminatedString { span }) => assert_eq!(span, Span::new(2, 3)),
+        other => panic!("{:#?}", other),
+    }
+}
+
+#[test]
+fn lexer_escape_test() {
+    let tokens = tokenize(r#"print_string "a\n\t\"\\\065\x42\o103 \
+                             b""#)
+    .unwrap();
+    assert_eq!(tokens[1].node, Token::String("a\n\t\"\\ABC b".to_owned()));
+
+    let tokens = tokenize("String.length s").unwrap();
+    assert_eq!(tokens[0].node, Token::Id("String.length".to_owned()));
+
+    match tokenize(r#""\q""#) {
+        Err(LexErr::InvalidEscape { found, span }) => {
+            assert_eq!(found, r"\q");
+            assert_eq!(span, Span::new(1, 3));
+        }
+        other => panic!("{:#?}", other),
+    }
+
+    match tokenize(r#""\200""#) {
+        Err(LexErr::InvalidEscape { found, .. }) => assert_eq!(found, r"\200"),
+        other => panic!("{:#?}", other),
+    }
+}
+
+#[test]
+fn lexer_char_test() {
+    let tokens = tokenize(r"print_char 'a'; f '\n' '\''").unwrap();
+    assert_eq!(tokens[1].node, Token::Char(b'a'));
+    assert_eq!(tokens[1].span, Span::new(11, 14));
+    assert_eq!(tokens[4].node, Token::Char(b'\n'));
+    assert_eq!(tokens[5].node, Token::Char(b'\''));
+
+    match tokenize("x 'ab'") {
+        Err(LexErr::InvalidCharLit { span }) => assert_eq!(span, Span::new(2, 3)),
+        other => panic!("{:#?}", other),
+    }
+}
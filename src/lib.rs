@@ -1,25 +1,36 @@
-#![feature(or_patterns, box_patterns)]
+#![feature(box_patterns)]
+#![cfg_attr(test, feature(test))]
+
+#[cfg(test)]
+extern crate test;
 
 mod anormal;
 mod cg_types;
 mod codegen;
 mod common;
 mod ctx;
+mod diagnostics;
+mod format;
 mod interner;
 mod lexer;
 mod locals;
 mod lower;
+mod monomorph;
 mod parser;
 mod perf;
+mod span;
 mod type_check;
 mod utils;
 mod var;
 
-use anormal::anormal;
+use anormal::anormal_pgm;
 use codegen::codegen;
+use diagnostics::Diagnostic;
 use lexer::{tokenize, Token};
 use lower::lower_pgm;
+use monomorph::monomorph_pgm;
 use parser::parse;
+use span::Spanned;
 use type_check::type_check_pgm;
 
 use std::fs::File;
@@ -57,54 +68,74 @@ fn record_pass_stats<A, F: FnOnce() -> A>(
 
 type ObjectCode = Vec<u8>;
 
-fn compile_expr(
-    expr_str: &str, dump_cc: bool, dump_cg: bool, show_pass_stats: bool,
-) -> Option<ObjectCode> {
-    let mut pass_stats: Vec<PassStats> = Vec::with_capacity(10);
+// Library functions written in MinCaml, e.g. `List.map`
+static PRELUDE: &str = include_str!("prelude.ml");
 
-    let tokens: Vec<Token> =
-        match record_pass_stats(&mut pass_stats, "tokenize", || tokenize(expr_str)) {
-            Err(err) => {
-                println!("Lexer error: {:#?}", err);
-                return None;
-            }
+fn parse_prelude(ctx: &mut ctx::Ctx) -> Vec<parser::Phrase> {
+    let tokens = tokenize(PRELUDE).unwrap();
+    parse(ctx, &tokens).unwrap()
+}
+
+// Passes before code generation. Returns the diagnostic of the first error in the program.
+// Warnings are added to `ctx`.
+fn front_end(
+    ctx: &mut ctx::Ctx, src: &str, pass_stats: &mut Vec<PassStats>,
+) -> Result<(Vec<lower::Fun>, ctx::VarId), Diagnostic> {
+    let tokens: Vec<Spanned<Token>> =
+        match record_pass_stats(pass_stats, "tokenize", || tokenize(src)) {
+            Err(err) => return Err(Diagnostic::from_lex_err(&err)),
             Ok(tokens) => tokens,
         };
 
     // println!("{:#?}", tokens);
 
-    let mut ctx = Default::default();
+    let mut pgm = parse_prelude(ctx);
 
-    let mut expr = match record_pass_stats(&mut pass_stats, "parse", || parse(&mut ctx, &tokens)) {
-        Err(err) => {
-            println!("Parser error: {:#?}", err);
-            return None;
-        }
-        Ok(expr) => expr,
+    match record_pass_stats(pass_stats, "parse", || parse(ctx, &tokens)) {
+        Err(err) => return Err(Diagnostic::from_parse_err(&err)),
+        Ok(phrases) => pgm.extend(phrases),
     };
 
-    // println!("Expr: {:#?}", expr);
+    // println!("Program: {:#?}", pgm);
 
-    match record_pass_stats(&mut pass_stats, "type check", || {
-        type_check_pgm(&mut ctx, &mut expr)
-    }) {
-        Err(err) => {
-            println!("Type error: {:#?}", err);
-            return None;
-        }
-        Ok(()) => {}
+    if let Err(err) = record_pass_stats(pass_stats, "type check", || type_check_pgm(ctx, &mut pgm))
+    {
+        return Err(Diagnostic::from_type_err(ctx, &err));
     };
 
-    // println!("Type-checked expr: {:#?}", expr);
+    // println!("Type-checked program: {:#?}", pgm);
 
-    let expr = record_pass_stats(&mut pass_stats, "anormal", || anormal(&mut ctx, expr));
+    let pgm = record_pass_stats(pass_stats, "monomorphize", || monomorph_pgm(ctx, &pgm));
+
+    let pgm = record_pass_stats(pass_stats, "anormal", || anormal_pgm(ctx, pgm));
 
     // println!("K normalized:");
-    // println!("{:?}", expr);
+    // println!("{:?}", pgm);
 
-    let (funs, main) = record_pass_stats(&mut pass_stats, "closure convert", || {
-        lower_pgm(&mut ctx, expr)
-    });
+    Ok(record_pass_stats(pass_stats, "closure convert", || lower_pgm(ctx, pgm)))
+}
+
+fn compile_expr(
+    file: &str, expr_str: &str, dump_cc: bool, dump_cg: bool, show_pass_stats: bool, color: bool,
+) -> Option<ObjectCode> {
+    let mut pass_stats: Vec<PassStats> = Vec::with_capacity(10);
+
+    let mut ctx: ctx::Ctx = Default::default();
+    ctx.set_source(file, expr_str);
+
+    let ret = front_end(&mut ctx, expr_str, &mut pass_stats);
+
+    for warning in ctx.take_warnings() {
+        print!("{}", warning.render(file, expr_str, color));
+    }
+
+    let (funs, main) = match ret {
+        Err(diag) => {
+            print!("{}", diag.render(file, expr_str, color));
+            return None;
+        }
+        Ok(lowered) => lowered,
+    };
 
     if dump_cc {
         println!("### Closure conversion:\n");
@@ -158,11 +189,13 @@ fn report_pass_stats(pass_stats: &[PassStats]) {
     println!("--------------------------------------------------------");
 }
 
+/// `color`: use ANSI colors when printing errors
 pub fn compile_file(
     path: &str, out_dir: Option<&str>, dump_cc: bool, dump_cg: bool, show_pass_stats: bool,
+    color: bool,
 ) -> i32 {
     let contents = std::fs::read_to_string(path).unwrap();
-    match compile_expr(&contents, dump_cc, dump_cg, show_pass_stats) {
+    match compile_expr(path, &contents, dump_cc, dump_cg, show_pass_stats, color) {
         None => 1,
         Some(object_code) => link(path, out_dir, object_code),
     }
@@ -174,14 +207,14 @@ fn link(path: &str, out_dir: Option<&str>, object_code: ObjectCode) -> i32 {
     let file_stem = path.file_stem().unwrap().to_str().unwrap();
     let o_file_name = format!("{}.o", file_stem);
 
-    File::create(&format!("{}/{}", out_dir, o_file_name))
+    File::create(format!("{}/{}", out_dir, o_file_name))
         .unwrap()
         .write_all(&object_code)
         .unwrap();
 
     // Build RTS
     let output = Command::new("gcc")
-        .args(&["rts.c", "-c", "-o", &format!("{}/rts.o", out_dir)])
+        .args(["rts.c", "-c", "-o", &format!("{}/rts.o", out_dir)])
         .spawn()
         .unwrap()
         .wait_with_output()
@@ -191,7 +224,7 @@ fn link(path: &str, out_dir: Option<&str>, object_code: ObjectCode) -> i32 {
 
     // Link
     let output = Command::new("gcc")
-        .args(&[
+        .args([
             &o_file_name,
             "rts.o",
             "-o",
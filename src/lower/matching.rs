@@ -0,0 +1,772 @@
+// Compilation of `match` expressions to decision trees
+
+use super::types::*;
+use super::{cc_block, BlockBuilder, CcCtx, Sequel};
+
+use crate::anormal;
+use crate::cg_types::RepType;
+use crate::common::Cmp;
+use crate::ctx::{Ctx, VarId};
+use crate::diagnostics::Diagnostic;
+use crate::parser::Pat;
+use crate::span::{self, Span};
+use crate::type_check::Type;
+use crate::var::CompilerPhase::ClosureConvert;
+
+use fxhash::FxHashMap;
+use std::fmt::Write;
+
+// A row of the pattern matrix. Columns are the values being matched (occurrences).
+#[derive(Clone)]
+struct Row {
+    pats: Vec<Pat>,
+    // Pattern variables of the columns removed so far, and the occurrences they are bound to
+    bndrs: Vec<(VarId, VarId)>,
+    // Index of the case this row comes from
+    case: usize,
+}
+
+// What we know about an occurrence in a node of the decision tree. Used to show an example of an
+// unmatched value when the match is not exhaustive.
+enum Fact {
+    // Occurrence is a tuple, fields are in these occurrences
+    Tuple(Vec<VarId>),
+    // Occurrence is this int or bool
+    Is(i64),
+    // Occurrence is none of these ints or bools
+    IsNot(Vec<i64>),
+    // Occurrence is this constructor, arguments are in these occurrences
+    Con(VarId, Vec<VarId>),
+    // Occurrence is none of these constructors
+    IsNotCon(Vec<VarId>),
+    // Occurrence is the empty list
+    Nil,
+    // Occurrence is a cons cell, head and tail are in these occurrences
+    Cons(VarId, VarId),
+}
+
+struct MatchState {
+    // The value being matched
+    scrut: VarId,
+    // Blocks of the case right-hand sides. Created when a leaf of the decision tree selects the
+    // case, so cases without a block are unused.
+    case_blocks: Vec<Option<BlockBuilder>>,
+    facts: FxHashMap<VarId, Fact>,
+    // An example of a value not matched by any of the cases
+    missing: Option<String>,
+    // Whether this is the handler of a `try`, which re-raises the exception when no case matches
+    handler: bool,
+    // Span of the `match`, for the location in `Match_failure`
+    span: Span,
+}
+
+impl MatchState {
+    fn case_block(&mut self, ctx: &mut CcCtx, case: usize) -> BlockIdx {
+        match &self.case_blocks[case] {
+            Some(block) => block.idx,
+            None => {
+                let block = ctx.create_block();
+                let idx = block.idx;
+                self.case_blocks[case] = Some(block);
+                idx
+            }
+        }
+    }
+}
+
+pub(super) fn cc_match(
+    ctx: &mut CcCtx, block: BlockBuilder, sequel: Sequel, scrut: VarId, cases: Vec<anormal::Case>,
+    span: Span, handler: bool,
+) {
+    let rows: Vec<Row> = cases
+        .iter()
+        .enumerate()
+        .map(|(case, anormal::Case { pat, .. })| Row {
+            pats: vec![pat.clone()],
+            bndrs: vec![],
+            case,
+        })
+        .collect();
+
+    let mut st = MatchState {
+        scrut,
+        case_blocks: cases.iter().map(|_| None).collect(),
+        facts: Default::default(),
+        missing: None,
+        handler,
+        span,
+    };
+
+    compile(ctx, &mut st, block, vec![scrut], rows);
+
+    if let Some(missing) = st.missing {
+        let mut warning = Diagnostic::warning(
+            "this pattern-matching is not exhaustive".to_owned(),
+            span,
+            String::new(),
+        );
+        warning.notes.push(format!(
+            "here is an example of a case that is not matched: `{}`",
+            missing
+        ));
+        ctx.ctx.add_warning(warning);
+    }
+
+    for (case, case_block) in cases.into_iter().zip(st.case_blocks) {
+        match case_block {
+            None => {
+                ctx.ctx.add_warning(Diagnostic::warning(
+                    "this match case is unused".to_owned(),
+                    case.span,
+                    String::new(),
+                ));
+            }
+            Some(case_block) => {
+                cc_block(ctx, case_block, sequel.clone(), case.rhs);
+            }
+        }
+    }
+}
+
+fn is_irrefutable(pat: &Pat) -> bool {
+    match pat {
+        Pat::Unit | Pat::Wildcard | Pat::Var(_) => true,
+        Pat::Bool(_)
+        | Pat::Int(_)
+        | Pat::Char(_)
+        | Pat::Tuple(_)
+        | Pat::Con { .. }
+        | Pat::Nil
+        | Pat::Cons(_, _) => false,
+    }
+}
+
+// Value of an int, bool, or char pattern
+fn pat_const(pat: &Pat) -> Option<i64> {
+    match pat {
+        Pat::Bool(b) => Some(if *b { 1 } else { 0 }),
+        Pat::Int(i) => Some(*i),
+        Pat::Char(c) => Some(i64::from(*c)),
+        Pat::Unit
+        | Pat::Wildcard
+        | Pat::Var(_)
+        | Pat::Tuple(_)
+        | Pat::Con { .. }
+        | Pat::Nil
+        | Pat::Cons(_, _) => None,
+    }
+}
+
+// Generate the decision tree for the pattern matrix `rows` in `block`. `occs` are the values being
+// matched by the columns.
+fn compile(
+    ctx: &mut CcCtx, st: &mut MatchState, mut block: BlockBuilder, mut occs: Vec<VarId>,
+    rows: Vec<Row>,
+) {
+    let first_row = match rows.first() {
+        None if st.handler => {
+            // No case matches, propagate the exception
+            ctx.finish_block_(Block {
+                idx: block.idx,
+                comment: block.comment,
+                stmts: block.stmts,
+                exit: Exit::Raise(st.scrut),
+            });
+            return;
+        }
+        None => {
+            // No case matches
+            if st.missing.is_none() {
+                let mut missing = String::new();
+                pp_missing(ctx.ctx, &st.facts, st.scrut, &mut missing);
+                st.missing = Some(missing);
+            }
+            // Location of the `match`, with OCaml's 0-based column as in `assert`
+            let loc = span::line_col(&ctx.ctx.source(), st.span.start);
+            let file = ctx.ctx.add_str_lit(ClosureConvert, &ctx.ctx.source_file());
+            ctx.finish_block_(Block {
+                idx: block.idx,
+                comment: block.comment,
+                stmts: block.stmts,
+                exit: Exit::MatchFailure {
+                    file,
+                    line: i64::from(loc.line),
+                    col: i64::from(loc.col) - 1,
+                },
+            });
+            return;
+        }
+        Some(row) => row,
+    };
+
+    let col = match first_row.pats.iter().position(|pat| !is_irrefutable(pat)) {
+        None => {
+            // First row matches, bind the variables and jump to the RHS
+            let Row {
+                pats,
+                mut bndrs,
+                case,
+            } = rows.into_iter().next().unwrap();
+            for (pat, occ) in pats.iter().zip(occs.iter()) {
+                if let Pat::Var(var) = pat {
+                    bndrs.push((*var, *occ));
+                }
+            }
+            for (var, occ) in bndrs {
+                block.asgn(var, Expr::Atom(Atom::Var(occ)));
+            }
+            let case_block = st.case_block(ctx, case);
+            ctx.finish_block_(Block {
+                idx: block.idx,
+                comment: block.comment,
+                stmts: block.stmts,
+                exit: Exit::Jump(case_block),
+            });
+            return;
+        }
+        Some(col) => col,
+    };
+
+    let occ = occs[col];
+
+    if let Pat::Tuple(_) = first_row.pats[col] {
+        // Read the fields and replace the tuple column with the field columns
+        let field_tys = match &*ctx.ctx.var_type(occ) {
+            Type::Tuple(field_tys) => field_tys.clone(),
+            other => panic!("Non-tuple type in tuple pattern: {:?}", other),
+        };
+        let mut fields: Vec<VarId> = Vec::with_capacity(field_tys.len());
+        for (field_idx, field_ty) in field_tys.into_iter().enumerate() {
+            let field = ctx.ctx.fresh_generated_var(ClosureConvert);
+            let field_ty_id = ctx.ctx.intern_type(field_ty);
+            ctx.ctx.set_var_type(field, field_ty_id);
+            block.asgn(field, Expr::TupleGet(occ, field_idx));
+            fields.push(field);
+        }
+
+        let rows: Vec<Row> = rows
+            .into_iter()
+            .map(|mut row| {
+                let field_pats = match row.pats[col].clone() {
+                    Pat::Tuple(pats) => pats,
+                    Pat::Var(var) => {
+                        row.bndrs.push((var, occ));
+                        vec![Pat::Wildcard; fields.len()]
+                    }
+                    Pat::Wildcard => vec![Pat::Wildcard; fields.len()],
+                    other => panic!("Non-tuple pattern in tuple column: {:?}", other),
+                };
+                row.pats.splice(col..col + 1, field_pats);
+                row
+            })
+            .collect();
+
+        occs.splice(col..col + 1, fields.iter().copied());
+        st.facts.insert(occ, Fact::Tuple(fields));
+        compile(ctx, st, block, occs, rows);
+        return;
+    }
+
+    if let Pat::Con { .. } = first_row.pats[col] {
+        compile_con(ctx, st, block, occs, rows, col);
+        return;
+    }
+
+    if let Pat::Nil | Pat::Cons(_, _) = first_row.pats[col] {
+        compile_list(ctx, st, block, occs, rows, col);
+        return;
+    }
+
+    // Test the constants in the column in the order they appear in the cases. Rows with an
+    // irrefutable pattern in the column are included in all branches.
+    let mut consts: Vec<i64> = vec![];
+    for row in &rows {
+        if let Some(c) = pat_const(&row.pats[col]) {
+            if !consts.contains(&c) {
+                consts.push(c);
+            }
+        }
+    }
+
+    // When both bools are tested we don't need a default branch
+    let complete = *ctx.ctx.var_type(occ) == Type::Bool && consts.len() == 2;
+
+    occs.remove(col);
+
+    for (const_idx, c) in consts.iter().enumerate() {
+        let const_rows = specialize(&rows, col, occ, Some(*c));
+
+        if complete && const_idx + 1 == consts.len() {
+            // Other value was tested before, no need to test this one
+            st.facts.insert(occ, Fact::Is(*c));
+            compile(ctx, st, block, occs, const_rows);
+            st.facts.remove(&occ);
+            return;
+        }
+
+        let then_block = ctx.create_block();
+        let else_block = ctx.create_block();
+
+        let const_var = ctx.fresh_var(RepType::Word);
+        block.asgn(const_var, Expr::Atom(Atom::Int(*c)));
+        ctx.finish_block_(Block {
+            idx: block.idx,
+            comment: block.comment,
+            stmts: block.stmts,
+            exit: Exit::Branch {
+                v1: occ,
+                v2: const_var,
+                cond: Cmp::Equal,
+                then_block: then_block.idx,
+                else_block: else_block.idx,
+            },
+        });
+
+        st.facts.insert(occ, Fact::Is(*c));
+        compile(ctx, st, then_block, occs.clone(), const_rows);
+
+        block = else_block;
+    }
+
+    let default_rows = specialize(&rows, col, occ, None);
+    st.facts.insert(occ, Fact::IsNot(consts));
+    compile(ctx, st, block, occs, default_rows);
+    st.facts.remove(&occ);
+}
+
+// Test the constructor of the value in column `col`. Values of constant constructors are their
+// tags, which are smaller than the number of constant constructors. Other values are pointers to
+// tuples with the tag in field 0. Exceptions are all tuples, see `ConInfo`.
+fn compile_con(
+    ctx: &mut CcCtx, st: &mut MatchState, mut block: BlockBuilder, mut occs: Vec<VarId>,
+    rows: Vec<Row>, col: usize,
+) {
+    let occ = occs[col];
+    let ty_con = match &*ctx.ctx.var_type(occ) {
+        Type::Con(ty_con) => ty_con.clone(),
+        other => panic!("Non-constructor type in constructor pattern: {:?}", other),
+    };
+    let (consts, blocks): (Vec<VarId>, Vec<VarId>) = ctx
+        .ctx
+        .ty_con_cons(&ty_con)
+        .iter()
+        .partition(|con| is_const_con(ctx.ctx, **con));
+
+    // Constructors tested in the column, in the order they appear in the cases
+    let mut tested_consts: Vec<VarId> = vec![];
+    let mut tested_blocks: Vec<VarId> = vec![];
+    for row in &rows {
+        if let Pat::Con { con, .. } = &row.pats[col] {
+            let tested = if consts.contains(con) {
+                &mut tested_consts
+            } else {
+                &mut tested_blocks
+            };
+            if !tested.contains(con) {
+                tested.push(*con);
+            }
+        }
+    }
+
+    occs.remove(col);
+
+    if blocks.is_empty() {
+        compile_con_switch(ctx, st, block, &occs, &rows, col, occ, tested_consts, &consts, &[]);
+    } else if consts.is_empty() {
+        compile_con_switch(ctx, st, block, &occs, &rows, col, occ, tested_blocks, &blocks, &[]);
+    } else {
+        let const_block = ctx.create_block();
+        let block_block = ctx.create_block();
+        let n_consts = ctx.fresh_var(RepType::Word);
+        block.asgn(n_consts, Expr::Atom(Atom::Int(consts.len() as i64)));
+        ctx.finish_block_(Block {
+            idx: block.idx,
+            comment: block.comment,
+            stmts: block.stmts,
+            exit: Exit::Branch {
+                v1: occ,
+                v2: n_consts,
+                cond: Cmp::LessThan,
+                then_block: const_block.idx,
+                else_block: block_block.idx,
+            },
+        });
+        compile_con_switch(
+            ctx,
+            st,
+            const_block,
+            &occs,
+            &rows,
+            col,
+            occ,
+            tested_consts,
+            &consts,
+            &blocks,
+        );
+        compile_con_switch(
+            ctx,
+            st,
+            block_block,
+            &occs,
+            &rows,
+            col,
+            occ,
+            tested_blocks,
+            &blocks,
+            &consts,
+        );
+    }
+}
+
+// Test the constructors `tested` of `cons`, which are either all constant or all non-constant
+// constructors of the type. `others` are the constructors of the other kind, which are known to
+// not match. `occs` are the occurrences without `occ`.
+#[allow(clippy::too_many_arguments)]
+fn compile_con_switch(
+    ctx: &mut CcCtx, st: &mut MatchState, mut block: BlockBuilder, occs: &[VarId], rows: &[Row],
+    col: usize, occ: VarId, tested: Vec<VarId>, cons: &[VarId], others: &[VarId],
+) {
+    let is_block = !is_const_con(ctx.ctx, cons[0]);
+
+    // When all constructors are tested we don't need a default branch. New exception constructors
+    // can be declared after the match, so matches on exceptions always need one.
+    let is_exn = ctx.ctx.con_info(cons[0]).exn_name.is_some();
+    let complete = tested.len() == cons.len() && !is_exn;
+
+    // Tag of a constant constructor is the value itself
+    let mut tag = occ;
+    if is_block && !tested.is_empty() && !(complete && tested.len() == 1) {
+        let view = view_var(ctx, &mut block, occ, vec![Type::Int]);
+        tag = ctx.fresh_var(RepType::Word);
+        block.asgn(tag, Expr::TupleGet(view, 0));
+    }
+
+    for (con_idx, con) in tested.iter().enumerate() {
+        let info = ctx.ctx.con_info(*con).clone();
+
+        // Other constructors were tested before, no need to test this one
+        let last = complete && con_idx + 1 == tested.len();
+
+        let (mut con_block, else_block) = if last {
+            (block, None)
+        } else {
+            let then_block = ctx.create_block();
+            let else_block = ctx.create_block();
+            let tag_var = ctx.fresh_var(RepType::Word);
+            let tag_atom = match info.exn_name {
+                None => Atom::Int(info.tag),
+                Some(exn_name) => Atom::Var(exn_name),
+            };
+            block.asgn(tag_var, Expr::Atom(tag_atom));
+            ctx.finish_block_(Block {
+                idx: block.idx,
+                comment: block.comment,
+                stmts: block.stmts,
+                exit: Exit::Branch {
+                    v1: tag,
+                    v2: tag_var,
+                    cond: Cmp::Equal,
+                    then_block: then_block.idx,
+                    else_block: else_block.idx,
+                },
+            });
+            (then_block, Some(else_block))
+        };
+
+        // Read the arguments and add them as columns
+        let mut fields: Vec<VarId> = Vec::with_capacity(info.args.len());
+        if !info.args.is_empty() {
+            let mut view_tys: Vec<Type> = Vec::with_capacity(info.args.len() + 1);
+            view_tys.push(Type::Int);
+            view_tys.extend(info.args.iter().cloned());
+            let view = view_var(ctx, &mut con_block, occ, view_tys);
+            for (arg_idx, arg_ty) in info.args.into_iter().enumerate() {
+                let field = ctx.ctx.fresh_generated_var(ClosureConvert);
+                let field_ty_id = ctx.ctx.intern_type(arg_ty);
+                ctx.ctx.set_var_type(field, field_ty_id);
+                con_block.asgn(field, Expr::TupleGet(view, arg_idx + 1));
+                fields.push(field);
+            }
+        }
+
+        let con_rows = specialize_con(rows, col, occ, *con, fields.len());
+        let mut con_occs = occs.to_vec();
+        con_occs.splice(col..col, fields.iter().copied());
+        st.facts.insert(occ, Fact::Con(*con, fields));
+        compile(ctx, st, con_block, con_occs, con_rows);
+
+        match else_block {
+            None => {
+                st.facts.remove(&occ);
+                return;
+            }
+            Some(else_block) => {
+                block = else_block;
+            }
+        }
+    }
+
+    let default_rows = specialize(rows, col, occ, None);
+    let mut not_cons = tested;
+    not_cons.extend_from_slice(others);
+    st.facts.insert(occ, Fact::IsNotCon(not_cons));
+    compile(ctx, st, block, occs.to_vec(), default_rows);
+    st.facts.remove(&occ);
+}
+
+// Test whether the list in column `col` is empty. The empty list is 0, cons cells are pointers to
+// tuples of the head and the tail.
+fn compile_list(
+    ctx: &mut CcCtx, st: &mut MatchState, mut block: BlockBuilder, mut occs: Vec<VarId>,
+    rows: Vec<Row>, col: usize,
+) {
+    let occ = occs[col];
+    let elem_ty = match &*ctx.ctx.var_type(occ) {
+        Type::List(elem_ty) => (**elem_ty).clone(),
+        other => panic!("Non-list type in list pattern: {:?}", other),
+    };
+
+    let nil_block = ctx.create_block();
+    let mut cons_block = ctx.create_block();
+    let nil_var = ctx.fresh_var(RepType::Word);
+    block.asgn(nil_var, Expr::Atom(Atom::Int(0)));
+    ctx.finish_block_(Block {
+        idx: block.idx,
+        comment: block.comment,
+        stmts: block.stmts,
+        exit: Exit::Branch {
+            v1: occ,
+            v2: nil_var,
+            cond: Cmp::Equal,
+            then_block: nil_block.idx,
+            else_block: cons_block.idx,
+        },
+    });
+
+    occs.remove(col);
+
+    let nil_rows = specialize_list(&rows, col, occ, false);
+    st.facts.insert(occ, Fact::Nil);
+    compile(ctx, st, nil_block, occs.clone(), nil_rows);
+
+    // Read the head and the tail and add them as columns
+    let list_ty = Type::List(Box::new(elem_ty.clone()));
+    let view = view_var(ctx, &mut cons_block, occ, vec![elem_ty.clone(), list_ty.clone()]);
+    let mut fields: Vec<VarId> = Vec::with_capacity(2);
+    for (field_idx, field_ty) in vec![elem_ty, list_ty].into_iter().enumerate() {
+        let field = ctx.ctx.fresh_generated_var(ClosureConvert);
+        let field_ty_id = ctx.ctx.intern_type(field_ty);
+        ctx.ctx.set_var_type(field, field_ty_id);
+        cons_block.asgn(field, Expr::TupleGet(view, field_idx));
+        fields.push(field);
+    }
+
+    let cons_rows = specialize_list(&rows, col, occ, true);
+    occs.splice(col..col, fields.iter().copied());
+    st.facts.insert(occ, Fact::Cons(fields[0], fields[1]));
+    compile(ctx, st, cons_block, occs, cons_rows);
+    st.facts.remove(&occ);
+}
+
+// Whether values of the constructor are represented as tags
+fn is_const_con(ctx: &Ctx, con: VarId) -> bool {
+    let info = ctx.con_info(con);
+    info.args.is_empty() && info.exn_name.is_none()
+}
+
+// A variable with the value of `occ` and a tuple type, to read fields of a constructor value
+fn view_var(ctx: &mut CcCtx, block: &mut BlockBuilder, occ: VarId, tys: Vec<Type>) -> VarId {
+    let view = ctx.ctx.fresh_generated_var(ClosureConvert);
+    let ty_id = ctx.ctx.intern_type(Type::Tuple(tys));
+    ctx.ctx.set_var_type(view, ty_id);
+    block.asgn(view, Expr::Atom(Atom::Var(occ)));
+    view
+}
+
+// Rows that match when the value in column `col` is constructor `con` with `n_args` arguments. The
+// column is replaced with the argument columns in the returned rows.
+fn specialize_con(rows: &[Row], col: usize, occ: VarId, con: VarId, n_args: usize) -> Vec<Row> {
+    let mut ret: Vec<Row> = Vec::with_capacity(rows.len());
+    for row in rows {
+        let mut row = row.clone();
+        let arg_pats = match row.pats.remove(col) {
+            Pat::Con { con: con_, arg } => {
+                if con_ != con {
+                    continue;
+                }
+                match arg {
+                    None => vec![],
+                    Some(arg) if n_args > 1 => match *arg {
+                        Pat::Tuple(pats) => pats,
+                        other => panic!("Non-tuple pattern for constructor arguments: {:?}", other),
+                    },
+                    Some(arg) => vec![*arg],
+                }
+            }
+            Pat::Wildcard => vec![Pat::Wildcard; n_args],
+            Pat::Var(var) => {
+                row.bndrs.push((var, occ));
+                vec![Pat::Wildcard; n_args]
+            }
+            other => panic!("Non-constructor pattern in constructor column: {:?}", other),
+        };
+        row.pats.splice(col..col, arg_pats);
+        ret.push(row);
+    }
+    ret
+}
+
+// Rows that match when the list in column `col` is a cons cell (`cons`) or the empty list. For cons
+// cells the column is replaced with the head and tail columns, otherwise it's removed.
+fn specialize_list(rows: &[Row], col: usize, occ: VarId, cons: bool) -> Vec<Row> {
+    let n_fields = if cons { 2 } else { 0 };
+    let mut ret: Vec<Row> = Vec::with_capacity(rows.len());
+    for row in rows {
+        let mut row = row.clone();
+        let field_pats = match row.pats.remove(col) {
+            Pat::Nil if !cons => vec![],
+            Pat::Cons(hd, tl) if cons => vec![*hd, *tl],
+            Pat::Nil | Pat::Cons(_, _) => continue,
+            Pat::Wildcard => vec![Pat::Wildcard; n_fields],
+            Pat::Var(var) => {
+                row.bndrs.push((var, occ));
+                vec![Pat::Wildcard; n_fields]
+            }
+            other => panic!("Non-list pattern in list column: {:?}", other),
+        };
+        row.pats.splice(col..col, field_pats);
+        ret.push(row);
+    }
+    ret
+}
+
+// Rows that match when the value in column `col` is `c`, or any value not tested in the column
+// when `c` is `None`. The column is removed from the returned rows.
+fn specialize(rows: &[Row], col: usize, occ: VarId, c: Option<i64>) -> Vec<Row> {
+    let mut ret: Vec<Row> = Vec::with_capacity(rows.len());
+    for row in rows {
+        let mut row = row.clone();
+        let pat = row.pats.remove(col);
+        match pat {
+            Pat::Wildcard => {}
+            Pat::Var(var) => {
+                row.bndrs.push((var, occ));
+            }
+            _ => {
+                if c.is_none() || pat_const(&pat) != c {
+                    continue;
+                }
+            }
+        }
+        ret.push(row);
+    }
+    ret
+}
+
+// Print an example value of the occurrence, in pattern syntax, using the facts
+fn pp_missing(ctx: &Ctx, facts: &FxHashMap<VarId, Fact>, occ: VarId, w: &mut String) {
+    let ty = ctx.var_type(occ);
+    match facts.get(&occ) {
+        None => w.push('_'),
+        Some(Fact::Tuple(fields)) => {
+            w.push('(');
+            for (field_idx, field) in fields.iter().enumerate() {
+                if field_idx != 0 {
+                    w.push_str(", ");
+                }
+                pp_missing(ctx, facts, *field, w);
+            }
+            w.push(')');
+        }
+        Some(Fact::Is(c)) => pp_const(&ty, *c, w),
+        Some(Fact::IsNot(cs)) => {
+            // Smallest non-negative value not tested, or for chars the first one from 'a'. For
+            // bools there has to be one, as we don't generate a default branch when both are
+            // tested.
+            let from = if *ty == Type::Char { i64::from(b'a') } else { 0 };
+            let c = (from..256).chain(0..).find(|c| !cs.contains(c)).unwrap();
+            pp_const(&ty, c, w);
+        }
+        Some(Fact::Con(con, fields)) => {
+            w.push_str(&ctx.var_name(*con));
+            match fields.len() {
+                0 => {}
+                1 => {
+                    let mut arg = String::new();
+                    pp_missing(ctx, facts, fields[0], &mut arg);
+                    // Constructor applications and negative numbers need parens
+                    if !arg.starts_with('(') && (arg.contains(' ') || arg.starts_with('-')) {
+                        write!(w, " ({})", arg).unwrap();
+                    } else {
+                        write!(w, " {}", arg).unwrap();
+                    }
+                }
+                _ => {
+                    w.push_str(" (");
+                    for (field_idx, field) in fields.iter().enumerate() {
+                        if field_idx != 0 {
+                            w.push_str(", ");
+                        }
+                        pp_missing(ctx, facts, *field, w);
+                    }
+                    w.push(')');
+                }
+            }
+        }
+        Some(Fact::IsNotCon(cons)) => {
+            // A constructor not tested. There has to be one, as we don't generate a default
+            // branch when all constructors are tested, except for exceptions.
+            let ty_con = match &*ctx.var_type(occ) {
+                Type::Con(ty_con) => ty_con.clone(),
+                other => panic!("Non-constructor type in constructor pattern: {:?}", other),
+            };
+            let con = match ctx.ty_con_cons(&ty_con).iter().find(|con| !cons.contains(con)) {
+                Some(con) => *con,
+                None => {
+                    w.push('_');
+                    return;
+                }
+            };
+            w.push_str(&ctx.var_name(con));
+            match ctx.con_info(con).args.len() {
+                0 => {}
+                1 => w.push_str(" _"),
+                n_args => {
+                    w.push_str(" (_");
+                    for _ in 1..n_args {
+                        w.push_str(", _");
+                    }
+                    w.push(')');
+                }
+            }
+        }
+        Some(Fact::Nil) => w.push_str("[]"),
+        Some(Fact::Cons(hd, tl)) => {
+            let mut hd_str = String::new();
+            pp_missing(ctx, facts, *hd, &mut hd_str);
+            // `::` is right associative, heads that are lists need parens
+            if !hd_str.starts_with('(') && hd_str.contains(" :: ") {
+                write!(w, "({})", hd_str).unwrap();
+            } else {
+                w.push_str(&hd_str);
+            }
+            w.push_str(" :: ");
+            pp_missing(ctx, facts, *tl, w);
+        }
+    }
+}
+
+fn pp_const(ty: &Type, c: i64, w: &mut String) {
+    match ty {
+        Type::Bool => w.push_str(if c == 0 { "false" } else { "true" }),
+        Type::Char => {
+            let c = c as u8;
+            if (c.is_ascii_graphic() && c != b'\'' && c != b'\\') || c == b' ' {
+                write!(w, "'{}'", char::from(c)).unwrap();
+            } else {
+                write!(w, "'\\{:03}'", c).unwrap();
+            }
+        }
+        _ => write!(w, "{}", c).unwrap(),
+    }
+}
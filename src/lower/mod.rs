@@ -1,14 +1,15 @@
+mod matching;
 mod print;
 mod types;
 
 use crate::anormal;
 use crate::cg_types::RepType;
-use crate::common::{BinOp, Cmp, IntBinOp};
+use crate::common::{BinOp, Cmp, ForDir, IntBinOp};
 use crate::ctx::{Ctx, VarId};
+use crate::parser::Pat;
 use crate::type_check::Type;
 use crate::var::CompilerPhase::ClosureConvert;
 
-pub use print::*;
 pub use types::*;
 
 use cranelift_entity::PrimaryMap;
@@ -178,12 +179,34 @@ impl<'ctx> CcCtx<'ctx> {
     }
 }
 
-pub fn lower_pgm(ctx: &mut Ctx, expr: anormal::Expr) -> (Vec<Fun>, VarId) {
+pub fn lower_pgm(ctx: &mut Ctx, pgm: Vec<anormal::Phrase>) -> (Vec<Fun>, VarId) {
     let mut ctx = CcCtx::new(ctx);
 
     let main_name = ctx.fresh_var(RepType::Word);
-    let main_block = ctx.create_block();
-    cc_block(&mut ctx, main_block, Sequel::Return, expr);
+
+    // Phrases are evaluated in order in main
+    let mut block = ctx.create_block();
+    for phrase in pgm {
+        match phrase {
+            anormal::Phrase::Let { id, rhs } => {
+                let cont_block = ctx.create_block();
+                let cont_idx = cont_block.idx;
+                cc_block(&mut ctx, block, Sequel::Asgn(id, cont_idx), rhs);
+                block = cont_block;
+            }
+            anormal::Phrase::LetRec(funs) => {
+                cc_letrec(&mut ctx, &mut block, funs);
+            }
+            anormal::Phrase::Expr(expr) => {
+                let cont_block = ctx.create_block();
+                let cont_idx = cont_block.idx;
+                let tmp = ctx.fresh_var(RepType::Word);
+                cc_block(&mut ctx, block, Sequel::Asgn(tmp, cont_idx), expr);
+                block = cont_block;
+            }
+        }
+    }
+    ctx.finish_block(block, Sequel::Return, Atom::Unit);
 
     ctx.funs.push(Fun {
         name: main_name,
@@ -216,6 +239,12 @@ fn cc_block(ctx: &mut CcCtx, mut block: BlockBuilder, sequel: Sequel, expr: anor
             ctx.finish_block(block, sequel, Atom::Var(tmp));
         }
 
+        anormal::Expr::StructCmp(kind, arg1, arg2) => {
+            let tmp = ctx.fresh_var(RepType::Word);
+            block.asgn(tmp, Expr::StructCmp(kind, arg1, arg2));
+            ctx.finish_block(block, sequel, Atom::Var(tmp));
+        }
+
         anormal::Expr::IBinOp(BinOp { op, arg1, arg2 }) => {
             let tmp = sequel.get_ret_var(ctx, RepType::Word);
             block.asgn(tmp, Expr::IBinOp(BinOp { op, arg1, arg2 }));
@@ -265,72 +294,8 @@ fn cc_block(ctx: &mut CcCtx, mut block: BlockBuilder, sequel: Sequel, expr: anor
             cc_block(ctx, cont_block, sequel, *body)
         }
 
-        anormal::Expr::LetRec {
-            name,
-            ty_id,
-            mut args,
-            rhs,
-            body,
-        } => {
-            // TODO: Not sure about reusing 'name' in multiple places below.
-
-            // After cc 'name' will refer to the closure tuple. For the function we'll need a fresh
-            // variable.
-            let fun_var = ctx.fresh_var(RepType::Word);
-
-            // Free variables of the closure will be moved to tuple payload
-            // NOTE: An inefficiency here is that if we have deeply nested letrecs we'll be
-            // computing fvs of nested letrecs when computing the outer ones. One solution could be
-            // to annotate LetRecs with fvs. Doesn't matter in practice though.
-            let closure_fvs: Vec<VarId> = {
-                let mut closure_fvs: FxHashSet<VarId> = Default::default();
-                fvs(ctx.ctx, &*rhs, &mut closure_fvs);
-                closure_fvs.remove(&name);
-                for arg in &args {
-                    closure_fvs.remove(arg);
-                }
-                closure_fvs.into_iter().collect()
-            };
-
-            // In the RHS and the body, 'name' will refer to the tuple. However in the RHS the
-            // tuple will be the first argument of the function, in the body we'll allocate a
-            // tuple.
-
-            // Emit function
-            args.insert(0, name); // first argument will be 'self'
-            ctx.fork_fun(|ctx| {
-                let mut entry_block = ctx.create_block();
-                // Bind captured variables in function body
-                for (fv_idx, fv) in closure_fvs.iter().enumerate() {
-                    entry_block.asgn(*fv, Expr::TupleGet(name, fv_idx + 1));
-                }
-                cc_block(ctx, entry_block, Sequel::Return, *rhs);
-
-                let fun_type = ctx.ctx.get_type(ty_id);
-                let fun_return_type = match &*fun_type {
-                    Type::Fun { ret, .. } => RepType::from(&**ret),
-                    _ => panic!("Non-function in function position"),
-                };
-
-                FunSig {
-                    name: fun_var,
-                    args,
-                    return_type: fun_return_type,
-                }
-            });
-
-            // Body
-            let mut closure_tuple_args = closure_fvs;
-            closure_tuple_args.insert(0, fun_var);
-            block.asgn(
-                name,
-                Expr::Tuple {
-                    len: closure_tuple_args.len(),
-                },
-            );
-            for (arg_idx, arg) in closure_tuple_args.iter().enumerate() {
-                block.expr(Expr::TuplePut(name, arg_idx, *arg));
-            }
+        anormal::Expr::LetRec { funs, body } => {
+            cc_letrec(ctx, &mut block, funs);
             cc_block(ctx, block, sequel, *body)
         }
 
@@ -362,6 +327,13 @@ fn cc_block(ctx: &mut CcCtx, mut block: BlockBuilder, sequel: Sequel, expr: anor
         anormal::Expr::TupleGet(tuple, idx) => {
             let elem_ty = match &*ctx.ctx.var_type(tuple) {
                 Type::Tuple(args) => RepType::from(&args[idx]),
+                // Records
+                Type::Con(ty_con) => {
+                    let field = ctx.ctx.record_fields(ty_con)[idx];
+                    RepType::from(&ctx.ctx.field_info(field).ty)
+                }
+                // References
+                Type::Ref(ty) => RepType::from(&**ty),
                 other => panic!(
                     "Non-tuple type in tuple position: {:?} (type={:?})",
                     tuple, other
@@ -372,6 +344,11 @@ fn cc_block(ctx: &mut CcCtx, mut block: BlockBuilder, sequel: Sequel, expr: anor
             ctx.finish_block(block, sequel, Atom::Var(ret_tmp));
         }
 
+        anormal::Expr::TuplePut(tuple, idx, val) => {
+            block.expr(Expr::TuplePut(tuple, idx, val));
+            ctx.finish_block(block, sequel, Atom::Unit);
+        }
+
         anormal::Expr::ArrayAlloc { len, elem } => {
             let array_tmp = sequel.get_ret_var(ctx, RepType::Word);
             block.asgn(array_tmp, Expr::ArrayAlloc { len });
@@ -426,6 +403,125 @@ fn cc_block(ctx: &mut CcCtx, mut block: BlockBuilder, sequel: Sequel, expr: anor
             ctx.finish_block(cont_block, sequel, Atom::Var(array_tmp));
         }
 
+        anormal::Expr::For {
+            var,
+            from,
+            to,
+            dir,
+            body,
+        } => {
+            block.asgn(var, Expr::Atom(Atom::Var(from)));
+
+            let mut loop_body_block = ctx.create_block();
+            let loop_body_idx = loop_body_block.idx;
+            let loop_test_block = ctx.create_block();
+            let mut loop_next_block = ctx.create_block();
+            let cont_block = ctx.create_block();
+
+            let (skip_cond, step_op) = match dir {
+                ForDir::Up => (Cmp::GreaterThan, IntBinOp::Add),
+                ForDir::Down => (Cmp::LessThan, IntBinOp::Sub),
+            };
+
+            // `from` and `to` are compared once before the loop. After the body the loop exits
+            // when `var = to`, instead of stepping and comparing, so that stepping never
+            // overflows when `to` is `max_int` (or `min_int` with `downto`).
+            ctx.finish_block_(Block {
+                idx: block.idx,
+                comment: block.comment,
+                stmts: block.stmts,
+                exit: Exit::Branch {
+                    v1: var,
+                    v2: to,
+                    cond: skip_cond,
+                    then_block: cont_block.idx,
+                    else_block: loop_body_idx,
+                },
+            });
+
+            // loop_body, value of the body is unit
+            loop_body_block.comment = Some("for body".to_string());
+            let body_tmp = ctx.fresh_var(RepType::Word);
+            cc_block(ctx, loop_body_block, Sequel::Asgn(body_tmp, loop_test_block.idx), *body);
+
+            // loop_test
+            ctx.finish_block_(Block {
+                idx: loop_test_block.idx,
+                comment: Some("for test".to_string()),
+                stmts: vec![],
+                exit: Exit::Branch {
+                    v1: var,
+                    v2: to,
+                    cond: Cmp::Equal,
+                    then_block: cont_block.idx,
+                    else_block: loop_next_block.idx,
+                },
+            });
+
+            // loop_next
+            let step_var = ctx.fresh_var(RepType::Word);
+            loop_next_block.asgn(step_var, Expr::Atom(Atom::Int(1)));
+            loop_next_block.asgn(
+                var,
+                Expr::IBinOp(BinOp {
+                    op: step_op,
+                    arg1: var,
+                    arg2: step_var,
+                }),
+            );
+            ctx.finish_block_(Block {
+                idx: loop_next_block.idx,
+                comment: Some("for next".to_string()),
+                stmts: loop_next_block.stmts,
+                exit: Exit::Jump(loop_body_idx),
+            });
+
+            ctx.finish_block(cont_block, sequel, Atom::Unit);
+        }
+
+        anormal::Expr::While { cond, body } => {
+            let mut loop_cond_block = ctx.create_block();
+            let mut loop_test_block = ctx.create_block();
+            let mut loop_body_block = ctx.create_block();
+            let cont_block = ctx.create_block();
+
+            ctx.finish_block_(Block {
+                idx: block.idx,
+                comment: block.comment,
+                stmts: block.stmts,
+                exit: Exit::Jump(loop_cond_block.idx),
+            });
+
+            // loop_cond, evaluated in each iteration
+            loop_cond_block.comment = Some("while cond".to_string());
+            let loop_cond_idx = loop_cond_block.idx;
+            let cond_var = ctx.fresh_var(RepType::Word);
+            cc_block(ctx, loop_cond_block, Sequel::Asgn(cond_var, loop_test_block.idx), *cond);
+
+            // loop_test
+            let true_var = ctx.fresh_var(RepType::Word);
+            loop_test_block.asgn(true_var, Expr::Atom(Atom::Int(1)));
+            ctx.finish_block_(Block {
+                idx: loop_test_block.idx,
+                comment: None,
+                stmts: loop_test_block.stmts,
+                exit: Exit::Branch {
+                    v1: cond_var,
+                    v2: true_var,
+                    cond: Cmp::Equal,
+                    then_block: loop_body_block.idx,
+                    else_block: cont_block.idx,
+                },
+            });
+
+            // loop_body, value of the body is unit
+            loop_body_block.comment = Some("while body".to_string());
+            let body_tmp = ctx.fresh_var(RepType::Word);
+            cc_block(ctx, loop_body_block, Sequel::Asgn(body_tmp, loop_cond_idx), *body);
+
+            ctx.finish_block(cont_block, sequel, Atom::Unit);
+        }
+
         anormal::Expr::ArrayGet(array, idx) => {
             let elem_ty = match &*ctx.ctx.var_type(array) {
                 Type::Array(elem_ty) => RepType::from(&**elem_ty),
@@ -451,6 +547,134 @@ fn cc_block(ctx: &mut CcCtx, mut block: BlockBuilder, sequel: Sequel, expr: anor
             block.asgn(ret_tmp, Expr::ArrayPut(array, idx, val));
             ctx.finish_block(block, sequel, Atom::Var(ret_tmp));
         }
+
+        anormal::Expr::Match {
+            scrut,
+            cases,
+            span,
+            handler,
+        } => {
+            matching::cc_match(ctx, block, sequel, scrut, cases, span, handler);
+        }
+
+        anormal::Expr::Raise(exn) => {
+            // The continuation is unreachable, but it may use the variable the value would be
+            // assigned to. Assign a dummy value so that the variable is declared in codegen.
+            if let Sequel::Asgn(var, _) = sequel {
+                let dummy = match ctx.ctx.var_rep_type(var) {
+                    RepType::Word => Atom::Int(0),
+                    RepType::Float => Atom::Float(0.0),
+                };
+                block.asgn(var, Expr::Atom(dummy));
+            }
+            ctx.finish_block_(Block {
+                idx: block.idx,
+                comment: block.comment,
+                stmts: block.stmts,
+                exit: Exit::Raise(exn),
+            });
+        }
+    }
+}
+
+// Emit functions of a `let rec` group, and allocate and initialize their closures in `block`
+fn cc_letrec(ctx: &mut CcCtx, block: &mut BlockBuilder, funs: Vec<anormal::FunDef>) {
+    // TODO: Not sure about reusing 'name' in multiple places below.
+
+    // Free variables of each closure will be moved to tuple payload. Other functions in
+    // the group are free variables too, so closures of the group refer to each other
+    // via their payloads.
+    // NOTE: An inefficiency here is that if we have deeply nested letrecs we'll be
+    // computing fvs of nested letrecs when computing the outer ones. One solution could be
+    // to annotate LetRecs with fvs. Doesn't matter in practice though.
+    let funs_fvs: Vec<Vec<VarId>> = funs
+        .iter()
+        .map(|anormal::FunDef { name, args, rhs, .. }| {
+            let mut closure_fvs: FxHashSet<VarId> = Default::default();
+            fvs(ctx.ctx, rhs, &mut closure_fvs);
+            closure_fvs.remove(name);
+            for arg in args {
+                closure_fvs.remove(arg);
+            }
+            closure_fvs.into_iter().collect()
+        })
+        .collect();
+
+    // In the RHS and the body, 'name' will refer to the tuple. However in the RHS the
+    // tuple will be the first argument of the function, in the body we'll allocate a
+    // tuple.
+
+    // (name, function, free variables) for each closure
+    let mut closures: Vec<(VarId, VarId, Vec<VarId>)> = Vec::with_capacity(funs.len());
+
+    // Emit functions
+    for (
+        anormal::FunDef {
+            name,
+            ty_id,
+            mut args,
+            rhs,
+        },
+        closure_fvs,
+    ) in funs.into_iter().zip(funs_fvs)
+    {
+        // After cc 'name' will refer to the closure tuple. For the function we'll need a
+        // fresh variable.
+        let fun_var = ctx.fresh_var(RepType::Word);
+
+        args.insert(0, name); // first argument will be 'self'
+        ctx.fork_fun(|ctx| {
+            let mut entry_block = ctx.create_block();
+            // Bind captured variables in function body. 'name' has the function type, so read
+            // the payload via a variable of the closure tuple type to read the variables with
+            // their own types.
+            if !closure_fvs.is_empty() {
+                let mut closure_tys: Vec<Type> = Vec::with_capacity(closure_fvs.len() + 1);
+                closure_tys.push(Type::Int);
+                for fv in &closure_fvs {
+                    closure_tys.push((*ctx.ctx.var_type(*fv)).clone());
+                }
+                let closure_ty_id = ctx.ctx.intern_type(Type::Tuple(closure_tys));
+                let closure = ctx.ctx.fresh_generated_var(ClosureConvert);
+                ctx.ctx.set_var_type(closure, closure_ty_id);
+                entry_block.asgn(closure, Expr::Atom(Atom::Var(name)));
+                for (fv_idx, fv) in closure_fvs.iter().enumerate() {
+                    entry_block.asgn(*fv, Expr::TupleGet(closure, fv_idx + 1));
+                }
+            }
+            cc_block(ctx, entry_block, Sequel::Return, *rhs);
+
+            let fun_type = ctx.ctx.get_type(ty_id);
+            let fun_return_type = match &*fun_type {
+                Type::Fun { ret, .. } => RepType::from(&**ret),
+                _ => panic!("Non-function in function position"),
+            };
+
+            FunSig {
+                name: fun_var,
+                args,
+                return_type: fun_return_type,
+            }
+        });
+
+        closures.push((name, fun_var, closure_fvs));
+    }
+
+    // Body. Allocate all closures before initializing them, as they may refer to each
+    // other.
+    for (name, _, closure_fvs) in &closures {
+        block.asgn(
+            *name,
+            Expr::Tuple {
+                len: closure_fvs.len() + 1,
+            },
+        );
+    }
+    for (name, fun_var, closure_fvs) in closures {
+        block.expr(Expr::TuplePut(name, 0, fun_var));
+        for (fv_idx, fv) in closure_fvs.into_iter().enumerate() {
+            block.expr(Expr::TuplePut(name, fv_idx + 1, fv));
+        }
     }
 }
 
@@ -469,6 +693,10 @@ fn fvs(ctx: &Ctx, e: &anormal::Expr, acc: &mut FxHashSet<VarId>) {
         Neg(arg) | FNeg(arg) => {
             fv(ctx, *arg, acc);
         }
+        StructCmp(_, arg1, arg2) => {
+            fv(ctx, *arg1, acc);
+            fv(ctx, *arg2, acc);
+        }
         If(arg1, arg2, _, e1, e2) => {
             fv(ctx, *arg1, acc);
             fv(ctx, *arg2, acc);
@@ -488,18 +716,16 @@ fn fvs(ctx: &Ctx, e: &anormal::Expr, acc: &mut FxHashSet<VarId>) {
         Var(id) => {
             fv(ctx, *id, acc);
         }
-        LetRec {
-            name,
-            ty_id: _,
-            args,
-            rhs,
-            body,
-        } => {
-            fvs(ctx, rhs, acc);
+        LetRec { funs, body } => {
+            for anormal::FunDef { rhs, .. } in funs {
+                fvs(ctx, rhs, acc);
+            }
             fvs(ctx, body, acc);
-            acc.remove(name);
-            for arg in args {
-                acc.remove(arg);
+            for anormal::FunDef { name, args, .. } in funs {
+                acc.remove(name);
+                for arg in args {
+                    acc.remove(arg);
+                }
             }
         }
         App(fun, args) => {
@@ -516,6 +742,10 @@ fn fvs(ctx: &Ctx, e: &anormal::Expr, acc: &mut FxHashSet<VarId>) {
         TupleGet(arg, _) => {
             fv(ctx, *arg, acc);
         }
+        TuplePut(arg1, _, arg2) => {
+            fv(ctx, *arg1, acc);
+            fv(ctx, *arg2, acc);
+        }
         ArrayAlloc { len, elem } => {
             fv(ctx, *len, acc);
             fv(ctx, *elem, acc);
@@ -529,11 +759,60 @@ fn fvs(ctx: &Ctx, e: &anormal::Expr, acc: &mut FxHashSet<VarId>) {
             fv(ctx, *arg2, acc);
             fv(ctx, *arg3, acc);
         }
+        For {
+            var,
+            from,
+            to,
+            dir: _,
+            body,
+        } => {
+            fvs(ctx, body, acc);
+            acc.remove(var);
+            fv(ctx, *from, acc);
+            fv(ctx, *to, acc);
+        }
+        While { cond, body } => {
+            fvs(ctx, cond, acc);
+            fvs(ctx, body, acc);
+        }
+        Match { scrut, cases, .. } => {
+            fv(ctx, *scrut, acc);
+            for anormal::Case { pat, rhs, .. } in cases {
+                fvs(ctx, rhs, acc);
+                remove_pat_vars(pat, acc);
+            }
+        }
+        Raise(exn) => {
+            fv(ctx, *exn, acc);
+        }
+    }
+}
+
+fn remove_pat_vars(pat: &Pat, acc: &mut FxHashSet<VarId>) {
+    match pat {
+        Pat::Unit | Pat::Bool(_) | Pat::Int(_) | Pat::Char(_) | Pat::Nil | Pat::Wildcard => {}
+        Pat::Var(var) => {
+            acc.remove(var);
+        }
+        Pat::Cons(hd, tl) => {
+            remove_pat_vars(hd, acc);
+            remove_pat_vars(tl, acc);
+        }
+        Pat::Tuple(pats) => {
+            for pat in pats {
+                remove_pat_vars(pat, acc);
+            }
+        }
+        Pat::Con { arg, .. } => {
+            if let Some(arg) = arg {
+                remove_pat_vars(arg, acc);
+            }
+        }
     }
 }
 
 fn fv(ctx: &Ctx, var: VarId, acc: &mut FxHashSet<VarId>) {
-    if !ctx.is_builtin_var(var) {
+    if !ctx.is_builtin_var(var) && !ctx.is_global_var(var) && !ctx.is_str_lit_var(var) {
         acc.insert(var);
     }
 }
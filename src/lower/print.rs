@@ -98,6 +98,15 @@ impl Exit {
                 write!(w, " then {} else {}", then_block, else_block)
             }
             Jump(lbl) => write!(w, "jump {}", lbl),
+            MatchFailure { file, line, col } => {
+                w.write_str("match_failure ")?;
+                pp_id(ctx, *file, w)?;
+                write!(w, " {} {}", line, col)
+            }
+            Raise(exn) => {
+                w.write_str("raise ")?;
+                pp_id(ctx, *exn, w)
+            }
         }
     }
 }
@@ -139,8 +148,15 @@ impl Expr {
                 let op_str = match op {
                     IntBinOp::Add => " + ",
                     IntBinOp::Sub => " - ",
-                    // IntBinOp::Mul => " * ",
-                    // IntBinOp::Div => " / ",
+                    IntBinOp::Mul => " * ",
+                    IntBinOp::Div => " / ",
+                    IntBinOp::Mod => " mod ",
+                    IntBinOp::And => " land ",
+                    IntBinOp::Or => " lor ",
+                    IntBinOp::Xor => " lxor ",
+                    IntBinOp::Lsl => " lsl ",
+                    IntBinOp::Lsr => " lsr ",
+                    IntBinOp::Asr => " asr ",
                 };
                 write!(w, "{}", op_str)?;
                 pp_id(ctx, *arg2, w)
@@ -164,6 +180,17 @@ impl Expr {
                 w.write_str("-.")?;
                 pp_id(ctx, *var, w)
             }
+            StructCmp(kind, arg1, arg2) => {
+                let fun = match kind {
+                    crate::common::StructCmp::Equal => "equal",
+                    crate::common::StructCmp::Compare => "compare",
+                };
+                write!(w, "{}(", fun)?;
+                pp_id(ctx, *arg1, w)?;
+                w.write_str(", ")?;
+                pp_id(ctx, *arg2, w)?;
+                w.write_str(")")
+            }
             App(fun, args, _) => {
                 pp_id(ctx, *fun, w)?;
                 w.write_str("(")?;
@@ -1,5 +1,5 @@
 use crate::cg_types::RepType;
-use crate::common::{BinOp, Cmp, FloatBinOp, IntBinOp};
+use crate::common::{BinOp, Cmp, FloatBinOp, IntBinOp, StructCmp};
 use crate::ctx::VarId;
 
 use cranelift_entity::{entity_impl, PrimaryMap};
@@ -70,6 +70,8 @@ pub enum Expr {
     FBinOp(BinOp<FloatBinOp>),
     Neg(VarId),
     FNeg(VarId),
+    // Call to the comparison function of the type of the arguments
+    StructCmp(StructCmp, VarId, VarId),
     App(VarId, Vec<VarId>, RepType),
     // Tuple allocation
     Tuple { len: usize },
@@ -105,4 +107,9 @@ pub enum Exit {
         else_block: BlockIdx,
     },
     Jump(BlockIdx),
+    // No case of a `match` matched the value. Raises `Match_failure (file, line, col)`, `file` is
+    // a string literal.
+    MatchFailure { file: VarId, line: i64, col: i64 },
+    // Raise the exception
+    Raise(VarId),
 }
@@ -0,0 +1,564 @@
+// Monomorphization: polymorphic `let` and `let rec` definitions are copied for each instantiation
+// of their generalized type variables, and uses of the definitions are replaced with the binders
+// of the copies. Copies are made per `RepType` instantiation: `let rec id x = x` used at `int`,
+// `bool` and `int array` gets one copy, used at `int` and `float` it gets two. Type variables of
+// values compared structurally are the exception, as the comparison code depends on the type:
+// these get a copy for each type (see `Ctx::add_compared_tyvar`).
+//
+// Type variables that are not instantiated to `float` are replaced with `int` in the copies, and
+// type variables left unconstrained by the type checker default to `int`, so after this pass
+// types of all variables are monomorphic.
+
+use fxhash::FxHashMap;
+
+use crate::cg_types::RepType;
+use crate::ctx::{Ctx, VarId};
+use crate::parser::{Case, Expr, ExprKind, FunDef, Pat, Phrase};
+use crate::type_check::{TyVar, Type};
+
+pub fn monomorph_pgm(ctx: &mut Ctx, pgm: &[Phrase]) -> Vec<Phrase> {
+    let mut mono = Mono {
+        ctx,
+        poly_defs: vec![],
+        poly_bndrs: Default::default(),
+    };
+    let mut env = Env::default();
+
+    // Instances of a definition are only known after seeing all of its uses, so phrases are
+    // processed in reverse order, with top-level polymorphic definitions in scope from the start
+    let mut poly_def_idxs: Vec<Option<usize>> = Vec::with_capacity(pgm.len());
+    for phrase in pgm {
+        let bndrs: Vec<VarId> = match phrase {
+            Phrase::Let { bndr, .. } => vec![*bndr],
+            Phrase::LetRec { defs } => defs.iter().map(|def| def.bndr).collect(),
+            _ => vec![],
+        };
+        if bndrs.is_empty() || mono.ctx.poly_vars(bndrs[0]).is_empty() {
+            poly_def_idxs.push(None);
+        } else {
+            poly_def_idxs.push(Some(mono.push_poly_def(&env, bndrs)));
+        }
+    }
+
+    let mut phrases: Vec<Phrase> = Vec::with_capacity(pgm.len());
+    for (phrase, poly_def_idx) in pgm.iter().zip(poly_def_idxs).rev() {
+        match phrase {
+            Phrase::Let { bndr, rhs } => match poly_def_idx {
+                None => {
+                    let bndr = mono.bndr(&mut env, *bndr);
+                    let rhs = mono.expr(&mut env, rhs);
+                    phrases.push(Phrase::Let { bndr, rhs });
+                }
+                Some(idx) => {
+                    while let Some(mut inst_env) = mono.next_inst(idx) {
+                        let bndr = mono.bndr(&mut inst_env, *bndr);
+                        let rhs = mono.expr(&mut inst_env, rhs);
+                        phrases.push(Phrase::Let { bndr, rhs });
+                    }
+                }
+            },
+
+            Phrase::LetRec { defs } => {
+                let defs = match poly_def_idx {
+                    None => mono.fun_defs(&mut env, defs),
+                    Some(idx) => {
+                        let mut inst_defs: Vec<FunDef> = vec![];
+                        while let Some(mut inst_env) = mono.next_inst(idx) {
+                            inst_defs.extend(mono.fun_defs(&mut inst_env, defs));
+                        }
+                        inst_defs
+                    }
+                };
+                if !defs.is_empty() {
+                    phrases.push(Phrase::LetRec { defs });
+                }
+            }
+
+            Phrase::LetTuple { pat, rhs } => {
+                let pat = mono.pat(&mut env, pat);
+                let rhs = mono.expr(&mut env, rhs);
+                phrases.push(Phrase::LetTuple { pat, rhs });
+            }
+
+            Phrase::LetRecord { fields, rhs } => {
+                let fields = fields
+                    .iter()
+                    .map(|(field, bndr)| (*field, mono.bndr(&mut env, *bndr)))
+                    .collect();
+                let rhs = mono.expr(&mut env, rhs);
+                phrases.push(Phrase::LetRecord { fields, rhs });
+            }
+
+            // Declarations are in `ctx` after type checking
+            Phrase::Type { .. } | Phrase::Exception(_) => {}
+
+            Phrase::Expr(expr) => {
+                phrases.push(Phrase::Expr(mono.expr(&mut env, expr)));
+            }
+        }
+    }
+
+    phrases.reverse();
+    phrases
+}
+
+// How to copy code
+#[derive(Debug, Clone, Default)]
+struct Env {
+    // Whether we're making a copy of a polymorphic definition. Binders in copies are renamed to
+    // fresh variables.
+    copy: bool,
+    // Types of generalized type variables in the copy. Type variables not in the map are replaced
+    // with `int`.
+    tys: FxHashMap<TyVar, Type>,
+    // Binders renamed in the copy
+    vars: FxHashMap<VarId, VarId>,
+}
+
+struct PolyDef {
+    // Binders of the `let` or the `let rec`
+    bndrs: Vec<VarId>,
+    // Generalized type variables of the binders
+    tyvars: Vec<TyVar>,
+    // Environment at the definition. Copies of the definition extend this.
+    env: Env,
+    // Instances used so far: types of `tyvars` in the copy, and binders of the copy
+    insts: Vec<(Vec<Type>, FxHashMap<VarId, VarId>)>,
+    // Number of instances in `insts` copied so far
+    n_copied: usize,
+}
+
+struct Mono<'a> {
+    ctx: &'a mut Ctx,
+    // Polymorphic definitions in scope, innermost last
+    poly_defs: Vec<PolyDef>,
+    // Binders of `poly_defs`, mapped to their index in `poly_defs`
+    poly_bndrs: FxHashMap<VarId, usize>,
+}
+
+impl<'a> Mono<'a> {
+    fn push_poly_def(&mut self, env: &Env, bndrs: Vec<VarId>) -> usize {
+        let idx = self.poly_defs.len();
+        for bndr in &bndrs {
+            self.poly_bndrs.insert(*bndr, idx);
+        }
+        self.poly_defs.push(PolyDef {
+            tyvars: self.ctx.poly_vars(bndrs[0]).to_vec(),
+            bndrs,
+            env: env.clone(),
+            insts: vec![],
+            n_copied: 0,
+        });
+        idx
+    }
+
+    fn pop_poly_def(&mut self) {
+        let poly_def = self.poly_defs.pop().unwrap();
+        for bndr in poly_def.bndrs {
+            self.poly_bndrs.remove(&bndr);
+        }
+    }
+
+    // Environment for copying the next instance of the definition that is not copied yet
+    fn next_inst(&mut self, idx: usize) -> Option<Env> {
+        let poly_def = &mut self.poly_defs[idx];
+        let (tys, vars) = poly_def.insts.get(poly_def.n_copied)?;
+        poly_def.n_copied += 1;
+        let mut env = poly_def.env.clone();
+        env.copy = true;
+        for (tyvar, ty) in poly_def.tyvars.iter().zip(tys) {
+            env.tys.insert(*tyvar, ty.clone());
+        }
+        env.vars.extend(vars.iter().map(|(var, inst_var)| (*var, *inst_var)));
+        Some(env)
+    }
+
+    // Binder of the copy of the polymorphic definition for the use, and whether the use has the
+    // type of the binder in the copy
+    fn inst(&mut self, env: &Env, var: VarId, bndr: VarId) -> (VarId, bool) {
+        let mut tys: FxHashMap<TyVar, Type> = Default::default();
+        let var_ty = self.ty(env, &self.ctx.var_type(var));
+        match_ty(&self.ctx.var_type(bndr), &var_ty, &mut tys);
+
+        let ctx = &mut *self.ctx;
+        let poly_def = &mut self.poly_defs[self.poly_bndrs[&bndr]];
+        let inst_tys: Vec<Type> = poly_def
+            .tyvars
+            .iter()
+            .map(|tyvar| match tys.get(tyvar) {
+                Some(ty) if ctx.is_compared_tyvar(*tyvar) => ty.clone(),
+                Some(ty) => match RepType::from(ty) {
+                    RepType::Word => Type::Int,
+                    RepType::Float => Type::Float,
+                },
+                None => Type::Int,
+            })
+            .collect();
+        let same_ty = tys.iter().all(|(tyvar, ty)| {
+            match poly_def.tyvars.iter().position(|tyvar_| tyvar_ == tyvar) {
+                Some(idx) => *ty == inst_tys[idx],
+                None => *ty == Type::Int || *ty == Type::Float,
+            }
+        });
+        if let Some((_, vars)) = poly_def.insts.iter().find(|(tys_, _)| *tys_ == inst_tys) {
+            return (vars[&bndr], same_ty);
+        }
+
+        let vars: FxHashMap<VarId, VarId> = poly_def
+            .bndrs
+            .iter()
+            .map(|bndr| (*bndr, ctx.fresh_user_var(&ctx.var_name(*bndr))))
+            .collect();
+        let inst_var = vars[&bndr];
+        poly_def.insts.push((inst_tys, vars));
+        (inst_var, same_ty)
+    }
+
+    // Type in the copy
+    fn ty(&self, env: &Env, ty: &Type) -> Type {
+        match ty {
+            Type::Unit
+            | Type::Bool
+            | Type::Int
+            | Type::Float
+            | Type::String
+            | Type::Char
+            | Type::Con(_) => ty.clone(),
+            Type::Fun { args, ret } => Type::Fun {
+                args: args.iter().map(|arg| self.ty(env, arg)).collect(),
+                ret: Box::new(self.ty(env, ret)),
+            },
+            Type::Tuple(args) => Type::Tuple(args.iter().map(|arg| self.ty(env, arg)).collect()),
+            Type::Array(ty) => Type::Array(Box::new(self.ty(env, ty))),
+            Type::List(ty) => Type::List(Box::new(self.ty(env, ty))),
+            Type::Ref(ty) => Type::Ref(Box::new(self.ty(env, ty))),
+            Type::Var(tyvar) => env.tys.get(tyvar).cloned().unwrap_or(Type::Int),
+            // Abbreviations are expanded in the copies
+            Type::Alias { ty, .. } => self.ty(env, ty),
+        }
+    }
+
+    // Binder in the copy, with its type in the copy
+    fn bndr(&mut self, env: &mut Env, var: VarId) -> VarId {
+        let ty = self.ty(env, &self.ctx.var_type(var));
+        let ty = self.ctx.intern_type(ty);
+        let var = if env.copy {
+            match env.vars.get(&var) {
+                Some(var_) => *var_,
+                None => {
+                    let var_ = self.ctx.fresh_user_var(&self.ctx.var_name(var));
+                    env.vars.insert(var, var_);
+                    var_
+                }
+            }
+        } else {
+            var
+        };
+        self.ctx.set_var_type(var, ty);
+        var
+    }
+
+    fn fun_defs(&mut self, env: &mut Env, defs: &[FunDef]) -> Vec<FunDef> {
+        // Rename the functions first, so that they can refer to each other
+        let bndrs: Vec<VarId> = defs.iter().map(|def| self.bndr(env, def.bndr)).collect();
+        defs.iter()
+            .zip(bndrs)
+            .map(|(FunDef { args, rhs, .. }, bndr)| FunDef {
+                bndr,
+                args: args.iter().map(|arg| self.bndr(env, *arg)).collect(),
+                rhs: Box::new(self.expr(env, rhs)),
+            })
+            .collect()
+    }
+
+    fn pat(&mut self, env: &mut Env, pat: &Pat) -> Pat {
+        match pat {
+            Pat::Unit
+            | Pat::Bool(_)
+            | Pat::Int(_)
+            | Pat::Char(_)
+            | Pat::Nil
+            | Pat::Wildcard => pat.clone(),
+            Pat::Var(var) => Pat::Var(self.bndr(env, *var)),
+            Pat::Tuple(pats) => Pat::Tuple(pats.iter().map(|pat| self.pat(env, pat)).collect()),
+            Pat::Cons(hd, tl) => {
+                Pat::Cons(Box::new(self.pat(env, hd)), Box::new(self.pat(env, tl)))
+            }
+            Pat::Con { con, arg } => Pat::Con {
+                con: *con,
+                arg: arg.as_ref().map(|arg| Box::new(self.pat(env, arg))),
+            },
+        }
+    }
+
+    fn expr(&mut self, env: &mut Env, expr: &Expr) -> Expr {
+        let kind = match &expr.kind {
+            ExprKind::Unit => ExprKind::Unit,
+            ExprKind::Bool(b) => ExprKind::Bool(*b),
+            ExprKind::Int(i) => ExprKind::Int(*i),
+            ExprKind::Float(f) => ExprKind::Float(*f),
+            ExprKind::String(s) => ExprKind::String(s.clone()),
+            ExprKind::Char(c) => ExprKind::Char(*c),
+            ExprKind::Printf(fmt) => ExprKind::Printf(fmt.clone()),
+            ExprKind::Not(e) => ExprKind::Not(self.expr_box(env, e)),
+            ExprKind::And(e1, e2) => ExprKind::And(self.expr_box(env, e1), self.expr_box(env, e2)),
+            ExprKind::Or(e1, e2) => ExprKind::Or(self.expr_box(env, e1), self.expr_box(env, e2)),
+            ExprKind::Neg(e) => ExprKind::Neg(self.expr_box(env, e)),
+            ExprKind::IntBinOp(e1, op, e2) => {
+                ExprKind::IntBinOp(self.expr_box(env, e1), *op, self.expr_box(env, e2))
+            }
+            ExprKind::FNeg(e) => ExprKind::FNeg(self.expr_box(env, e)),
+            ExprKind::FloatBinOp(e1, op, e2) => {
+                ExprKind::FloatBinOp(self.expr_box(env, e1), *op, self.expr_box(env, e2))
+            }
+            ExprKind::Cmp(e1, op, e2) => {
+                ExprKind::Cmp(self.expr_box(env, e1), *op, self.expr_box(env, e2))
+            }
+            ExprKind::PhysCmp(e1, op, e2) => {
+                ExprKind::PhysCmp(self.expr_box(env, e1), *op, self.expr_box(env, e2))
+            }
+            ExprKind::Compare(e1, e2) => {
+                ExprKind::Compare(self.expr_box(env, e1), self.expr_box(env, e2))
+            }
+            ExprKind::If(e1, e2, e3) => ExprKind::If(
+                self.expr_box(env, e1),
+                self.expr_box(env, e2),
+                self.expr_box(env, e3),
+            ),
+
+            ExprKind::Let { bndr, rhs, body } if !self.ctx.poly_vars(*bndr).is_empty() => {
+                let idx = self.push_poly_def(env, vec![*bndr]);
+                let mut body = self.expr(env, body);
+                while let Some(mut inst_env) = self.next_inst(idx) {
+                    let bndr = self.bndr(&mut inst_env, *bndr);
+                    let rhs = self.expr_box(&mut inst_env, rhs);
+                    body = Expr {
+                        kind: ExprKind::Let {
+                            bndr,
+                            rhs,
+                            body: Box::new(body),
+                        },
+                        span: expr.span,
+                    };
+                }
+                self.pop_poly_def();
+                return body;
+            }
+
+            ExprKind::Let { bndr, rhs, body } => ExprKind::Let {
+                rhs: self.expr_box(env, rhs),
+                bndr: self.bndr(env, *bndr),
+                body: self.expr_box(env, body),
+            },
+
+            ExprKind::Var(var) => match self.ctx.poly_use_binder(*var) {
+                None => ExprKind::Var(env.vars.get(var).copied().unwrap_or(*var)),
+                Some(bndr) => {
+                    // Types in the copy only keep representations of the instantiated type
+                    // variables, e.g. a function or a tuple returned by the copy has type `int`.
+                    // Otherwise bind the copy to a variable of the type of the use, so that the
+                    // returned function can be applied, or the tuple can be taken apart.
+                    let (inst, same_ty) = self.inst(env, *var, bndr);
+                    if same_ty {
+                        ExprKind::Var(inst)
+                    } else {
+                        let use_var = self.bndr(env, *var);
+                        ExprKind::Let {
+                            bndr: use_var,
+                            rhs: Box::new(Expr {
+                                kind: ExprKind::Var(inst),
+                                span: expr.span,
+                            }),
+                            body: Box::new(Expr {
+                                kind: ExprKind::Var(use_var),
+                                span: expr.span,
+                            }),
+                        }
+                    }
+                }
+            },
+
+            ExprKind::LetRec { defs, body } if !self.ctx.poly_vars(defs[0].bndr).is_empty() => {
+                let idx = self.push_poly_def(env, defs.iter().map(|def| def.bndr).collect());
+                let body = self.expr_box(env, body);
+                let mut inst_defs: Vec<FunDef> = vec![];
+                while let Some(mut inst_env) = self.next_inst(idx) {
+                    inst_defs.extend(self.fun_defs(&mut inst_env, defs));
+                }
+                self.pop_poly_def();
+                if inst_defs.is_empty() {
+                    return *body;
+                }
+                ExprKind::LetRec {
+                    defs: inst_defs,
+                    body,
+                }
+            }
+
+            ExprKind::LetRec { defs, body } => ExprKind::LetRec {
+                defs: self.fun_defs(env, defs),
+                body: self.expr_box(env, body),
+            },
+
+            ExprKind::Fun { args, body } => ExprKind::Fun {
+                args: args.iter().map(|arg| self.bndr(env, *arg)).collect(),
+                body: self.expr_box(env, body),
+            },
+
+            ExprKind::App { fun, args } => ExprKind::App {
+                fun: self.expr_box(env, fun),
+                args: args.iter().map(|arg| self.expr(env, arg)).collect(),
+            },
+
+            // Annotations are only needed in type checking
+            ExprKind::Annot(e, _) => return self.expr(env, e),
+
+            ExprKind::Tuple(args) => {
+                ExprKind::Tuple(args.iter().map(|arg| self.expr(env, arg)).collect())
+            }
+
+            ExprKind::Nil { ty } => ExprKind::Nil {
+                ty: self.bndr(env, *ty),
+            },
+
+            ExprKind::Cons(hd, tl) => {
+                ExprKind::Cons(self.expr_box(env, hd), self.expr_box(env, tl))
+            }
+
+            ExprKind::LetTuple { pat, rhs, body } => ExprKind::LetTuple {
+                rhs: self.expr_box(env, rhs),
+                pat: self.pat(env, pat),
+                body: self.expr_box(env, body),
+            },
+
+            ExprKind::Array { len, elem } => ExprKind::Array {
+                len: self.expr_box(env, len),
+                elem: self.expr_box(env, elem),
+            },
+
+            ExprKind::Get(e1, e2) => ExprKind::Get(self.expr_box(env, e1), self.expr_box(env, e2)),
+
+            ExprKind::Put(e1, e2, e3) => ExprKind::Put(
+                self.expr_box(env, e1),
+                self.expr_box(env, e2),
+                self.expr_box(env, e3),
+            ),
+
+            ExprKind::For {
+                var,
+                from,
+                to,
+                dir,
+                body,
+            } => ExprKind::For {
+                from: self.expr_box(env, from),
+                to: self.expr_box(env, to),
+                var: self.bndr(env, *var),
+                dir: *dir,
+                body: self.expr_box(env, body),
+            },
+
+            ExprKind::While { cond, body } => ExprKind::While {
+                cond: self.expr_box(env, cond),
+                body: self.expr_box(env, body),
+            },
+
+            ExprKind::Ref(e) => ExprKind::Ref(self.expr_box(env, e)),
+
+            ExprKind::Deref(e) => ExprKind::Deref(self.expr_box(env, e)),
+
+            ExprKind::Assign(e1, e2) => {
+                ExprKind::Assign(self.expr_box(env, e1), self.expr_box(env, e2))
+            }
+
+            ExprKind::Match { scrut, cases } => ExprKind::Match {
+                scrut: self.expr_box(env, scrut),
+                cases: self.cases(env, cases),
+            },
+
+            ExprKind::Raise { exn, ty } => ExprKind::Raise {
+                exn: self.expr_box(env, exn),
+                ty: self.bndr(env, *ty),
+            },
+
+            ExprKind::Try { body, cases } => ExprKind::Try {
+                body: self.expr_box(env, body),
+                cases: self.cases(env, cases),
+            },
+
+            ExprKind::Assert(e) => ExprKind::Assert(self.expr_box(env, e)),
+
+            ExprKind::Con { con, arg } => ExprKind::Con {
+                con: *con,
+                arg: arg.as_ref().map(|arg| self.expr_box(env, arg)),
+            },
+
+            ExprKind::Record(fields) => ExprKind::Record(
+                fields
+                    .iter()
+                    .map(|(field, expr)| (*field, self.expr(env, expr)))
+                    .collect(),
+            ),
+
+            ExprKind::GetField(record, field) => {
+                ExprKind::GetField(self.expr_box(env, record), *field)
+            }
+
+            ExprKind::SetField(record, field, val) => {
+                ExprKind::SetField(self.expr_box(env, record), *field, self.expr_box(env, val))
+            }
+
+            ExprKind::LetRecord { fields, rhs, body } => ExprKind::LetRecord {
+                rhs: self.expr_box(env, rhs),
+                fields: fields
+                    .iter()
+                    .map(|(field, bndr)| (*field, self.bndr(env, *bndr)))
+                    .collect(),
+                body: self.expr_box(env, body),
+            },
+        };
+        Expr {
+            kind,
+            span: expr.span,
+        }
+    }
+
+    fn cases(&mut self, env: &mut Env, cases: &[Case]) -> Vec<Case> {
+        cases
+            .iter()
+            .map(|Case { pat, span, rhs }| Case {
+                pat: self.pat(env, pat),
+                span: *span,
+                rhs: self.expr(env, rhs),
+            })
+            .collect()
+    }
+
+    fn expr_box(&mut self, env: &mut Env, expr: &Expr) -> Box<Expr> {
+        Box::new(self.expr(env, expr))
+    }
+}
+
+// Add types of type variables in `generic` to `tys`, by matching it against its instance `ty`
+fn match_ty(generic: &Type, ty: &Type, tys: &mut FxHashMap<TyVar, Type>) {
+    match (generic, ty) {
+        (Type::Var(tyvar), _) => {
+            tys.insert(*tyvar, ty.clone());
+        }
+        (Type::Fun { args: args1, ret: ret1 }, Type::Fun { args: args2, ret: ret2 }) => {
+            for (arg1, arg2) in args1.iter().zip(args2) {
+                match_ty(arg1, arg2, tys);
+            }
+            match_ty(ret1, ret2, tys);
+        }
+        (Type::Tuple(args1), Type::Tuple(args2)) => {
+            for (arg1, arg2) in args1.iter().zip(args2) {
+                match_ty(arg1, arg2, tys);
+            }
+        }
+        (Type::Array(ty1), Type::Array(ty2))
+        | (Type::List(ty1), Type::List(ty2))
+        | (Type::Ref(ty1), Type::Ref(ty2)) => match_ty(ty1, ty2, tys),
+        _ => {}
+    }
+}
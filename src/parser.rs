@@ -1,20 +1,37 @@
 use crate::common::*;
 use crate::ctx::{Ctx, VarId};
 use crate::lexer::Token;
+use crate::span::{Span, Spanned};
 use crate::var::CompilerPhase;
 
 use std::fmt;
 
 #[derive(Debug)]
-pub enum Expr {
+pub struct Expr {
+    pub kind: ExprKind,
+    pub span: Span,
+}
+
+#[derive(Debug)]
+pub enum ExprKind {
     // ()
     Unit,
     // true, false
     Bool(bool),
     Int(i64),
     Float(f64),
+    // "..."
+    String(String),
+    // 'c'
+    Char(u8),
+    // Printf.printf "..."
+    Printf(String),
     // not <expr>
     Not(Box<Expr>),
+    // <expr> && <expr>
+    And(Box<Expr>, Box<Expr>),
+    // <expr> || <expr>
+    Or(Box<Expr>, Box<Expr>),
     // - <expr>
     Neg(Box<Expr>),
     // '<expr> + <expr>' or '<expr> - <expr>'
@@ -23,8 +40,13 @@ pub enum Expr {
     FNeg(Box<Expr>),
     // A float binary operation, e.g. '<expr> +. <expr>'
     FloatBinOp(Box<Expr>, FloatBinOp, Box<Expr>),
-    // Comparison, e.g. <expr> <= <expr>
+    // Comparison, e.g. <expr> <= <expr>. Structural on tuples, arrays etc.
     Cmp(Box<Expr>, Cmp, Box<Expr>),
+    // Physical comparison: <expr> == <expr> (`Cmp::Equal`) or <expr> != <expr> (`Cmp::NotEqual`)
+    PhysCmp(Box<Expr>, Cmp, Box<Expr>),
+    // compare <expr> <expr>. Not produced by the parser: the type checker replaces uses of
+    // `compare` with this, when it's not shadowed.
+    Compare(Box<Expr>, Box<Expr>),
     // if <expr> then <expr> else <expr>
     If(Box<Expr>, Box<Expr>, Box<Expr>),
     // let <ident> = <expr> in <expr>
@@ -35,11 +57,14 @@ pub enum Expr {
     },
     // <ident>
     Var(VarId),
-    // let rec <ident> <ident>+ = <expr> in <expr>
+    // let rec <fun_def> (and <fun_def>)* in <expr>
     LetRec {
-        bndr: VarId,
+        defs: Vec<FunDef>,
+        body: Box<Expr>,
+    },
+    // fun <ident>+ -> <expr>
+    Fun {
         args: Vec<VarId>,
-        rhs: Box<Expr>,
         body: Box<Expr>,
     },
     // <expr> <expr>+
@@ -47,11 +72,19 @@ pub enum Expr {
         fun: Box<Expr>,
         args: Vec<Expr>,
     },
+    // ( <expr> : <ty> ). Also generated for `let <ident> : <ty> = ...`, annotated function
+    // arguments, and return type annotations of functions.
+    Annot(Box<Expr>, TyExpr),
     // <expr> (, <expr>)+
     Tuple(Vec<Expr>),
-    // let ( <ident> (, <ident>)+ ) = <expr> in <expr>
+    // [], and the end of list literals. `ty` is a generated variable for the type of the list.
+    Nil { ty: VarId },
+    // <expr> :: <expr>, and [<expr> (; <expr>)*] as nested conses
+    Cons(Box<Expr>, Box<Expr>),
+    // let <bndr_pat> = <expr> in <expr>, where the pattern is not a single identifier. Only `_`,
+    // `()`, identifiers, and tuples of these are allowed in the pattern.
     LetTuple {
-        bndrs: Vec<VarId>,
+        pat: Pat,
         rhs: Box<Expr>,
         body: Box<Expr>,
     },
@@ -64,6 +97,169 @@ pub enum Expr {
     Get(Box<Expr>, Box<Expr>),
     // <expr> . ( <expr> ) <- <expr>
     Put(Box<Expr>, Box<Expr>, Box<Expr>),
+    // ref <expr>
+    Ref(Box<Expr>),
+    // ! <expr>
+    Deref(Box<Expr>),
+    // <expr> := <expr>
+    Assign(Box<Expr>, Box<Expr>),
+    // for <ident> = <expr> (to | downto) <expr> do <expr> done
+    For {
+        var: VarId,
+        from: Box<Expr>,
+        to: Box<Expr>,
+        dir: ForDir,
+        body: Box<Expr>,
+    },
+    // while <expr> do <expr> done
+    While {
+        cond: Box<Expr>,
+        body: Box<Expr>,
+    },
+    // match <expr> with (|)? <case> (| <case>)*
+    Match {
+        scrut: Box<Expr>,
+        cases: Vec<Case>,
+    },
+    // raise <expr>, or failwith <expr> as `raise (Failure <expr>)`. `ty` is a generated variable
+    // for the type of the expression, which is not related to the type of the exception.
+    Raise {
+        exn: Box<Expr>,
+        ty: VarId,
+    },
+    // try <expr> with (|)? <case> (| <case>)*
+    Try {
+        body: Box<Expr>,
+        cases: Vec<Case>,
+    },
+    // assert <expr>
+    Assert(Box<Expr>),
+    // <con> <expr>?. Constructors with multiple arguments take a tuple expression.
+    Con {
+        con: VarId,
+        arg: Option<Box<Expr>>,
+    },
+    // { <field> = <expr> (; <field> = <expr>)* }
+    Record(Vec<(VarId, Expr)>),
+    // <expr> . <field>
+    GetField(Box<Expr>, VarId),
+    // <expr> . <field> <- <expr>
+    SetField(Box<Expr>, VarId, Box<Expr>),
+    // let { <field> (= <ident>)? (; <field> (= <ident>)?)* } = <expr> in <expr>
+    LetRecord {
+        fields: Vec<(VarId, VarId)>,
+        rhs: Box<Expr>,
+        body: Box<Expr>,
+    },
+}
+
+// A case of a `match`: <pat> -> <expr>
+#[derive(Debug)]
+pub struct Case {
+    pub pat: Pat,
+    // Span of the pattern
+    pub span: Span,
+    pub rhs: Expr,
+}
+
+#[derive(Debug, Clone)]
+pub enum Pat {
+    // ()
+    Unit,
+    // true, false
+    Bool(bool),
+    // Integer literal, possibly negative
+    Int(i64),
+    // Character literal
+    Char(u8),
+    // _
+    Wildcard,
+    // <ident>
+    Var(VarId),
+    // <pat> (, <pat>)+
+    Tuple(Vec<Pat>),
+    // <con> <pat>?. Constructors with multiple arguments take a tuple pattern or `_`.
+    Con { con: VarId, arg: Option<Box<Pat>> },
+    // []
+    Nil,
+    // <pat> :: <pat>, and [<pat> (; <pat>)*] as nested conses
+    Cons(Box<Pat>, Box<Pat>),
+}
+
+// Type expressions
+#[derive(Debug)]
+pub struct TyExpr {
+    pub kind: TyExprKind,
+    pub span: Span,
+}
+
+#[derive(Debug)]
+pub enum TyExprKind {
+    // <ty>* <ident>, e.g. `int`, `t`, `int array`
+    Con { args: Vec<TyExpr>, name: String },
+    // <ty> (* <ty>)+
+    Tuple(Vec<TyExpr>),
+    // <ty> (-> <ty>)+
+    Fun { args: Vec<TyExpr>, ret: Box<TyExpr> },
+}
+
+// A type declaration: <ident> = <type_decl_kind>
+#[derive(Debug)]
+pub struct TypeDecl {
+    pub name: String,
+    pub kind: TypeDeclKind,
+}
+
+#[derive(Debug)]
+pub enum TypeDeclKind {
+    // (|)? <con_decl> (| <con_decl>)*
+    Variant(Vec<ConDecl>),
+    // { <field_decl> (; <field_decl>)* }
+    Record(Vec<FieldDecl>),
+    // <ty>, a type abbreviation
+    Alias(TyExpr),
+}
+
+// A constructor declaration: <con> (of <ty> (* <ty>)*)?
+#[derive(Debug)]
+pub struct ConDecl {
+    pub con: VarId,
+    pub args: Vec<TyExpr>,
+}
+
+// A record field declaration: (mutable)? <field> : <ty>
+#[derive(Debug)]
+pub struct FieldDecl {
+    pub field: VarId,
+    pub mutable: bool,
+    pub ty: TyExpr,
+}
+
+// A top-level phrase. Top-level definitions are visible in the phrases that follow.
+#[derive(Debug)]
+pub enum Phrase {
+    // let <ident> = <expr>
+    Let { bndr: VarId, rhs: Expr },
+    // let rec <fun_def> (and <fun_def>)*
+    LetRec { defs: Vec<FunDef> },
+    // let <bndr_pat> = <expr>, as in `ExprKind::LetTuple`
+    LetTuple { pat: Pat, rhs: Expr },
+    // let { <field> (= <ident>)? (; <field> (= <ident>)?)* } = <expr>
+    LetRecord { fields: Vec<(VarId, VarId)>, rhs: Expr },
+    // type <type_decl> (and <type_decl>)*
+    Type { decls: Vec<TypeDecl> },
+    // exception <con_decl>
+    Exception(ConDecl),
+    // <expr>, evaluated for side effects
+    Expr(Expr),
+}
+
+// A function in a `let rec`: <ident> <ident>+ = <expr>
+#[derive(Debug)]
+pub struct FunDef {
+    pub bndr: VarId,
+    pub args: Vec<VarId>,
+    pub rhs: Box<Expr>,
 }
 
 impl fmt::Display for Cmp {
@@ -81,20 +277,48 @@ impl fmt::Display for Cmp {
     }
 }
 
-pub fn parse(ctx: &mut Ctx, tokens: &[Token]) -> Result<Expr, ParseErr> {
+impl Expr {
+    fn new(kind: ExprKind, span: Span) -> Expr {
+        Expr { kind, span }
+    }
+}
+
+impl TyExpr {
+    fn new(kind: TyExprKind, span: Span) -> TyExpr {
+        TyExpr { kind, span }
+    }
+}
+
+pub fn parse(ctx: &mut Ctx, tokens: &[Spanned<Token>]) -> Result<Vec<Phrase>, ParseErr> {
     let mut parser = Parser::new(tokens);
-    let expr = parser.expr(ctx)?;
-    Ok(expr)
+    parser.phrases(ctx)
 }
 
 #[derive(Debug)]
 pub enum ParseErr {
-    EndOfInput,
-    Unexpected { seen: Token, expected: &'static str },
+    EndOfInput {
+        span: Span,
+    },
+    Unexpected {
+        seen: Token,
+        expected: &'static str,
+        span: Span,
+    },
+}
+
+impl fmt::Display for ParseErr {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ParseErr::EndOfInput { .. } => write!(f, "unexpected end of input"),
+            ParseErr::Unexpected { seen, expected, .. } => {
+                write!(f, "unexpected {}, expected {}", seen, expected)
+            }
+        }
+    }
 }
 
 pub struct Parser<'a> {
-    tokens: &'a [Token],
+    tokens: &'a [Spanned<Token>],
     tok_idx: usize,
 }
 
@@ -103,28 +327,38 @@ const IN_PREC: usize = 0;
 const LET_PREC: usize = 1;
 const SEMICOLON_PREC: usize = 2;
 const IF_PREC: usize = 3;
-// The arrow in `x.(y) <- blah`
+// The arrow in `x.(y) <- blah`, and `:=`
 const LESS_MINUS_PREC: usize = 4;
 const TUPLE_PREC: usize = 5;
 const COMMA_PREC: usize = 6;
+// `||` and `&&`. Both right associative.
+const OR_PREC: usize = 7;
+const AND_PREC: usize = 8;
 // Comparison operators
-const CMP_PREC: usize = 7;
+const CMP_PREC: usize = 9;
+// String concatenation. Right associative.
+const CONCAT_PREC: usize = 10;
+// `::`. Right associative.
+const CONS_PREC: usize = 11;
 // Plus and minus, for floats and ints
-const PLUS_MINUS_PREC: usize = 8;
-// Multiplication and division, for floats and ints
-const DIV_MULT_PREC: usize = 9;
-const UNARY_MINUS_PREC: usize = 10;
-// Function application, `not`, and `Array.create`
-const APP_PREC: usize = 11;
+const PLUS_MINUS_PREC: usize = 12;
+// Multiplication and division, for floats and ints, and `land`, `lor`, `lxor`
+const DIV_MULT_PREC: usize = 13;
+// Shifts: `lsl`, `lsr`, `asr`. Right associative.
+const SHIFT_PREC: usize = 14;
+const UNARY_MINUS_PREC: usize = 15;
+// Function application, `not`, `lnot`, `ref`, `raise`, `failwith`, `assert`, and `Array.create`
+const APP_PREC: usize = 16;
 // Dots in `x.(y)` (both for getting and setting)
-const DOT_PREC: usize = 12;
+const DOT_PREC: usize = 17;
 
 impl<'a> Parser<'a> {
-    pub fn new(tokens: &[Token]) -> Parser {
+    pub fn new(tokens: &[Spanned<Token>]) -> Parser<'_> {
         Parser { tokens, tok_idx: 0 }
     }
 
     pub fn expr0(&mut self, ctx: &mut Ctx, prec: usize) -> Result<Expr, ParseErr> {
+        let start = self.next_span();
         match self.next_token()? {
             //
             // Single-token expressions
@@ -132,156 +366,321 @@ impl<'a> Parser<'a> {
             Token::Bool(bool) => {
                 let b = *bool;
                 self.consume();
-                Ok(Expr::Bool(b))
+                Ok(Expr::new(ExprKind::Bool(b), start))
             }
             Token::Int(int) => {
                 let i = *int;
                 self.consume();
-                Ok(Expr::Int(i))
+                Ok(Expr::new(ExprKind::Int(i), start))
             }
             Token::Float(float) => {
                 let f = *float;
                 self.consume();
-                Ok(Expr::Float(f))
+                Ok(Expr::new(ExprKind::Float(f), start))
+            }
+            Token::Id(id) if id == "Printf.printf" => {
+                // Type depends on the format string, which has to be a literal
+                self.consume();
+                match self.next_token()? {
+                    Token::String(fmt) => {
+                        let fmt = fmt.clone();
+                        self.consume();
+                        Ok(Expr::new(ExprKind::Printf(fmt), self.span_from(start)))
+                    }
+                    other => Err(ParseErr::Unexpected {
+                        seen: other.clone(),
+                        expected: "format string literal",
+                        span: self.next_span(),
+                    }),
+                }
             }
             Token::Id(id) => {
                 let var = ctx.fresh_user_var(id);
                 self.consume();
-                Ok(Expr::Var(var))
+                Ok(Expr::new(ExprKind::Var(var), start))
+            }
+            Token::String(s) => {
+                let s = s.clone();
+                self.consume();
+                Ok(Expr::new(ExprKind::String(s), start))
+            }
+            Token::Char(c) => {
+                let c = *c;
+                self.consume();
+                Ok(Expr::new(ExprKind::Char(c), start))
+            }
+            Token::UId(con) => {
+                let con = ctx.fresh_user_var(con);
+                self.consume();
+                // Constructors in arguments don't take arguments: `f A x` is `f (A) x`
+                let arg = match self.next_token() {
+                    Ok(tok) if prec < APP_PREC && *tok != Token::Let => {
+                        self.expr0(ctx, APP_PREC).ok().map(Box::new)
+                    }
+                    _ => None,
+                };
+                Ok(Expr::new(ExprKind::Con { con, arg }, self.span_from(start)))
             }
 
             //
             // Other stuff
             //
+            Token::LBrace => {
+                self.consume();
+                let mut fields = vec![];
+                loop {
+                    let field = self.expect_id()?;
+                    let field = ctx.fresh_user_var(field);
+                    self.expect(Token::Equal, "'='")?;
+                    // Parse everything until ';' or '}'
+                    let expr = self.expr1(ctx, IF_PREC)?;
+                    fields.push((field, expr));
+                    if !self.seq_sep(Token::RBrace, "';' or '}'")? {
+                        break;
+                    }
+                }
+                Ok(Expr::new(ExprKind::Record(fields), self.span_from(start)))
+            }
+            Token::LBracket => {
+                self.consume();
+                let mut elems = vec![];
+                if let Token::RBracket = self.next_token()? {
+                    self.consume();
+                } else {
+                    loop {
+                        // Parse everything until ';' or ']'
+                        elems.push(self.expr1(ctx, IF_PREC)?);
+                        if !self.seq_sep(Token::RBracket, "';' or ']'")? {
+                            break;
+                        }
+                    }
+                }
+                let span = self.span_from(start);
+                let nil = ExprKind::Nil {
+                    ty: ctx.fresh_generated_var(CompilerPhase::Parser),
+                };
+                let list = elems.into_iter().rev().fold(Expr::new(nil, span), |tl, hd| {
+                    let span = hd.span.to(span);
+                    Expr::new(ExprKind::Cons(Box::new(hd), Box::new(tl)), span)
+                });
+                Ok(list)
+            }
             Token::LParen => {
                 self.consume();
                 match self.next_token()? {
                     Token::RParen => {
                         self.consume();
-                        Ok(Expr::Unit)
+                        Ok(Expr::new(ExprKind::Unit, self.span_from(start)))
                     }
                     _ => {
-                        // Parse everything until ')'
+                        // Parse everything until ')' or ':'
                         let expr = self.expr1(ctx, INIT_PREC)?;
+                        if let Ok(Token::Colon) = self.next_token() {
+                            self.consume();
+                            let ty = self.ty()?;
+                            self.expect(Token::RParen, "')'")?;
+                            let kind = ExprKind::Annot(Box::new(expr), ty);
+                            return Ok(Expr::new(kind, self.span_from(start)));
+                        }
                         self.expect(Token::RParen, "')'")?;
-                        Ok(expr)
+                        Ok(Expr::new(expr.kind, self.span_from(start)))
                     }
                 }
             }
             Token::Not if prec <= APP_PREC => {
                 self.consume();
-                Ok(Expr::Not(Box::new(self.expr1(ctx, APP_PREC)?)))
+                let expr = self.expr1(ctx, APP_PREC)?;
+                Ok(Expr::new(ExprKind::Not(Box::new(expr)), self.span_from(start)))
+            }
+            Token::Lnot if prec <= APP_PREC => {
+                // `lnot x` is `x lxor -1`
+                self.consume();
+                let expr = self.expr1(ctx, APP_PREC)?;
+                let span = self.span_from(start);
+                let minus_one = Expr::new(ExprKind::Int(-1), span);
+                Ok(Expr::new(
+                    ExprKind::IntBinOp(Box::new(expr), IntBinOp::Xor, Box::new(minus_one)),
+                    span,
+                ))
+            }
+            Token::Ref if prec <= APP_PREC => {
+                self.consume();
+                let expr = self.expr1(ctx, APP_PREC)?;
+                Ok(Expr::new(ExprKind::Ref(Box::new(expr)), self.span_from(start)))
+            }
+            Token::Raise if prec <= APP_PREC => {
+                self.consume();
+                let exn = self.expr1(ctx, APP_PREC)?;
+                let ty = ctx.fresh_generated_var(CompilerPhase::Parser);
+                Ok(Expr::new(
+                    ExprKind::Raise {
+                        exn: Box::new(exn),
+                        ty,
+                    },
+                    self.span_from(start),
+                ))
+            }
+            Token::Failwith if prec <= APP_PREC => {
+                self.consume();
+                let msg = self.expr1(ctx, APP_PREC)?;
+                let span = self.span_from(start);
+                let con = ctx.fresh_user_var("Failure");
+                let exn = Expr::new(
+                    ExprKind::Con {
+                        con,
+                        arg: Some(Box::new(msg)),
+                    },
+                    span,
+                );
+                let ty = ctx.fresh_generated_var(CompilerPhase::Parser);
+                Ok(Expr::new(
+                    ExprKind::Raise {
+                        exn: Box::new(exn),
+                        ty,
+                    },
+                    span,
+                ))
+            }
+            Token::Assert if prec <= APP_PREC => {
+                self.consume();
+                let expr = self.expr1(ctx, APP_PREC)?;
+                Ok(Expr::new(ExprKind::Assert(Box::new(expr)), self.span_from(start)))
+            }
+            Token::Bang => {
+                self.consume();
+                let expr = self.expr0(ctx, DOT_PREC)?;
+                Ok(Expr::new(ExprKind::Deref(Box::new(expr)), self.span_from(start)))
             }
             Token::Minus if prec <= UNARY_MINUS_PREC => {
                 self.consume();
                 let expr = self.expr1(ctx, UNARY_MINUS_PREC)?;
-                match expr {
-                    Expr::Float(_) =>
+                let span = self.span_from(start);
+                match expr.kind {
+                    ExprKind::Float(_) =>
                     // Hacky, but this is how the original min-caml parses this as well.
                     {
-                        Ok(Expr::FNeg(Box::new(expr)))
+                        Ok(Expr::new(ExprKind::FNeg(Box::new(expr)), span))
                     }
-                    _ => Ok(Expr::Neg(Box::new(expr))),
+                    _ => Ok(Expr::new(ExprKind::Neg(Box::new(expr)), span)),
                 }
             }
             Token::MinusDot if prec <= UNARY_MINUS_PREC => {
                 self.consume();
-                Ok(Expr::FNeg(Box::new(self.expr1(ctx, PLUS_MINUS_PREC)?)))
+                let expr = self.expr1(ctx, PLUS_MINUS_PREC)?;
+                Ok(Expr::new(ExprKind::FNeg(Box::new(expr)), self.span_from(start)))
             }
             Token::ArrayCreate if prec <= APP_PREC => {
                 self.consume();
                 let expr1 = self.expr0(ctx, APP_PREC)?;
                 let expr2 = self.expr0(ctx, APP_PREC)?;
-                Ok(Expr::Array {
-                    len: Box::new(expr1),
-                    elem: Box::new(expr2),
-                })
+                Ok(Expr::new(
+                    ExprKind::Array {
+                        len: Box::new(expr1),
+                        elem: Box::new(expr2),
+                    },
+                    self.span_from(start),
+                ))
             }
             Token::Let => {
                 self.consume();
-                match self.next_token()? {
-                    Token::Rec => {
-                        self.consume();
-                        let bndr = self.expect_id()?;
-                        let bndr = ctx.fresh_user_var(bndr);
-                        let mut args = vec![];
-                        loop {
-                            match self.next_token()? {
-                                Token::Underscore => {
-                                    args.push(ctx.fresh_generated_var(CompilerPhase::Parser));
-                                    self.consume();
-                                }
-                                Token::Id(arg) => {
-                                    args.push(ctx.fresh_user_var(arg));
-                                    self.consume();
-                                }
-                                Token::Equal => {
-                                    break;
-                                }
-                                other => {
-                                    return Err(ParseErr::Unexpected {
-                                        expected: "binder or '='",
-                                        seen: other.clone(),
-                                    });
-                                }
-                            }
-                        }
-                        self.expect(Token::Equal, "'='")?;
-                        // Parse everything until 'in'
-                        let rhs = Box::new(self.expr1(ctx, INIT_PREC)?);
-                        self.expect(Token::In, "'in'")?;
-                        let body = Box::new(self.expr1(ctx, LET_PREC)?);
-                        Ok(Expr::LetRec {
-                            bndr,
-                            args,
-                            rhs,
-                            body,
-                        })
-                    }
-                    Token::LParen => {
-                        self.consume();
-                        let mut bndrs = vec![];
-                        {
-                            let bndr = self.expect_id()?;
-                            bndrs.push(ctx.fresh_user_var(bndr));
-                        }
-                        while let Ok(Token::Comma) = self.next_token() {
-                            self.consume();
-                            {
-                                let bndr = self.expect_id()?;
-                                bndrs.push(ctx.fresh_user_var(bndr));
-                            }
-                        }
-                        self.expect(Token::RParen, "')'")?;
-                        self.expect(Token::Equal, "'='")?;
-                        // Parse everything until '='
-                        let rhs = self.expr1(ctx, INIT_PREC)?;
-                        self.expect(Token::In, "in")?;
-                        let body = self.expr1(ctx, IN_PREC)?;
-                        Ok(Expr::LetTuple {
-                            bndrs,
-                            rhs: Box::new(rhs),
-                            body: Box::new(body),
-                        })
-                    }
-                    Token::Id(var) => {
-                        let bndr = ctx.fresh_user_var(var);
-                        self.consume();
-                        self.expect(Token::Equal, "'='")?;
-                        // Parse everything until 'in'
-                        let rhs = Box::new(self.expr1(ctx, INIT_PREC)?);
-                        self.expect(Token::In, "'in'")?;
-                        let body = Box::new(self.expr1(ctx, IN_PREC)?);
-                        Ok(Expr::Let { bndr, rhs, body })
-                    }
+                let binding = self.let_binding(ctx)?;
+                self.let_body(ctx, binding, start)
+            }
+            Token::Fun => {
+                self.consume();
+                let (args, annots, pats) =
+                    self.fun_args(ctx, &Token::MinusGreater, "binder or '->'")?;
+                if args.is_empty() {
+                    return Err(ParseErr::Unexpected {
+                        seen: Token::MinusGreater,
+                        expected: "binder",
+                        span: self.next_span(),
+                    });
+                }
+                self.expect(Token::MinusGreater, "'->'")?;
+                // Parse everything, as in a `let` body
+                let body = self.expr1(ctx, INIT_PREC)?;
+                Ok(Expr::new(
+                    ExprKind::Fun {
+                        args,
+                        body: Box::new(annot_args(ctx, annots, pat_args(pats, body))),
+                    },
+                    self.span_from(start),
+                ))
+            }
+            Token::For => {
+                self.consume();
+                let var = self.expect_id()?;
+                let var = ctx.fresh_user_var(var);
+                self.expect(Token::Equal, "'='")?;
+                // Parse everything until 'to' or 'downto'
+                let from = self.expr1(ctx, INIT_PREC)?;
+                let dir = match self.next_token()? {
+                    Token::To => ForDir::Up,
+                    Token::Downto => ForDir::Down,
                     other => {
-                        Err(ParseErr::Unexpected {
-                            // TODO: remove cloning
+                        return Err(ParseErr::Unexpected {
                             seen: other.clone(),
-                            expected: "'rec', '(', or identifier",
-                        })
+                            expected: "'to' or 'downto'",
+                            span: self.next_span(),
+                        });
                     }
-                }
+                };
+                self.consume();
+                // Parse everything until 'do'
+                let to = self.expr1(ctx, INIT_PREC)?;
+                let body = self.loop_body(ctx)?;
+                Ok(Expr::new(
+                    ExprKind::For {
+                        var,
+                        from: Box::new(from),
+                        to: Box::new(to),
+                        dir,
+                        body: Box::new(body),
+                    },
+                    self.span_from(start),
+                ))
+            }
+            Token::While => {
+                self.consume();
+                // Parse everything until 'do'
+                let cond = self.expr1(ctx, INIT_PREC)?;
+                let body = self.loop_body(ctx)?;
+                Ok(Expr::new(
+                    ExprKind::While {
+                        cond: Box::new(cond),
+                        body: Box::new(body),
+                    },
+                    self.span_from(start),
+                ))
+            }
+            Token::Match => {
+                self.consume();
+                // Parse everything until 'with'
+                let scrut = self.expr1(ctx, INIT_PREC)?;
+                self.expect(Token::With, "'with'")?;
+                let cases = self.cases(ctx)?;
+                Ok(Expr::new(
+                    ExprKind::Match {
+                        scrut: Box::new(scrut),
+                        cases,
+                    },
+                    self.span_from(start),
+                ))
+            }
+            Token::Try => {
+                self.consume();
+                // Parse everything until 'with'
+                let body = self.expr1(ctx, INIT_PREC)?;
+                self.expect(Token::With, "'with'")?;
+                let cases = self.cases(ctx)?;
+                Ok(Expr::new(
+                    ExprKind::Try {
+                        body: Box::new(body),
+                        cases,
+                    },
+                    self.span_from(start),
+                ))
             }
             Token::If if prec <= IF_PREC => {
                 self.consume();
@@ -292,11 +691,15 @@ impl<'a> Parser<'a> {
                 let e2 = self.expr1(ctx, INIT_PREC)?;
                 self.expect(Token::Else, "'else'")?;
                 let e3 = self.expr1(ctx, IF_PREC)?;
-                Ok(Expr::If(Box::new(e1), Box::new(e2), Box::new(e3)))
+                Ok(Expr::new(
+                    ExprKind::If(Box::new(e1), Box::new(e2), Box::new(e3)),
+                    self.span_from(start),
+                ))
             }
             other => Err(ParseErr::Unexpected {
                 seen: other.clone(),
                 expected: "expression",
+                span: start,
             }),
         }
     }
@@ -304,92 +707,218 @@ impl<'a> Parser<'a> {
     pub fn expr1(&mut self, ctx: &mut Ctx, prec: usize) -> Result<Expr, ParseErr> {
         let mut expr = self.expr0(ctx, prec)?;
         let mut parsing_app = false;
+        // Like `parsing_app`, so that `(a, b), c` is not parsed as `a, b, c`
+        let mut parsing_tuple = false;
         loop {
             match self.next_token() {
                 Ok(Token::Semicolon) if prec <= SEMICOLON_PREC => {
                     self.consume();
                     let sym = ctx.fresh_generated_var(CompilerPhase::Parser);
                     let expr2 = self.expr1(ctx, prec)?;
-                    expr = Expr::Let {
-                        bndr: sym,
-                        rhs: Box::new(expr),
-                        body: Box::new(expr2),
-                    };
+                    let span = expr.span.to(expr2.span);
+                    expr = Expr::new(
+                        ExprKind::Let {
+                            bndr: sym,
+                            rhs: Box::new(expr),
+                            body: Box::new(expr2),
+                        },
+                        span,
+                    );
                 }
                 Ok(Token::Plus) if prec < PLUS_MINUS_PREC => {
                     self.consume();
                     let expr2 = self.expr1(ctx, PLUS_MINUS_PREC)?;
-                    expr = Expr::IntBinOp(Box::new(expr), IntBinOp::Add, Box::new(expr2));
+                    expr = int_binop(expr, IntBinOp::Add, expr2);
                 }
                 Ok(Token::Minus) if prec < PLUS_MINUS_PREC => {
                     self.consume();
                     let expr2 = self.expr1(ctx, PLUS_MINUS_PREC)?;
-                    expr = Expr::IntBinOp(Box::new(expr), IntBinOp::Sub, Box::new(expr2));
+                    expr = int_binop(expr, IntBinOp::Sub, expr2);
                 }
                 Ok(Token::PlusDot) if prec < PLUS_MINUS_PREC => {
                     self.consume();
                     let expr2 = self.expr1(ctx, PLUS_MINUS_PREC)?;
-                    expr = Expr::FloatBinOp(Box::new(expr), FloatBinOp::Add, Box::new(expr2));
+                    expr = float_binop(expr, FloatBinOp::Add, expr2);
                 }
                 Ok(Token::MinusDot) if prec < PLUS_MINUS_PREC => {
                     self.consume();
                     let expr2 = self.expr1(ctx, PLUS_MINUS_PREC)?;
-                    expr = Expr::FloatBinOp(Box::new(expr), FloatBinOp::Sub, Box::new(expr2));
+                    expr = float_binop(expr, FloatBinOp::Sub, expr2);
+                }
+                Ok(Token::Ast) if prec < DIV_MULT_PREC => {
+                    self.consume();
+                    let expr2 = self.expr1(ctx, DIV_MULT_PREC)?;
+                    expr = int_binop(expr, IntBinOp::Mul, expr2);
+                }
+                Ok(Token::Slash) if prec < DIV_MULT_PREC => {
+                    self.consume();
+                    let expr2 = self.expr1(ctx, DIV_MULT_PREC)?;
+                    expr = int_binop(expr, IntBinOp::Div, expr2);
+                }
+                Ok(Token::Mod) if prec < DIV_MULT_PREC => {
+                    self.consume();
+                    let expr2 = self.expr1(ctx, DIV_MULT_PREC)?;
+                    expr = int_binop(expr, IntBinOp::Mod, expr2);
+                }
+                Ok(Token::Land) if prec < DIV_MULT_PREC => {
+                    self.consume();
+                    let expr2 = self.expr1(ctx, DIV_MULT_PREC)?;
+                    expr = int_binop(expr, IntBinOp::And, expr2);
+                }
+                Ok(Token::Lor) if prec < DIV_MULT_PREC => {
+                    self.consume();
+                    let expr2 = self.expr1(ctx, DIV_MULT_PREC)?;
+                    expr = int_binop(expr, IntBinOp::Or, expr2);
+                }
+                Ok(Token::Lxor) if prec < DIV_MULT_PREC => {
+                    self.consume();
+                    let expr2 = self.expr1(ctx, DIV_MULT_PREC)?;
+                    expr = int_binop(expr, IntBinOp::Xor, expr2);
+                }
+                Ok(Token::Lsl) if prec <= SHIFT_PREC => {
+                    self.consume();
+                    let expr2 = self.expr1(ctx, SHIFT_PREC)?;
+                    expr = int_binop(expr, IntBinOp::Lsl, expr2);
+                }
+                Ok(Token::Lsr) if prec <= SHIFT_PREC => {
+                    self.consume();
+                    let expr2 = self.expr1(ctx, SHIFT_PREC)?;
+                    expr = int_binop(expr, IntBinOp::Lsr, expr2);
+                }
+                Ok(Token::Asr) if prec <= SHIFT_PREC => {
+                    self.consume();
+                    let expr2 = self.expr1(ctx, SHIFT_PREC)?;
+                    expr = int_binop(expr, IntBinOp::Asr, expr2);
                 }
                 Ok(Token::AstDot) if prec < DIV_MULT_PREC => {
                     self.consume();
                     let expr2 = self.expr1(ctx, DIV_MULT_PREC)?;
-                    expr = Expr::FloatBinOp(Box::new(expr), FloatBinOp::Mul, Box::new(expr2));
+                    expr = float_binop(expr, FloatBinOp::Mul, expr2);
                 }
                 Ok(Token::SlashDot) if prec < DIV_MULT_PREC => {
                     self.consume();
                     let expr2 = self.expr1(ctx, DIV_MULT_PREC)?;
-                    expr = Expr::FloatBinOp(Box::new(expr), FloatBinOp::Div, Box::new(expr2));
+                    expr = float_binop(expr, FloatBinOp::Div, expr2);
                 }
                 Ok(Token::Equal) if prec < CMP_PREC => {
                     self.consume();
                     let expr2 = self.expr1(ctx, CMP_PREC)?;
-                    expr = Expr::Cmp(Box::new(expr), Cmp::Equal, Box::new(expr2));
+                    expr = cmp(expr, Cmp::Equal, expr2);
+                }
+                Ok(Token::EqualEqual) if prec < CMP_PREC => {
+                    self.consume();
+                    let expr2 = self.expr1(ctx, CMP_PREC)?;
+                    expr = phys_cmp(expr, Cmp::Equal, expr2);
+                }
+                Ok(Token::BangEqual) if prec < CMP_PREC => {
+                    self.consume();
+                    let expr2 = self.expr1(ctx, CMP_PREC)?;
+                    expr = phys_cmp(expr, Cmp::NotEqual, expr2);
                 }
                 Ok(Token::LessGreater) if prec < CMP_PREC => {
                     self.consume();
                     let expr2 = self.expr1(ctx, CMP_PREC)?;
-                    expr = Expr::Cmp(Box::new(expr), Cmp::NotEqual, Box::new(expr2));
+                    expr = cmp(expr, Cmp::NotEqual, expr2);
                 }
                 Ok(Token::Less) if prec < CMP_PREC => {
                     self.consume();
                     let expr2 = self.expr1(ctx, CMP_PREC)?;
-                    expr = Expr::Cmp(Box::new(expr), Cmp::LessThan, Box::new(expr2));
+                    expr = cmp(expr, Cmp::LessThan, expr2);
                 }
                 Ok(Token::LessEqual) if prec < CMP_PREC => {
                     self.consume();
                     let expr2 = self.expr1(ctx, CMP_PREC)?;
-                    expr = Expr::Cmp(Box::new(expr), Cmp::LessThanOrEqual, Box::new(expr2));
+                    expr = cmp(expr, Cmp::LessThanOrEqual, expr2);
                 }
                 Ok(Token::Greater) if prec <= CMP_PREC => {
                     self.consume();
                     let expr2 = self.expr1(ctx, CMP_PREC)?;
-                    expr = Expr::Cmp(Box::new(expr), Cmp::GreaterThan, Box::new(expr2));
+                    expr = cmp(expr, Cmp::GreaterThan, expr2);
                 }
                 Ok(Token::GreaterEqual) if prec <= CMP_PREC => {
                     self.consume();
                     let expr2 = self.expr1(ctx, CMP_PREC)?;
-                    expr = Expr::Cmp(Box::new(expr), Cmp::GreaterThanOrEqual, Box::new(expr2));
+                    expr = cmp(expr, Cmp::GreaterThanOrEqual, expr2);
+                }
+                Ok(Token::Caret) if prec <= CONCAT_PREC => {
+                    // `s1 ^ s2` is an application of the builtin `^`
+                    let op_span = self.tokens[self.tok_idx].span;
+                    self.consume();
+                    let expr2 = self.expr1(ctx, CONCAT_PREC)?;
+                    let span = expr.span.to(expr2.span);
+                    let fun = Expr::new(ExprKind::Var(ctx.fresh_user_var("^")), op_span);
+                    expr = Expr::new(
+                        ExprKind::App {
+                            fun: Box::new(fun),
+                            args: vec![expr, expr2],
+                        },
+                        span,
+                    );
+                }
+                Ok(Token::ColonColon) if prec <= CONS_PREC => {
+                    self.consume();
+                    let expr2 = self.expr1(ctx, CONS_PREC)?;
+                    let span = expr.span.to(expr2.span);
+                    expr = Expr::new(ExprKind::Cons(Box::new(expr), Box::new(expr2)), span);
+                }
+                Ok(Token::DoubleAmp) if prec <= AND_PREC => {
+                    self.consume();
+                    let expr2 = self.expr1(ctx, AND_PREC)?;
+                    let span = expr.span.to(expr2.span);
+                    expr = Expr::new(ExprKind::And(Box::new(expr), Box::new(expr2)), span);
+                }
+                Ok(Token::DoublePipe) if prec <= OR_PREC => {
+                    self.consume();
+                    let expr2 = self.expr1(ctx, OR_PREC)?;
+                    let span = expr.span.to(expr2.span);
+                    expr = Expr::new(ExprKind::Or(Box::new(expr), Box::new(expr2)), span);
+                }
+                Ok(Token::ColonEqual) if prec <= LESS_MINUS_PREC => {
+                    self.consume();
+                    let expr2 = self.expr1(ctx, LESS_MINUS_PREC)?;
+                    let span = expr.span.to(expr2.span);
+                    expr = Expr::new(ExprKind::Assign(Box::new(expr), Box::new(expr2)), span);
                 }
                 Ok(Token::Comma) if prec <= TUPLE_PREC => {
                     self.consume();
                     let expr2 = self.expr1(ctx, COMMA_PREC)?;
-                    match expr {
-                        Expr::Tuple(ref mut vec) => {
+                    let span = expr.span.to(expr2.span);
+                    match expr.kind {
+                        ExprKind::Tuple(ref mut vec) if parsing_tuple => {
                             vec.push(expr2);
+                            expr.span = span;
                         }
                         _ => {
-                            expr = Expr::Tuple(vec![expr, expr2]);
+                            parsing_tuple = true;
+                            expr = Expr::new(ExprKind::Tuple(vec![expr, expr2]), span);
                         }
                     }
                 }
                 Ok(Token::Dot) if prec < DOT_PREC => {
                     self.consume();
+                    if let Ok(Token::Id(field)) = self.next_token() {
+                        let field = ctx.fresh_user_var(field);
+                        self.consume();
+                        let field_span = self.span_from(expr.span);
+                        expr = match self.next_token() {
+                            Ok(Token::LessMinus) => {
+                                self.consume();
+                                let val = self.expr1(ctx, LESS_MINUS_PREC)?;
+                                map_last_arg(expr, parsing_app, |record| {
+                                    let span = record.span.to(val.span);
+                                    Expr::new(
+                                        ExprKind::SetField(Box::new(record), field, Box::new(val)),
+                                        span,
+                                    )
+                                })
+                            }
+                            _ => map_last_arg(expr, parsing_app, |record| {
+                                let span = record.span.to(field_span);
+                                Expr::new(ExprKind::GetField(Box::new(record), field), span)
+                            }),
+                        };
+                        continue;
+                    }
                     self.expect(Token::LParen, "'('")?;
                     // Parse everything until ')'
                     let expr1 = self.expr1(ctx, INIT_PREC)?;
@@ -398,55 +927,95 @@ impl<'a> Parser<'a> {
                         Ok(Token::LessMinus) => {
                             self.consume();
                             let expr2 = self.expr1(ctx, LESS_MINUS_PREC)?;
-                            match expr {
-                                Expr::App { mut args, fun } if parsing_app => {
+                            match expr.kind {
+                                ExprKind::App { mut args, fun } if parsing_app => {
                                     let arg = args.pop().unwrap();
-                                    expr = Expr::App {
-                                        fun,
-                                        args: vec![Expr::Put(
+                                    let put_span = arg.span.to(expr2.span);
+                                    let put = Expr::new(
+                                        ExprKind::Put(
                                             Box::new(arg),
                                             Box::new(expr1),
                                             Box::new(expr2),
-                                        )],
-                                    };
+                                        ),
+                                        put_span,
+                                    );
+                                    expr = Expr::new(
+                                        ExprKind::App {
+                                            args: vec![put],
+                                            fun,
+                                        },
+                                        expr.span.to(put_span),
+                                    );
                                 }
-                                _ => {
-                                    expr =
-                                        Expr::Put(Box::new(expr), Box::new(expr1), Box::new(expr2));
+                                kind => {
+                                    let span = expr.span.to(expr2.span);
+                                    expr = Expr::new(
+                                        ExprKind::Put(
+                                            Box::new(Expr::new(kind, expr.span)),
+                                            Box::new(expr1),
+                                            Box::new(expr2),
+                                        ),
+                                        span,
+                                    );
                                 }
                             }
                         }
-                        _ => match expr {
-                            Expr::App { mut args, fun } if parsing_app => {
+                        _ => match expr.kind {
+                            ExprKind::App { mut args, fun } if parsing_app => {
                                 let arg = args.pop().unwrap();
-                                expr = Expr::App {
-                                    fun,
-                                    args: vec![Expr::Get(Box::new(arg), Box::new(expr1))],
-                                };
+                                let get_span = self.span_from(arg.span);
+                                let get = Expr::new(
+                                    ExprKind::Get(Box::new(arg), Box::new(expr1)),
+                                    get_span,
+                                );
+                                expr = Expr::new(
+                                    ExprKind::App {
+                                        args: vec![get],
+                                        fun,
+                                    },
+                                    expr.span.to(get_span),
+                                );
                             }
-                            _ => {
-                                expr = Expr::Get(Box::new(expr), Box::new(expr1));
+                            kind => {
+                                let span = self.span_from(expr.span);
+                                expr = Expr::new(
+                                    ExprKind::Get(
+                                        Box::new(Expr::new(kind, expr.span)),
+                                        Box::new(expr1),
+                                    ),
+                                    span,
+                                );
                             }
                         },
                     }
                 }
-                Ok(_) if prec <= APP_PREC => match self.expr0(ctx, APP_PREC) {
-                    Err(_) => {
-                        break;
-                    }
-                    Ok(expr_) => match expr {
-                        Expr::App { ref mut args, .. } if parsing_app => {
-                            args.push(expr_);
+                // 'let' can't be an argument, it starts the next phrase in top-level definitions
+                Ok(tok) if prec <= APP_PREC && *tok != Token::Let => {
+                    match self.expr0(ctx, APP_PREC) {
+                        Err(_) => {
+                            break;
                         }
-                        _ => {
-                            parsing_app = true;
-                            expr = Expr::App {
-                                fun: Box::new(expr),
-                                args: vec![expr_],
-                            };
+                        Ok(expr_) => {
+                            let span = expr.span.to(expr_.span);
+                            match expr.kind {
+                                ExprKind::App { ref mut args, .. } if parsing_app => {
+                                    args.push(expr_);
+                                    expr.span = span;
+                                }
+                                _ => {
+                                    parsing_app = true;
+                                    expr = Expr::new(
+                                        ExprKind::App {
+                                            fun: Box::new(expr),
+                                            args: vec![expr_],
+                                        },
+                                        span,
+                                    );
+                                }
+                            }
                         }
-                    },
-                },
+                    }
+                }
                 _ => {
                     break;
                 }
@@ -457,41 +1026,686 @@ impl<'a> Parser<'a> {
     }
 
     // Entry point for parsing
-    pub fn expr(&mut self, ctx: &mut Ctx) -> Result<Expr, ParseErr> {
-        let ret = self.expr1(ctx, INIT_PREC)?;
-        match self.next_token() {
-            Err(_) => Ok(ret),
-            Ok(next) => Err(ParseErr::Unexpected {
-                seen: next.clone(),
-                expected: "EOF",
-            }),
-        }
-    }
+    pub fn phrases(&mut self, ctx: &mut Ctx) -> Result<Vec<Phrase>, ParseErr> {
+        let mut phrases = vec![];
+        loop {
+            while let Ok(Token::DoubleSemicolon) = self.next_token() {
+                self.consume();
+            }
 
-    fn expect(&mut self, tok: Token, str: &'static str) -> Result<(), ParseErr> {
-        let next_token = self.next_token()?;
-        if next_token == &tok {
-            self.consume();
-            Ok(())
-        } else {
-            Err(ParseErr::Unexpected {
-                seen: next_token.clone(),
-                expected: str,
-            })
+            match self.next_token() {
+                Err(_) => {
+                    return Ok(phrases);
+                }
+                Ok(Token::Let) => {
+                    let start = self.next_span();
+                    self.consume();
+                    let binding = self.let_binding(ctx)?;
+                    match self.next_token() {
+                        Ok(Token::In) => {
+                            let expr = self.let_body(ctx, binding, start)?;
+                            phrases.push(Phrase::Expr(expr));
+                        }
+                        _ => {
+                            phrases.push(binding);
+                        }
+                    }
+                }
+                Ok(Token::Type) => {
+                    self.consume();
+                    let mut decls = vec![self.type_decl(ctx)?];
+                    while let Ok(Token::And) = self.next_token() {
+                        self.consume();
+                        decls.push(self.type_decl(ctx)?);
+                    }
+                    phrases.push(Phrase::Type { decls });
+                }
+                Ok(Token::Exception) => {
+                    self.consume();
+                    phrases.push(Phrase::Exception(self.con_decl(ctx)?));
+                }
+                Ok(_) => {
+                    let expr = self.expr1(ctx, INIT_PREC)?;
+                    phrases.push(Phrase::Expr(expr));
+                }
+            }
+
+            match self.next_token() {
+                Err(_)
+                | Ok(Token::DoubleSemicolon)
+                | Ok(Token::Let)
+                | Ok(Token::Type)
+                | Ok(Token::Exception) => {}
+                Ok(next) => {
+                    return Err(ParseErr::Unexpected {
+                        seen: next.clone(),
+                        expected: "';;', 'let', 'type', 'exception', or EOF",
+                        span: self.next_span(),
+                    });
+                }
+            }
         }
     }
 
-    fn expect_id(&mut self) -> Result<&str, ParseErr> {
-        // NOTE: 'consume' and 'next_token' inlined below to work around borrowchk issues
-        match self.tokens.get(self.tok_idx) {
-            None => Err(ParseErr::EndOfInput),
-            Some(Token::Id(id)) => {
-                self.tok_idx += 1;
-                Ok(id)
-            }
-            Some(other) => Err(ParseErr::Unexpected {
+    // Parse a `let` binding until 'in', after 'let'. Returned phrase is never `Phrase::Type`,
+    // `Phrase::Exception`, or `Phrase::Expr`.
+    fn let_binding(&mut self, ctx: &mut Ctx) -> Result<Phrase, ParseErr> {
+        match self.next_token()? {
+            Token::Rec => {
+                self.consume();
+                let mut defs = vec![self.fun_def(ctx)?];
+                while let Ok(Token::And) = self.next_token() {
+                    self.consume();
+                    defs.push(self.fun_def(ctx)?);
+                }
+                Ok(Phrase::LetRec { defs })
+            }
+            Token::LParen | Token::Underscore => {
+                let pat = self.bndr_pat0(ctx)?;
+                self.expect(Token::Equal, "'='")?;
+                // Parse everything until 'in'
+                let rhs = self.expr1(ctx, INIT_PREC)?;
+                Ok(Phrase::LetTuple { pat, rhs })
+            }
+            Token::LBrace => {
+                self.consume();
+                let mut fields = vec![];
+                loop {
+                    match self.next_token()? {
+                        // `_` for the fields not mentioned
+                        Token::Underscore => {
+                            self.consume();
+                        }
+                        _ => {
+                            let field = self.expect_id()?;
+                            let field = ctx.fresh_user_var(field);
+                            // `{ x }` is `{ x = x }`
+                            let bndr = match self.next_token() {
+                                Ok(Token::Equal) => {
+                                    self.consume();
+                                    self.expect_id()?.to_owned()
+                                }
+                                _ => ctx.var_name(field).to_string(),
+                            };
+                            fields.push((field, ctx.fresh_user_var(&bndr)));
+                        }
+                    }
+                    if !self.seq_sep(Token::RBrace, "';' or '}'")? {
+                        break;
+                    }
+                }
+                self.expect(Token::Equal, "'='")?;
+                // Parse everything until 'in'
+                let rhs = self.expr1(ctx, INIT_PREC)?;
+                Ok(Phrase::LetRecord { fields, rhs })
+            }
+            Token::Id(var) => {
+                let bndr = ctx.fresh_user_var(var);
+                self.consume();
+                let ty = self.ret_annot()?;
+                self.expect(Token::Equal, "'='")?;
+                // Parse everything until 'in'
+                let rhs = annot(self.expr1(ctx, INIT_PREC)?, ty);
+                Ok(Phrase::Let { bndr, rhs })
+            }
+            other => {
+                Err(ParseErr::Unexpected {
+                    // TODO: remove cloning
+                    seen: other.clone(),
+                    expected: "'rec', '(', '{', '_', or identifier",
+                    span: self.next_span(),
+                })
+            }
+        }
+    }
+
+    // Parse 'in <expr>' after a `let` binding. `start` is the span of the 'let' token.
+    fn let_body(&mut self, ctx: &mut Ctx, binding: Phrase, start: Span) -> Result<Expr, ParseErr> {
+        self.expect(Token::In, "'in'")?;
+        let kind = match binding {
+            Phrase::LetRec { defs } => {
+                let body = Box::new(self.expr1(ctx, LET_PREC)?);
+                ExprKind::LetRec { defs, body }
+            }
+            Phrase::LetTuple { pat, rhs } => {
+                let body = self.expr1(ctx, IN_PREC)?;
+                ExprKind::LetTuple {
+                    pat,
+                    rhs: Box::new(rhs),
+                    body: Box::new(body),
+                }
+            }
+            Phrase::LetRecord { fields, rhs } => {
+                let body = self.expr1(ctx, IN_PREC)?;
+                ExprKind::LetRecord {
+                    fields,
+                    rhs: Box::new(rhs),
+                    body: Box::new(body),
+                }
+            }
+            Phrase::Let { bndr, rhs } => {
+                let body = Box::new(self.expr1(ctx, IN_PREC)?);
+                ExprKind::Let {
+                    bndr,
+                    rhs: Box::new(rhs),
+                    body,
+                }
+            }
+            Phrase::Type { .. } | Phrase::Exception(_) | Phrase::Expr(_) => {
+                panic!("let_body: non-let phrase")
+            }
+        };
+        Ok(Expr::new(kind, self.span_from(start)))
+    }
+
+    // <ident> <ident>+ = <expr>, after 'let rec' or 'and'
+    fn fun_def(&mut self, ctx: &mut Ctx) -> Result<FunDef, ParseErr> {
+        let bndr = self.expect_id()?;
+        let bndr = ctx.fresh_user_var(bndr);
+        let (args, annots, pats) = self.fun_args(ctx, &Token::Equal, "binder, ':', or '='")?;
+        let ret_ty = self.ret_annot()?;
+        self.expect(Token::Equal, "'='")?;
+        // Parse everything until 'and' or 'in'
+        let rhs = annot(self.expr1(ctx, INIT_PREC)?, ret_ty);
+        let rhs = Box::new(annot_args(ctx, annots, pat_args(pats, rhs)));
+        Ok(FunDef { bndr, args, rhs })
+    }
+
+    // (: <ty>)?, before the '=' of a `let` binding
+    fn ret_annot(&mut self) -> Result<Option<TyExpr>, ParseErr> {
+        if let Ok(Token::Colon) = self.next_token() {
+            self.consume();
+            Ok(Some(self.ty()?))
+        } else {
+            Ok(None)
+        }
+    }
+
+    // do <expr> done
+    fn loop_body(&mut self, ctx: &mut Ctx) -> Result<Expr, ParseErr> {
+        self.expect(Token::Do, "'do'")?;
+        // Parse everything until 'done'
+        let body = self.expr1(ctx, INIT_PREC)?;
+        self.expect(Token::Done, "'done'")?;
+        Ok(body)
+    }
+
+    // Arguments of a function until `end` or ':', which are not consumed. Returns the arguments,
+    // annotated arguments as `Annot`s of uses of the arguments, and the patterns of tuple and unit
+    // arguments, which are bound to generated arguments.
+    #[allow(clippy::type_complexity)]
+    fn fun_args(
+        &mut self, ctx: &mut Ctx, end: &Token, expected: &'static str,
+    ) -> Result<(Vec<VarId>, Vec<Expr>, Vec<(VarId, Pat)>), ParseErr> {
+        let mut args = vec![];
+        let mut annots = vec![];
+        let mut pats = vec![];
+        loop {
+            match self.next_token()? {
+                Token::Underscore => {
+                    args.push(ctx.fresh_generated_var(CompilerPhase::Parser));
+                    self.consume();
+                }
+                Token::Id(arg) => {
+                    args.push(ctx.fresh_user_var(arg));
+                    self.consume();
+                }
+                // ( <ident> : <ty> ), or ( <bndr_pat>? )
+                Token::LParen => {
+                    self.consume();
+                    let start = self.next_span();
+                    let pat = match self.next_token()? {
+                        Token::RParen => Pat::Unit,
+                        _ => self.bndr_pat(ctx)?,
+                    };
+                    match pat {
+                        Pat::Var(arg) => {
+                            args.push(arg);
+                            if let Ok(Token::Colon) = self.next_token() {
+                                self.consume();
+                                let use_ = ctx.fresh_user_var(&ctx.var_name(arg));
+                                let use_ = Expr::new(ExprKind::Var(use_), start);
+                                let ty = self.ty()?;
+                                annots.push(Expr::new(ExprKind::Annot(Box::new(use_), ty), start));
+                            }
+                        }
+                        pat => {
+                            let arg = ctx.fresh_generated_var(CompilerPhase::Parser);
+                            args.push(arg);
+                            pats.push((arg, pat));
+                        }
+                    }
+                    self.expect(Token::RParen, "')'")?;
+                }
+                tok if tok == end || *tok == Token::Colon => {
+                    return Ok((args, annots, pats));
+                }
+                other => {
+                    return Err(ParseErr::Unexpected {
+                        expected,
+                        seen: other.clone(),
+                        span: self.next_span(),
+                    });
+                }
+            }
+        }
+    }
+
+    // <bndr_pat0> (, <bndr_pat0>)*, in `let` bindings and function arguments
+    fn bndr_pat(&mut self, ctx: &mut Ctx) -> Result<Pat, ParseErr> {
+        let pat = self.bndr_pat0(ctx)?;
+        if let Ok(Token::Comma) = self.next_token() {
+            let mut pats = vec![pat];
+            while let Ok(Token::Comma) = self.next_token() {
+                self.consume();
+                pats.push(self.bndr_pat0(ctx)?);
+            }
+            Ok(Pat::Tuple(pats))
+        } else {
+            Ok(pat)
+        }
+    }
+
+    // _ | () | <ident> | ( <bndr_pat> )
+    fn bndr_pat0(&mut self, ctx: &mut Ctx) -> Result<Pat, ParseErr> {
+        match self.next_token()? {
+            Token::Underscore => {
+                self.consume();
+                Ok(Pat::Wildcard)
+            }
+            Token::Id(var) => {
+                let var = ctx.fresh_user_var(var);
+                self.consume();
+                Ok(Pat::Var(var))
+            }
+            Token::LParen => {
+                self.consume();
+                if let Token::RParen = self.next_token()? {
+                    self.consume();
+                    return Ok(Pat::Unit);
+                }
+                let pat = self.bndr_pat(ctx)?;
+                self.expect(Token::RParen, "')'")?;
+                Ok(pat)
+            }
+            other => Err(ParseErr::Unexpected {
+                seen: other.clone(),
+                expected: "'_', '(', or identifier",
+                span: self.next_span(),
+            }),
+        }
+    }
+
+    // Cases of a `match` or `try`, after 'with'
+    fn cases(&mut self, ctx: &mut Ctx) -> Result<Vec<Case>, ParseErr> {
+        // '|' before the first case is optional
+        if let Ok(Token::Pipe) = self.next_token() {
+            self.consume();
+        }
+        let mut cases = vec![self.case(ctx)?];
+        while let Ok(Token::Pipe) = self.next_token() {
+            self.consume();
+            cases.push(self.case(ctx)?);
+        }
+        Ok(cases)
+    }
+
+    // <pat> -> <expr>, in a `match` or `try`
+    fn case(&mut self, ctx: &mut Ctx) -> Result<Case, ParseErr> {
+        let start = self.next_span();
+        let pat = self.pat(ctx)?;
+        let span = self.span_from(start);
+        self.expect(Token::MinusGreater, "'->'")?;
+        // Parse everything until the next '|'. Nested matches take the cases that follow, as in
+        // OCaml.
+        let rhs = self.expr1(ctx, INIT_PREC)?;
+        Ok(Case { pat, span, rhs })
+    }
+
+    // <pat> (, <pat>)*
+    fn pat(&mut self, ctx: &mut Ctx) -> Result<Pat, ParseErr> {
+        let pat = self.pat2(ctx)?;
+        if let Ok(Token::Comma) = self.next_token() {
+            let mut pats = vec![pat];
+            while let Ok(Token::Comma) = self.next_token() {
+                self.consume();
+                pats.push(self.pat2(ctx)?);
+            }
+            Ok(Pat::Tuple(pats))
+        } else {
+            Ok(pat)
+        }
+    }
+
+    // <pat1> (:: <pat1>)*, right associative
+    fn pat2(&mut self, ctx: &mut Ctx) -> Result<Pat, ParseErr> {
+        let pat = self.pat1(ctx)?;
+        if let Ok(Token::ColonColon) = self.next_token() {
+            self.consume();
+            let tl = self.pat2(ctx)?;
+            Ok(Pat::Cons(Box::new(pat), Box::new(tl)))
+        } else {
+            Ok(pat)
+        }
+    }
+
+    // Constructor applications and `pat0`s
+    fn pat1(&mut self, ctx: &mut Ctx) -> Result<Pat, ParseErr> {
+        match self.next_token()? {
+            Token::UId(con) => {
+                let con = ctx.fresh_user_var(con);
+                self.consume();
+                let arg = match self.next_token() {
+                    Ok(Token::Int(_))
+                    | Ok(Token::Minus)
+                    | Ok(Token::Bool(_))
+                    | Ok(Token::Char(_))
+                    | Ok(Token::Underscore)
+                    | Ok(Token::Id(_))
+                    | Ok(Token::UId(_))
+                    | Ok(Token::LParen)
+                    | Ok(Token::LBracket) => Some(Box::new(self.pat0(ctx)?)),
+                    _ => None,
+                };
+                Ok(Pat::Con { con, arg })
+            }
+            _ => self.pat0(ctx),
+        }
+    }
+
+    // Patterns other than tuples without parens and constructor applications
+    fn pat0(&mut self, ctx: &mut Ctx) -> Result<Pat, ParseErr> {
+        match self.next_token()? {
+            Token::Int(i) => {
+                let i = *i;
+                self.consume();
+                Ok(Pat::Int(i))
+            }
+            Token::Minus => {
+                self.consume();
+                match self.next_token()? {
+                    Token::Int(i) => {
+                        let i = *i;
+                        self.consume();
+                        Ok(Pat::Int(-i))
+                    }
+                    other => Err(ParseErr::Unexpected {
+                        seen: other.clone(),
+                        expected: "integer",
+                        span: self.next_span(),
+                    }),
+                }
+            }
+            Token::Bool(b) => {
+                let b = *b;
+                self.consume();
+                Ok(Pat::Bool(b))
+            }
+            Token::Char(c) => {
+                let c = *c;
+                self.consume();
+                Ok(Pat::Char(c))
+            }
+            Token::Underscore => {
+                self.consume();
+                Ok(Pat::Wildcard)
+            }
+            Token::Id(var) => {
+                let var = ctx.fresh_user_var(var);
+                self.consume();
+                Ok(Pat::Var(var))
+            }
+            Token::UId(con) => {
+                let con = ctx.fresh_user_var(con);
+                self.consume();
+                Ok(Pat::Con { con, arg: None })
+            }
+            Token::LParen => {
+                self.consume();
+                if let Token::RParen = self.next_token()? {
+                    self.consume();
+                    return Ok(Pat::Unit);
+                }
+                let pat = self.pat(ctx)?;
+                self.expect(Token::RParen, "')'")?;
+                Ok(pat)
+            }
+            Token::LBracket => {
+                self.consume();
+                let mut elems = vec![];
+                if let Token::RBracket = self.next_token()? {
+                    self.consume();
+                } else {
+                    loop {
+                        elems.push(self.pat(ctx)?);
+                        if !self.seq_sep(Token::RBracket, "';' or ']'")? {
+                            break;
+                        }
+                    }
+                }
+                Ok(elems.into_iter().rev().fold(Pat::Nil, |tl, hd| {
+                    Pat::Cons(Box::new(hd), Box::new(tl))
+                }))
+            }
+            other => Err(ParseErr::Unexpected {
+                seen: other.clone(),
+                expected: "pattern",
+                span: self.next_span(),
+            }),
+        }
+    }
+
+    // <ident> = <type_decl_kind>, after 'type' or 'and'
+    fn type_decl(&mut self, ctx: &mut Ctx) -> Result<TypeDecl, ParseErr> {
+        let name = self.expect_id()?.to_owned();
+        self.expect(Token::Equal, "'='")?;
+        if let Ok(Token::LBrace) = self.next_token() {
+            self.consume();
+            let mut fields = vec![];
+            loop {
+                fields.push(self.field_decl(ctx)?);
+                if !self.seq_sep(Token::RBrace, "';' or '}'")? {
+                    break;
+                }
+            }
+            return Ok(TypeDecl {
+                name,
+                kind: TypeDeclKind::Record(fields),
+            });
+        }
+        // '|' before the first constructor is optional
+        match self.next_token()? {
+            Token::Pipe => self.consume(),
+            Token::UId(_) => {}
+            _ => {
+                let ty = self.ty()?;
+                return Ok(TypeDecl {
+                    name,
+                    kind: TypeDeclKind::Alias(ty),
+                });
+            }
+        }
+        let mut cons = vec![self.con_decl(ctx)?];
+        while let Ok(Token::Pipe) = self.next_token() {
+            self.consume();
+            cons.push(self.con_decl(ctx)?);
+        }
+        Ok(TypeDecl {
+            name,
+            kind: TypeDeclKind::Variant(cons),
+        })
+    }
+
+    // (mutable)? <field> : <ty>
+    fn field_decl(&mut self, ctx: &mut Ctx) -> Result<FieldDecl, ParseErr> {
+        let mutable = match self.next_token()? {
+            Token::Mutable => {
+                self.consume();
+                true
+            }
+            _ => false,
+        };
+        let field = self.expect_id()?;
+        let field = ctx.fresh_user_var(field);
+        self.expect(Token::Colon, "':'")?;
+        let ty = self.ty()?;
+        Ok(FieldDecl { field, mutable, ty })
+    }
+
+    // Parse the ';' or `close` after a field in a record expression, pattern, or declaration, or an
+    // element of a list literal or pattern. Returns whether there are more elements. ';' before
+    // `close` is allowed.
+    fn seq_sep(&mut self, close: Token, expected: &'static str) -> Result<bool, ParseErr> {
+        match self.next_token()? {
+            Token::Semicolon => {
+                self.consume();
+                if *self.next_token()? == close {
+                    self.consume();
+                    Ok(false)
+                } else {
+                    Ok(true)
+                }
+            }
+            tok if *tok == close => {
+                self.consume();
+                Ok(false)
+            }
+            other => Err(ParseErr::Unexpected {
+                seen: other.clone(),
+                expected,
+                span: self.next_span(),
+            }),
+        }
+    }
+
+    // <con> (of <ty> (* <ty>)*)?, in a type declaration or after 'exception'
+    fn con_decl(&mut self, ctx: &mut Ctx) -> Result<ConDecl, ParseErr> {
+        let con = match self.next_token()? {
+            Token::UId(con) => ctx.fresh_user_var(con),
+            other => {
+                return Err(ParseErr::Unexpected {
+                    seen: other.clone(),
+                    expected: "constructor",
+                    span: self.next_span(),
+                });
+            }
+        };
+        self.consume();
+        let args = match self.next_token() {
+            Ok(Token::Of) => {
+                self.consume();
+                self.ty_tuple_elems()?
+            }
+            _ => vec![],
+        };
+        Ok(ConDecl { con, args })
+    }
+
+    // <ty> (-> <ty>)*
+    fn ty(&mut self) -> Result<TyExpr, ParseErr> {
+        let start = self.next_span();
+        let ty = self.ty_tuple()?;
+        if let Ok(Token::MinusGreater) = self.next_token() {
+            let mut args = vec![ty];
+            while let Ok(Token::MinusGreater) = self.next_token() {
+                self.consume();
+                args.push(self.ty_tuple()?);
+            }
+            let ret = Box::new(args.pop().unwrap());
+            Ok(TyExpr::new(TyExprKind::Fun { args, ret }, self.span_from(start)))
+        } else {
+            Ok(ty)
+        }
+    }
+
+    // <ty> (* <ty>)*
+    fn ty_tuple(&mut self) -> Result<TyExpr, ParseErr> {
+        let start = self.next_span();
+        let mut tys = self.ty_tuple_elems()?;
+        if tys.len() == 1 {
+            Ok(tys.pop().unwrap())
+        } else {
+            Ok(TyExpr::new(TyExprKind::Tuple(tys), self.span_from(start)))
+        }
+    }
+
+    fn ty_tuple_elems(&mut self) -> Result<Vec<TyExpr>, ParseErr> {
+        let mut tys = vec![self.ty_app()?];
+        while let Ok(Token::Ast) = self.next_token() {
+            self.consume();
+            tys.push(self.ty_app()?);
+        }
+        Ok(tys)
+    }
+
+    // Type constructor applications, e.g. `int array array`, and types in parens
+    fn ty_app(&mut self) -> Result<TyExpr, ParseErr> {
+        let start = self.next_span();
+        let mut ty = match self.next_token()? {
+            Token::Id(name) => {
+                let name = name.clone();
+                self.consume();
+                TyExpr::new(TyExprKind::Con { args: vec![], name }, start)
+            }
+            Token::LParen => {
+                self.consume();
+                let ty = self.ty()?;
+                self.expect(Token::RParen, "')'")?;
+                TyExpr::new(ty.kind, self.span_from(start))
+            }
+            other => {
+                return Err(ParseErr::Unexpected {
+                    seen: other.clone(),
+                    expected: "type",
+                    span: start,
+                });
+            }
+        };
+        while let Ok(Token::Id(name)) = self.next_token() {
+            let name = name.clone();
+            self.consume();
+            ty = TyExpr::new(
+                TyExprKind::Con {
+                    args: vec![ty],
+                    name,
+                },
+                self.span_from(start),
+            );
+        }
+        Ok(ty)
+    }
+
+    fn expect(&mut self, tok: Token, str: &'static str) -> Result<(), ParseErr> {
+        let next_token = self.next_token()?;
+        if next_token == &tok {
+            self.consume();
+            Ok(())
+        } else {
+            Err(ParseErr::Unexpected {
+                seen: next_token.clone(),
+                expected: str,
+                span: self.next_span(),
+            })
+        }
+    }
+
+    fn expect_id(&mut self) -> Result<&str, ParseErr> {
+        // NOTE: 'consume' and 'next_token' inlined below to work around borrowchk issues
+        match self.tokens.get(self.tok_idx) {
+            None => Err(ParseErr::EndOfInput {
+                span: self.eof_span(),
+            }),
+            Some(Spanned {
+                node: Token::Id(id),
+                ..
+            }) => {
+                self.tok_idx += 1;
+                Ok(id)
+            }
+            Some(Spanned { node: other, span }) => Err(ParseErr::Unexpected {
                 seen: other.clone(),
                 expected: "identifier",
+                span: *span,
             }),
         }
     }
@@ -502,8 +1716,532 @@ impl<'a> Parser<'a> {
 
     fn next_token(&self) -> Result<&Token, ParseErr> {
         match self.tokens.get(self.tok_idx) {
-            None => Err(ParseErr::EndOfInput),
-            Some(next) => Ok(next),
+            None => Err(ParseErr::EndOfInput {
+                span: self.eof_span(),
+            }),
+            Some(next) => Ok(&next.node),
+        }
+    }
+
+    // Span of the next token, or an empty span at the end of the input
+    fn next_span(&self) -> Span {
+        match self.tokens.get(self.tok_idx) {
+            None => self.eof_span(),
+            Some(next) => next.span,
+        }
+    }
+
+    // Span from the start of `start` to the end of the last consumed token
+    fn span_from(&self, start: Span) -> Span {
+        match self.tok_idx.checked_sub(1).and_then(|idx| self.tokens.get(idx)) {
+            None => start,
+            Some(prev) => start.to(prev.span),
+        }
+    }
+
+    fn eof_span(&self) -> Span {
+        match self.tokens.last() {
+            None => Span::default(),
+            Some(last) => Span {
+                start: last.span.end,
+                end: last.span.end,
+            },
+        }
+    }
+}
+
+// Dots bind tighter than application: in `f x.y` the field access applies to the last argument.
+// Apply `f` to the last argument when `expr` is an application being parsed, to `expr` otherwise.
+fn map_last_arg(expr: Expr, parsing_app: bool, f: impl FnOnce(Expr) -> Expr) -> Expr {
+    match expr.kind {
+        ExprKind::App { fun, mut args } if parsing_app => {
+            let arg = f(args.pop().unwrap());
+            let span = expr.span.to(arg.span);
+            args.push(arg);
+            Expr::new(ExprKind::App { fun, args }, span)
+        }
+        kind => f(Expr::new(kind, expr.span)),
+    }
+}
+
+fn int_binop(e1: Expr, op: IntBinOp, e2: Expr) -> Expr {
+    let span = e1.span.to(e2.span);
+    Expr::new(ExprKind::IntBinOp(Box::new(e1), op, Box::new(e2)), span)
+}
+
+fn float_binop(e1: Expr, op: FloatBinOp, e2: Expr) -> Expr {
+    let span = e1.span.to(e2.span);
+    Expr::new(ExprKind::FloatBinOp(Box::new(e1), op, Box::new(e2)), span)
+}
+
+fn cmp(e1: Expr, cmp: Cmp, e2: Expr) -> Expr {
+    let span = e1.span.to(e2.span);
+    Expr::new(ExprKind::Cmp(Box::new(e1), cmp, Box::new(e2)), span)
+}
+
+fn phys_cmp(e1: Expr, cmp: Cmp, e2: Expr) -> Expr {
+    let span = e1.span.to(e2.span);
+    Expr::new(ExprKind::PhysCmp(Box::new(e1), cmp, Box::new(e2)), span)
+}
+
+fn annot(expr: Expr, ty: Option<TyExpr>) -> Expr {
+    match ty {
+        None => expr,
+        Some(ty) => {
+            let span = expr.span;
+            Expr::new(ExprKind::Annot(Box::new(expr), ty), span)
+        }
+    }
+}
+
+// Check annotated arguments of a function before the body, as in `(x : int); <body>`
+fn annot_args(ctx: &mut Ctx, annots: Vec<Expr>, body: Expr) -> Expr {
+    annots.into_iter().rev().fold(body, |body, annot| {
+        let span = body.span;
+        Expr::new(
+            ExprKind::Let {
+                bndr: ctx.fresh_generated_var(CompilerPhase::Parser),
+                rhs: Box::new(annot),
+                body: Box::new(body),
+            },
+            span,
+        )
+    })
+}
+
+// Bind the patterns of tuple and unit arguments of a function before the body, as in
+// `let <pat> = <arg> in <body>`
+fn pat_args(pats: Vec<(VarId, Pat)>, body: Expr) -> Expr {
+    pats.into_iter().rev().fold(body, |body, (arg, pat)| {
+        let span = body.span;
+        Expr::new(
+            ExprKind::LetTuple {
+                pat,
+                rhs: Box::new(Expr::new(ExprKind::Var(arg), span)),
+                body: Box::new(body),
+            },
+            span,
+        )
+    })
+}
+
+// Parse a program in tests. Returns the context for the names of the variables.
+#[cfg(test)]
+fn parse_phrases(src: &str) -> (Ctx, Vec<Phrase>) {
+    let mut ctx = Default::default();
+    let tokens = crate::lexer::tokenize(src).unwrap();
+    let phrases = parse(&mut ctx, &tokens).unwrap();
+    (ctx, phrases)
+}
+
+// Parse a program with a single expression in tests
+#[cfg(test)]
+fn parse_expr(src: &str) -> Expr {
+    let mut phrases = parse_phrases(src).1;
+    match (phrases.pop(), phrases.is_empty()) {
+        (Some(Phrase::Expr(expr)), true) => expr,
+        other => panic!("{:#?}", other),
+    }
+}
+
+#[test]
+fn parser_span_test() {
+    let expr = parse_expr("let x = f 1 in\n(x +. 2.0)");
+    assert_eq!(expr.span, Span::new(0, 25));
+    match expr.kind {
+        ExprKind::Let { rhs, body, .. } => {
+            assert_eq!(rhs.span, Span::new(8, 11));
+            assert_eq!(body.span, Span::new(15, 25));
+        }
+        other => panic!("{:#?}", other),
+    }
+
+    // Spans of functions and their arguments don't include the following arguments
+    let expr = parse_expr("print_int (foo x 2)");
+    assert_eq!(expr.span, Span::new(0, 19));
+    match expr.kind {
+        ExprKind::App { fun, args } => {
+            assert_eq!(fun.span, Span::new(0, 9));
+            match &args[0].kind {
+                ExprKind::App { fun, args } => {
+                    assert_eq!(fun.span, Span::new(11, 14));
+                    assert_eq!(args[0].span, Span::new(15, 16));
+                    assert_eq!(args[1].span, Span::new(17, 18));
+                }
+                other => panic!("{:#?}", other),
+            }
+        }
+        other => panic!("{:#?}", other),
+    }
+
+    let expr = parse_expr("nope, 2, x");
+    assert_eq!(expr.span, Span::new(0, 10));
+    match expr.kind {
+        ExprKind::Tuple(elems) => {
+            let spans: Vec<Span> = elems.iter().map(|elem| elem.span).collect();
+            assert_eq!(spans, vec![Span::new(0, 4), Span::new(6, 7), Span::new(9, 10)]);
+        }
+        other => panic!("{:#?}", other),
+    }
+}
+
+#[test]
+fn parser_phrases_test() {
+    let phrases =
+        parse_phrases("let x = f 1\nlet rec g y = y and h z = z;;\nlet () = g x;; print_int x;;").1;
+    match phrases.as_slice() {
+        [Phrase::Let { .. }, Phrase::LetRec { defs }, Phrase::LetTuple { pat, .. }, Phrase::Expr(_)] =>
+        {
+            assert_eq!(defs.len(), 2);
+            assert!(matches!(pat, Pat::Unit));
+        }
+        other => panic!("{:#?}", other),
+    }
+}
+
+#[test]
+fn parser_match_test() {
+    let expr = parse_expr("match x with | ((0, y), _) -> y | (-1, ()), true -> 1");
+    match expr.kind {
+        ExprKind::Match { cases, .. } => match cases.as_slice() {
+            [Case {
+                pat: Pat::Tuple(pats1),
+                ..
+            }, Case {
+                pat: Pat::Tuple(pats2),
+                span,
+                ..
+            }] => {
+                assert!(matches!(pats1.as_slice(), [Pat::Tuple(_), Pat::Wildcard]));
+                assert!(matches!(pats2.as_slice(), [Pat::Tuple(_), Pat::Bool(true)]));
+                assert_eq!(*span, Span::new(34, 48));
+            }
+            other => panic!("{:#?}", other),
+        },
+        other => panic!("{:#?}", other),
+    }
+}
+
+#[test]
+fn parser_type_decl_test() {
+    let src = "type t = | A | B of int * (t -> t) and u = C of t array";
+    let decls = match parse_phrases(src).1.pop() {
+        Some(Phrase::Type { decls }) => decls,
+        other => panic!("{:#?}", other),
+    };
+    match decls.as_slice() {
+        [TypeDecl {
+            name: t,
+            kind: TypeDeclKind::Variant(t_cons),
+        }, TypeDecl {
+            name: u,
+            kind: TypeDeclKind::Variant(u_cons),
+        }] => {
+            assert_eq!(t, "t");
+            assert_eq!(u, "u");
+            assert_eq!(t_cons.len(), 2);
+            assert!(t_cons[0].args.is_empty());
+            assert!(matches!(
+                t_cons[1].args.as_slice(),
+                [
+                    TyExpr {
+                        kind: TyExprKind::Con { .. },
+                        ..
+                    },
+                    TyExpr {
+                        kind: TyExprKind::Fun { .. },
+                        ..
+                    }
+                ]
+            ));
+            match u_cons[0].args.as_slice() {
+                [TyExpr {
+                    kind: TyExprKind::Con { args, name },
+                    span,
+                }] => {
+                    assert_eq!(name, "array");
+                    assert_eq!(args.len(), 1);
+                    assert_eq!(*span, Span::new(48, 55));
+                }
+                other => panic!("{:#?}", other),
+            }
+        }
+        other => panic!("{:#?}", other),
+    }
+}
+
+#[test]
+fn parser_record_test() {
+    let (ctx, phrases) = parse_phrases(
+        "type r = { x : int; mutable y : float; };; let { x; y = z; _ } = r;; f r.x; r.y <- 1.0",
+    );
+    match phrases.as_slice() {
+        [Phrase::Type { decls }, Phrase::LetRecord { fields, .. }, Phrase::Expr(expr)] => {
+            match &decls[0].kind {
+                TypeDeclKind::Record(fields) => {
+                    assert_eq!(fields.len(), 2);
+                    assert!(!fields[0].mutable);
+                    assert!(fields[1].mutable);
+                }
+                other => panic!("{:#?}", other),
+            }
+            assert_eq!(fields.len(), 2);
+            assert_eq!(&*ctx.var_name(fields[1].1), "z");
+            match &expr.kind {
+                ExprKind::Let { rhs, body, .. } => {
+                    assert!(matches!(
+                        &rhs.kind,
+                        ExprKind::App { args, .. } if matches!(args[0].kind, ExprKind::GetField(..))
+                    ));
+                    assert!(matches!(body.kind, ExprKind::SetField(..)));
+                    assert_eq!(body.span, Span::new(76, 86));
+                }
+                other => panic!("{:#?}", other),
+            }
+        }
+        other => panic!("{:#?}", other),
+    }
+}
+
+#[test]
+fn parser_fun_test() {
+    let expr = parse_expr("f (fun x _ -> x; x) 1");
+    match expr.kind {
+        ExprKind::App { args, .. } => match args.as_slice() {
+            [Expr {
+                kind: ExprKind::Fun { args, body },
+                span,
+            }, _] => {
+                assert_eq!(args.len(), 2);
+                assert!(matches!(body.kind, ExprKind::Let { .. }));
+                assert_eq!(*span, Span::new(2, 19));
+            }
+            other => panic!("{:#?}", other),
+        },
+        other => panic!("{:#?}", other),
+    }
+}
+
+#[test]
+fn parser_ref_test() {
+    let expr = parse_expr("r := !r.x + 1; f !r");
+    let (assign, body) = match expr.kind {
+        ExprKind::Let { rhs, body, .. } => (rhs, body),
+        other => panic!("{:#?}", other),
+    };
+    match assign.kind {
+        ExprKind::Assign(lhs, rhs) => {
+            assert!(matches!(lhs.kind, ExprKind::Var(_)));
+            match rhs.kind {
+                ExprKind::IntBinOp(e, _, _) => match e.kind {
+                    ExprKind::GetField(e, _) => assert!(matches!(e.kind, ExprKind::Deref(_))),
+                    other => panic!("{:#?}", other),
+                },
+                other => panic!("{:#?}", other),
+            }
+        }
+        other => panic!("{:#?}", other),
+    }
+    match body.kind {
+        ExprKind::App { args, .. } => {
+            assert!(matches!(args.as_slice(), [Expr { kind: ExprKind::Deref(_), .. }]))
+        }
+        other => panic!("{:#?}", other),
+    }
+}
+
+#[test]
+fn parser_exn_test() {
+    let (ctx, phrases) = parse_phrases(
+        "exception E of int * float;; try f (raise E) with E _ -> failwith \"f\" | _ -> assert b",
+    );
+    match phrases.as_slice() {
+        [Phrase::Exception(con_decl), Phrase::Expr(expr)] => {
+            assert_eq!(&*ctx.var_name(con_decl.con), "E");
+            assert_eq!(con_decl.args.len(), 2);
+            match &expr.kind {
+                ExprKind::Try { body, cases } => {
+                    assert!(matches!(
+                        &body.kind,
+                        ExprKind::App { args, .. } if matches!(args[0].kind, ExprKind::Raise { .. })
+                    ));
+                    assert_eq!(cases.len(), 2);
+                    match &cases[0].rhs.kind {
+                        ExprKind::Raise { exn, .. } => assert!(matches!(
+                            &exn.kind,
+                            ExprKind::Con { arg: Some(arg), .. }
+                                if matches!(&arg.kind, ExprKind::String(s) if s == "f")
+                        )),
+                        other => panic!("{:#?}", other),
+                    }
+                    assert!(matches!(cases[1].rhs.kind, ExprKind::Assert(_)));
+                }
+                other => panic!("{:#?}", other),
+            }
+        }
+        other => panic!("{:#?}", other),
+    }
+}
+
+#[test]
+fn parser_bitwise_test() {
+    let expr = parse_expr("a lsl b lsl c land lnot d");
+    match expr.kind {
+        ExprKind::IntBinOp(lhs, IntBinOp::And, rhs) => {
+            assert!(matches!(
+                &lhs.kind,
+                ExprKind::IntBinOp(_, IntBinOp::Lsl, b_lsl_c)
+                    if matches!(b_lsl_c.kind, ExprKind::IntBinOp(_, IntBinOp::Lsl, _))
+            ));
+            assert!(matches!(
+                &rhs.kind,
+                ExprKind::IntBinOp(_, IntBinOp::Xor, minus_one)
+                    if matches!(minus_one.kind, ExprKind::Int(-1))
+            ));
+        }
+        other => panic!("{:#?}", other),
+    }
+}
+
+#[test]
+fn parser_and_or_test() {
+    let expr = parse_expr("a && b || c < d && e || f");
+    match expr.kind {
+        ExprKind::Or(lhs, rhs) => {
+            assert!(matches!(lhs.kind, ExprKind::And(_, _)));
+            assert!(matches!(
+                &rhs.kind,
+                ExprKind::Or(c_and_e, _)
+                    if matches!(&c_and_e.kind, ExprKind::And(c, _)
+                        if matches!(c.kind, ExprKind::Cmp(_, Cmp::LessThan, _)))
+            ));
+        }
+        other => panic!("{:#?}", other),
+    }
+}
+
+#[test]
+fn parser_list_test() {
+    let expr = parse_expr("match 1 + 2 :: [3; 4;] with [] -> 0 | [x] -> x | (a, b) :: _ :: t -> a");
+    match expr.kind {
+        ExprKind::Match { scrut, cases } => {
+            assert!(matches!(
+                &scrut.kind,
+                ExprKind::Cons(hd, tl)
+                    if matches!(hd.kind, ExprKind::IntBinOp(_, IntBinOp::Add, _))
+                        && matches!(&tl.kind, ExprKind::Cons(_, tl)
+                            if matches!(&tl.kind, ExprKind::Cons(_, tl)
+                                if matches!(tl.kind, ExprKind::Nil { .. })))
+            ));
+            assert!(matches!(cases[0].pat, Pat::Nil));
+            assert!(matches!(
+                &cases[1].pat,
+                Pat::Cons(hd, tl) if matches!(**hd, Pat::Var(_)) && matches!(**tl, Pat::Nil)
+            ));
+            assert!(matches!(
+                &cases[2].pat,
+                Pat::Cons(hd, tl)
+                    if matches!(**hd, Pat::Tuple(_))
+                        && matches!(&**tl, Pat::Cons(_, t) if matches!(**t, Pat::Var(_)))
+            ));
+        }
+        other => panic!("{:#?}", other),
+    }
+}
+
+#[test]
+fn parser_annot_test() {
+    let phrases = parse_phrases("let rec f (x : int) y : int array = (y : int -> int) x").1;
+    match phrases.as_slice() {
+        [Phrase::LetRec { defs }] => {
+            assert_eq!(defs[0].args.len(), 2);
+            // `(x : int); (<rhs> : int array)`
+            match &defs[0].rhs.kind {
+                ExprKind::Let { rhs, body, .. } => {
+                    assert!(matches!(
+                        &rhs.kind,
+                        ExprKind::Annot(x, TyExpr { kind: TyExprKind::Con { name, .. }, .. })
+                            if matches!(x.kind, ExprKind::Var(_)) && name == "int"
+                    ));
+                    assert!(matches!(
+                        &body.kind,
+                        ExprKind::Annot(app, TyExpr { kind: TyExprKind::Con { name, .. }, .. })
+                            if name == "array" && matches!(&app.kind, ExprKind::App { fun, .. }
+                                if matches!(fun.kind, ExprKind::Annot(_, TyExpr {
+                                    kind: TyExprKind::Fun { .. },
+                                    ..
+                                })))
+                    ));
+                }
+                other => panic!("{:#?}", other),
+            }
+        }
+        other => panic!("{:#?}", other),
+    }
+}
+
+#[test]
+fn parser_type_alias_test() {
+    let src = "type vec = float array and point = (float * float)";
+    let decls = match parse_phrases(src).1.pop() {
+        Some(Phrase::Type { decls }) => decls,
+        other => panic!("{:#?}", other),
+    };
+    match decls.as_slice() {
+        [TypeDecl {
+            name: vec,
+            kind: TypeDeclKind::Alias(vec_ty),
+        }, TypeDecl {
+            name: point,
+            kind: TypeDeclKind::Alias(point_ty),
+        }] => {
+            assert_eq!(vec, "vec");
+            assert_eq!(point, "point");
+            assert!(matches!(&vec_ty.kind, TyExprKind::Con { name, .. } if name == "array"));
+            assert!(matches!(&point_ty.kind, TyExprKind::Tuple(tys) if tys.len() == 2));
+        }
+        other => panic!("{:#?}", other),
+    }
+}
+
+#[test]
+fn parser_phys_cmp_test() {
+    let expr = parse_expr("a == b && !c != d");
+    match expr.kind {
+        ExprKind::And(e1, e2) => {
+            assert!(matches!(e1.kind, ExprKind::PhysCmp(_, Cmp::Equal, _)));
+            match e2.kind {
+                ExprKind::PhysCmp(e1, Cmp::NotEqual, _) => {
+                    assert!(matches!(e1.kind, ExprKind::Deref(_)));
+                }
+                other => panic!("{:#?}", other),
+            }
+        }
+        other => panic!("{:#?}", other),
+    }
+}
+
+#[test]
+fn parser_let_pat_test() {
+    let expr = parse_expr("let rec f ((a, _), b) () = a in let (x, (), _) = f p () in x");
+    match expr.kind {
+        ExprKind::LetRec { defs, body } => {
+            assert_eq!(defs[0].args.len(), 2);
+            // `let ((a, _), b) = <arg> in let () = <arg> in a`
+            match &defs[0].rhs.kind {
+                ExprKind::LetTuple { pat: Pat::Tuple(pats), body, .. } => {
+                    assert!(matches!(pats.as_slice(), [Pat::Tuple(_), Pat::Var(_)]));
+                    assert!(matches!(body.kind, ExprKind::LetTuple { pat: Pat::Unit, .. }));
+                }
+                other => panic!("{:#?}", other),
+            }
+            match &body.kind {
+                ExprKind::LetTuple { pat: Pat::Tuple(pats), .. } => {
+                    assert!(matches!(pats.as_slice(), [Pat::Var(_), Pat::Unit, Pat::Wildcard]));
+                }
+                other => panic!("{:#?}", other),
+            }
         }
+        other => panic!("{:#?}", other),
     }
 }
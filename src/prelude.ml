@@ -0,0 +1,31 @@
+(* Library functions defined in MinCaml, type checked and compiled with every program. Unused
+   functions are polymorphic, so they're dropped by monomorphization. *)
+
+let rec List.length l =
+  match l with
+  | [] -> 0
+  | _ :: l -> 1 + List.length l
+
+let rec List.rev_append l1 l2 =
+  match l1 with
+  | [] -> l2
+  | x :: l1 -> List.rev_append l1 (x :: l2)
+
+let rec List.rev l = List.rev_append l []
+
+let rec List.map f l =
+  match l with
+  | [] -> []
+  | x :: l ->
+    let y = f x in
+    y :: List.map f l
+
+let rec List.iter f l =
+  match l with
+  | [] -> ()
+  | x :: l -> f x; List.iter f l
+
+let rec List.fold_left f acc l =
+  match l with
+  | [] -> acc
+  | x :: l -> List.fold_left f (f acc x) l
@@ -0,0 +1,63 @@
+// Source locations
+
+/// A region in the source file, as byte offsets. `end` is exclusive.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub struct Span {
+    pub start: u32,
+    pub end: u32,
+}
+
+impl Span {
+    pub fn new(start: usize, end: usize) -> Span {
+        debug_assert!(start <= end);
+        Span {
+            start: start as u32,
+            end: end as u32,
+        }
+    }
+
+    /// Smallest span that covers both `self` and `other`.
+    pub fn to(self, other: Span) -> Span {
+        Span {
+            start: self.start.min(other.start),
+            end: self.end.max(other.end),
+        }
+    }
+}
+
+/// Something with a source location
+#[derive(Debug, Clone, PartialEq)]
+pub struct Spanned<T> {
+    pub node: T,
+    pub span: Span,
+}
+
+/// 1-based line and column numbers. Columns are counted in characters.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LineCol {
+    pub line: u32,
+    pub col: u32,
+}
+
+pub fn line_col(src: &str, offset: u32) -> LineCol {
+    let offset = (offset as usize).min(src.len());
+    let before = &src[..offset];
+    let line_start = match before.rfind('\n') {
+        None => 0,
+        Some(newline) => newline + 1,
+    };
+    LineCol {
+        line: before.matches('\n').count() as u32 + 1,
+        col: before[line_start..].chars().count() as u32 + 1,
+    }
+}
+
+#[test]
+fn line_col_test() {
+    let src = "let x = 1 in\n  print_int x\n";
+    assert_eq!(line_col(src, 0), LineCol { line: 1, col: 1 });
+    assert_eq!(line_col(src, 4), LineCol { line: 1, col: 5 });
+    assert_eq!(line_col(src, 13), LineCol { line: 2, col: 1 });
+    assert_eq!(line_col(src, 15), LineCol { line: 2, col: 3 });
+    assert_eq!(line_col(src, 100), LineCol { line: 3, col: 1 });
+}
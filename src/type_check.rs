@@ -1,11 +1,15 @@
 use fxhash::FxHashMap;
+use std::fmt;
 use std::rc::Rc;
 
 use crate::ctx::{Ctx, VarId};
+use crate::format;
 use crate::locals::Locals;
-use crate::parser::Expr;
-use crate::utils::take;
-use crate::var::Uniq;
+use crate::parser::{
+    Case, Expr, ExprKind, FunDef, Pat, Phrase, TyExpr, TyExprKind, TypeDecl, TypeDeclKind,
+};
+use crate::span::Span;
+use crate::var::{CompilerPhase, Uniq};
 
 pub type TyVar = Uniq;
 
@@ -17,10 +21,86 @@ pub enum Type {
     Bool,
     Int,
     Float,
+    String,
+    Char,
     Fun { args: Vec<Type>, ret: Box<Type> },
     Tuple(Vec<Type>),
     Array(Box<Type>),
+    List(Box<Type>),
+    Ref(Box<Type>),
     Var(TyVar),
+    // A type declared with `type`
+    Con(TyCon),
+    // A type abbreviation declared with `type`, e.g. `vec` in `type vec = float array`. Unifies
+    // like `ty`, the name is for printing types in error messages. Abbreviations don't have type
+    // parameters, so `ty` doesn't have type variables.
+    Alias { name: Rc<str>, ty: Box<Type> },
+}
+
+/// A type constructor declared with `type`. Declarations with the same name get different `uniq`s.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct TyCon {
+    pub name: Rc<str>,
+    pub uniq: Uniq,
+}
+
+/// A data constructor declared with `type` or `exception`. Constructors without arguments are
+/// represented as their tag, others as a tuple with the tag in field 0 and the arguments in the
+/// rest. Tags are counted separately for the two kinds of constructors.
+///
+/// Exception constructors don't have tags: all exceptions are tuples with the address of the
+/// constructor name (`exn_name`) in field 0, which identifies the constructor and is used by the
+/// RTS to print uncaught exceptions. The name is followed by a NUL and a character per argument
+/// telling the RTS how to print the argument, see `exn_name_lit`.
+#[derive(Debug, Clone)]
+pub struct ConInfo {
+    pub ty_con: TyCon,
+    pub tag: i64,
+    pub args: Vec<Type>,
+    pub exn_name: Option<VarId>,
+}
+
+/// A record field declared with `type`. Records are represented as tuples, `idx` is the position
+/// of the field in the tuple.
+#[derive(Debug, Clone)]
+pub struct FieldInfo {
+    pub ty_con: TyCon,
+    pub idx: usize,
+    pub mutable: bool,
+    pub ty: Type,
+}
+
+impl Type {
+    // The type itself if it's not an abbreviation, otherwise the expansion of the abbreviation
+    fn unalias(&self) -> &Type {
+        match self {
+            Type::Alias { ty, .. } => ty.unalias(),
+            _ => self,
+        }
+    }
+
+    // The type with all abbreviations expanded
+    fn expand_aliases(&self) -> Type {
+        match self {
+            Type::Unit
+            | Type::Bool
+            | Type::Int
+            | Type::Float
+            | Type::String
+            | Type::Char
+            | Type::Var(_)
+            | Type::Con(_) => self.clone(),
+            Type::Fun { args, ret } => Type::Fun {
+                args: args.iter().map(Type::expand_aliases).collect(),
+                ret: Box::new(ret.expand_aliases()),
+            },
+            Type::Tuple(args) => Type::Tuple(args.iter().map(Type::expand_aliases).collect()),
+            Type::Array(ty) => Type::Array(Box::new(ty.expand_aliases())),
+            Type::List(ty) => Type::List(Box::new(ty.expand_aliases())),
+            Type::Ref(ty) => Type::Ref(Box::new(ty.expand_aliases())),
+            Type::Alias { ty, .. } => ty.expand_aliases(),
+        }
+    }
 }
 
 /*
@@ -37,24 +117,158 @@ impl Type {
 #[derive(Debug)]
 pub enum TypeErr {
     /// Can't unify these two types
-    UnifyError(Type, Type),
+    UnifyError {
+        expected: Type,
+        found: Type,
+        span: Span,
+    },
     /// Occurs check failed
-    InfiniteType(Type, Type),
+    InfiniteType {
+        expected: Type,
+        found: Type,
+        span: Span,
+    },
     /// Unbound variable
-    UnboundVar(VarId),
+    UnboundVar { var: VarId, span: Span },
+    /// Unbound constructor
+    UnboundCon { con: VarId, span: Span },
+    /// Constructor applied to wrong number of arguments
+    ConArity {
+        con: VarId,
+        expected: usize,
+        found: usize,
+        span: Span,
+    },
+    /// Unbound type constructor
+    UnboundType { name: String, span: Span },
+    /// Type abbreviation that expands to itself, at a use of the abbreviation in its expansion
+    CyclicAlias { name: String, span: Span },
+    /// Type constructor applied to wrong number of arguments
+    TyConArity {
+        name: String,
+        expected: usize,
+        found: usize,
+        span: Span,
+    },
+    /// Unbound record field
+    UnboundField { field: VarId, span: Span },
+    /// Record expression without a value for the field
+    MissingField { field: VarId, span: Span },
+    /// Record expression with two values for the field
+    DuplicateField { field: VarId, span: Span },
+    /// Update of a field not declared `mutable`
+    ImmutableField { field: VarId, span: Span },
+    /// Invalid directive in a `Printf.printf` format string
+    InvalidFormat { directive: String, span: Span },
+    /// Type of an expression doesn't match its type annotation
+    AnnotMismatch {
+        annot: Type,
+        found: Type,
+        span: Span,
+    },
+}
+
+// Substitution of type variables, as a union-find of type variables with path compression. The
+// table is indexed by type variable, and each variable is unbound, linked to another variable of
+// its set, or bound to a type. Only the root of a set is unbound or bound.
+//
+// Unbound variables have a level: the number of `let` and `let rec` RHSs they're created in.
+// Variables with a higher level than the current level don't occur in the types of variables in
+// scope, so they can be generalized.
+#[derive(Debug, Default)]
+struct SubstEnv {
+    links: Vec<Link>,
+    // Level of the RHS being checked
+    level: u32,
+    // Types of the operands of structural comparisons. Generalized type variables that occur in
+    // these are recorded in `Ctx`, see `Ctx::add_compared_tyvar`.
+    compared: Vec<Type>,
+}
+
+#[derive(Debug, Clone)]
+enum Link {
+    Unbound { level: u32 },
+    Var(TyVar),
+    // In an `Rc` so that unification doesn't have to copy it
+    Ty(Rc<Type>),
+}
+
+impl SubstEnv {
+    fn new_var(&mut self, ctx: &mut Ctx) -> Type {
+        let var = ctx.fresh_tyvar();
+        let idx = var.0.get() as usize;
+        // Uniqs are also used for other things, so this leaves gaps in the table
+        if self.links.len() <= idx {
+            self.links.resize(idx + 1, Link::Unbound { level: 0 });
+        }
+        self.links[idx] = Link::Unbound { level: self.level };
+        Type::Var(var)
+    }
+
+    fn link(&self, var: TyVar) -> &Link {
+        &self.links[var.0.get() as usize]
+    }
+
+    fn link_mut(&mut self, var: TyVar) -> &mut Link {
+        &mut self.links[var.0.get() as usize]
+    }
+
+    // Root of the set of the variable. Variables on the way are linked directly to the root.
+    fn find(&mut self, var: TyVar) -> TyVar {
+        let mut root = var;
+        while let Link::Var(next) = self.link(root) {
+            root = *next;
+        }
+        let mut var = var;
+        while var != root {
+            match std::mem::replace(self.link_mut(var), Link::Var(root)) {
+                Link::Var(next) => var = next,
+                _ => unreachable!(),
+            }
+        }
+        root
+    }
+
+    fn enter_level(&mut self) {
+        self.level += 1;
+    }
+
+    fn exit_level(&mut self) {
+        self.level -= 1;
+    }
+}
+
+// A type with the type variable at the top resolved: the type itself if it's not a variable, the
+// type the variable is bound to, or the root of the variable's set if it's unbound
+enum DerefTy<'a> {
+    Ty(&'a Type),
+    Bound(Rc<Type>),
+    Unbound(Type),
 }
 
-type SubstEnv = FxHashMap<TyVar, Type>;
+impl std::ops::Deref for DerefTy<'_> {
+    type Target = Type;
+
+    fn deref(&self) -> &Type {
+        match self {
+            DerefTy::Ty(ty) => ty,
+            DerefTy::Bound(ty) => ty,
+            DerefTy::Unbound(ty) => ty,
+        }
+    }
+}
 
 #[derive(Debug, Clone)]
 struct Binder {
     binder: VarId,
     ty: Type,
+    // Type variables generalized in `ty`, instantiated with fresh type variables at each use
+    tyvars: Vec<TyVar>,
 }
 
 type Scope = Locals<Rc<str>, Binder>;
 
-pub fn type_check_pgm(ctx: &mut Ctx, expr: &mut Expr) -> Result<(), TypeErr> {
+pub fn type_check_pgm(ctx: &mut Ctx, pgm: &mut [Phrase]) -> Result<(), TypeErr> {
     let mut global_scope: FxHashMap<Rc<str>, Binder> = Default::default();
 
     for (var_id, ty_id) in ctx.builtins() {
@@ -65,7 +279,21 @@ pub fn type_check_pgm(ctx: &mut Ctx, expr: &mut Expr) -> Result<(), TypeErr> {
             var_name,
             Binder {
                 binder: *var_id,
-                ty: (&*ty).clone(),
+                ty: (*ty).clone(),
+                tyvars: vec![],
+            },
+        );
+    }
+
+    // Predefined exceptions. Users can shadow these with `exception` declarations.
+    let exn_ty = Type::Con(ctx.exn_ty_con());
+    for con in ctx.exn_cons() {
+        global_scope.insert(
+            ctx.var_name(*con),
+            Binder {
+                binder: *con,
+                ty: exn_ty.clone(),
+                tyvars: vec![],
             },
         );
     }
@@ -73,11 +301,13 @@ pub fn type_check_pgm(ctx: &mut Ctx, expr: &mut Expr) -> Result<(), TypeErr> {
     let mut scope: Scope = Locals::new(global_scope);
     let mut subst_env: SubstEnv = Default::default();
     let mut ty_env: TypeEnv = Default::default();
-    let ty = type_check(ctx, &mut ty_env, &mut subst_env, &mut scope, expr)?;
-    unify(&mut subst_env, &Type::Unit, &ty)?;
+    // Top-level definitions are added to the scope in order, and are never removed
+    for phrase in pgm.iter_mut() {
+        type_check_phrase(ctx, &mut ty_env, &mut subst_env, &mut scope, phrase)?;
+    }
 
     for ty in ty_env.values_mut() {
-        take(ty, |ty| norm_ty(&subst_env, ty));
+        *ty = norm_ty(&mut subst_env, ty);
     }
 
     ctx.extend_type_env(ty_env.into_iter());
@@ -85,53 +315,408 @@ pub fn type_check_pgm(ctx: &mut Ctx, expr: &mut Expr) -> Result<(), TypeErr> {
     Ok(())
 }
 
-fn norm_ty(substs: &SubstEnv, ty: Type) -> Type {
-    match ty {
-        Type::Unit | Type::Bool | Type::Int | Type::Float => ty,
-        Type::Fun { args, ret } => Type::Fun {
-            args: args.into_iter().map(|ty| norm_ty(substs, ty)).collect(),
-            ret: Box::new(norm_ty(substs, *ret)),
-        },
-        Type::Tuple(args) => Type::Tuple(args.into_iter().map(|ty| norm_ty(substs, ty)).collect()),
-        Type::Array(ty) => Type::Array(Box::new(norm_ty(substs, *ty))),
-        Type::Var(_) => {
-            let deref = deref_ty(substs, &ty).clone();
-            if deref == ty {
-                ty
+fn type_check_phrase(
+    ctx: &mut Ctx, ty_env: &mut TypeEnv, subst_env: &mut SubstEnv, scope: &mut Scope,
+    phrase: &mut Phrase,
+) -> Result<(), TypeErr> {
+    match phrase {
+        Phrase::Let { bndr, rhs } => {
+            subst_env.enter_level();
+            let bndr_ty = subst_env.new_var(ctx);
+            ty_env.insert(*bndr, bndr_ty.clone());
+            let rhs_ty = type_check(ctx, ty_env, subst_env, scope, rhs)?;
+            unify(subst_env, &bndr_ty, &rhs_ty, rhs.span)?;
+            subst_env.exit_level();
+            let tyvars = generalize_let(ctx, subst_env, *bndr, &bndr_ty, rhs);
+            scope.add(
+                ctx.var_name(*bndr),
+                Binder {
+                    binder: *bndr,
+                    ty: bndr_ty,
+                    tyvars,
+                },
+            );
+            Ok(())
+        }
+
+        Phrase::LetRec { defs } => type_check_fun_defs(ctx, ty_env, subst_env, scope, defs),
+
+        Phrase::LetTuple { pat, rhs } => {
+            let bndrs = type_check_let_tuple(ctx, ty_env, subst_env, scope, pat, rhs)?;
+            for bndr in bndrs {
+                let ty = ty_env[&bndr].clone();
+                scope.add(ctx.var_name(bndr), Binder { binder: bndr, ty, tyvars: vec![] });
+            }
+            Ok(())
+        }
+
+        Phrase::Expr(expr) => {
+            let ty = type_check(ctx, ty_env, subst_env, scope, expr)?;
+            unify(subst_env, &Type::Unit, &ty, expr.span)
+        }
+
+        Phrase::LetRecord { fields, rhs } => {
+            let bndr_tys = type_check_let_record(ctx, ty_env, subst_env, scope, fields, rhs)?;
+            for ((_, bndr), bndr_ty) in fields.iter().zip(bndr_tys) {
+                scope.add(
+                    ctx.var_name(*bndr),
+                    Binder {
+                        binder: *bndr,
+                        ty: bndr_ty,
+                        tyvars: vec![],
+                    },
+                );
+            }
+            Ok(())
+        }
+
+        Phrase::Type { decls } => type_check_type_decls(ctx, scope, decls),
+
+        Phrase::Exception(con_decl) => {
+            let args: Vec<Type> = con_decl
+                .args
+                .iter()
+                .map(|arg| resolve_ty(ctx, arg).map(|ty| ty.expand_aliases()))
+                .collect::<Result<_, _>>()?;
+            let ty_con = ctx.exn_ty_con();
+            let name = ctx.var_name(con_decl.con);
+            let exn_name =
+                ctx.add_str_lit(CompilerPhase::TypeCheck, &exn_name_lit(&name, &args));
+            ctx.add_con(
+                con_decl.con,
+                ConInfo {
+                    ty_con: ty_con.clone(),
+                    tag: 0,
+                    args,
+                    exn_name: Some(exn_name),
+                },
+            );
+            scope.add(
+                name,
+                Binder {
+                    binder: con_decl.con,
+                    ty: Type::Con(ty_con),
+                    tyvars: vec![],
+                },
+            );
+            Ok(())
+        }
+    }
+}
+
+// Add type constructors, abbreviations, constructors, and record fields of a `type ... and ...` to
+// the context, and constructors to the scope. Types of constructor arguments and fields are stored
+// with abbreviations expanded.
+fn type_check_type_decls(
+    ctx: &mut Ctx, scope: &mut Scope, decls: &[TypeDecl],
+) -> Result<(), TypeErr> {
+    // Add the type constructors first, so that the types can refer to each other. Abbreviations
+    // can refer to the type constructors and to each other, they're resolved in dependency order.
+    let mut ty_cons: Vec<Option<TyCon>> = Vec::with_capacity(decls.len());
+    let mut alias_states: Vec<AliasState> = Vec::with_capacity(decls.len());
+    for decl in decls {
+        match &decl.kind {
+            TypeDeclKind::Alias(_) => {
+                ty_cons.push(None);
+                alias_states.push(AliasState::Unresolved);
+            }
+            _ => {
+                ty_cons.push(Some(ctx.add_ty_con(&decl.name)));
+                alias_states.push(AliasState::Resolved);
+            }
+        }
+    }
+    for idx in 0..decls.len() {
+        if alias_states[idx] == AliasState::Unresolved {
+            resolve_alias(ctx, decls, &mut alias_states, idx)?;
+        }
+    }
+
+    for (decl, ty_con) in decls.iter().zip(ty_cons) {
+        let (cons, ty_con) = match (&decl.kind, ty_con) {
+            (TypeDeclKind::Variant(cons), Some(ty_con)) => (cons, ty_con),
+            (TypeDeclKind::Record(fields), Some(ty_con)) => {
+                for (idx, field_decl) in fields.iter().enumerate() {
+                    let ty = resolve_ty(ctx, &field_decl.ty)?.expand_aliases();
+                    ctx.add_field(
+                        field_decl.field,
+                        FieldInfo {
+                            ty_con: ty_con.clone(),
+                            idx,
+                            mutable: field_decl.mutable,
+                            ty,
+                        },
+                    );
+                }
+                continue;
+            }
+            // Abbreviations were added above
+            (TypeDeclKind::Alias(_), _) | (_, None) => continue,
+        };
+        let mut n_consts = 0;
+        let mut n_blocks = 0;
+        for con_decl in cons {
+            let args: Vec<Type> = con_decl
+                .args
+                .iter()
+                .map(|arg| resolve_ty(ctx, arg).map(|ty| ty.expand_aliases()))
+                .collect::<Result<_, _>>()?;
+            let tag = if args.is_empty() {
+                n_consts += 1;
+                n_consts - 1
             } else {
-                norm_ty(substs, deref)
+                n_blocks += 1;
+                n_blocks - 1
+            };
+            ctx.add_con(
+                con_decl.con,
+                ConInfo {
+                    ty_con: ty_con.clone(),
+                    tag,
+                    args,
+                    exn_name: None,
+                },
+            );
+            scope.add(
+                ctx.var_name(con_decl.con),
+                Binder {
+                    binder: con_decl.con,
+                    ty: Type::Con(ty_con.clone()),
+                    tyvars: vec![],
+                },
+            );
+        }
+    }
+
+    Ok(())
+}
+
+// Whether `var` refers to OCaml's `compare`, which is not a function in the prelude but compiled to
+// the comparison function of the compared type in `codegen`. Programs can shadow `compare`.
+fn is_compare_prim(ctx: &Ctx, scope: &Scope, var: VarId) -> bool {
+    let name = ctx.var_name(var);
+    &*name == "compare" && scope.get(&name).is_none()
+}
+
+// Contents of the name string of an exception constructor: the name, then after a NUL a character
+// per argument for printing uncaught exceptions as OCaml does: 'i' for values represented as
+// integers, 'f' for floats, 's' for strings, and '_' for the rest. See `mc_raise` in the RTS.
+fn exn_name_lit(name: &str, args: &[Type]) -> String {
+    let mut lit = format!("{}\0", name);
+    lit.extend(args.iter().map(|arg| match arg {
+        Type::Int | Type::Bool | Type::Char | Type::Unit => 'i',
+        Type::Float => 'f',
+        Type::String => 's',
+        _ => '_',
+    }));
+    lit
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum AliasState {
+    Unresolved,
+    InProgress,
+    // Also the state of the other kinds of declarations
+    Resolved,
+}
+
+// Resolve the abbreviation `decls[idx]` of a `type ... and ...` group after the abbreviations of
+// the group it refers to, and add them to `ctx`
+fn resolve_alias(
+    ctx: &mut Ctx, decls: &[TypeDecl], states: &mut [AliasState], idx: usize,
+) -> Result<(), TypeErr> {
+    let ty = match &decls[idx].kind {
+        TypeDeclKind::Alias(ty) => ty,
+        _ => panic!("resolve_alias: not an abbreviation"),
+    };
+    states[idx] = AliasState::InProgress;
+    let mut ty_cons: Vec<(&str, Span)> = vec![];
+    ty_expr_cons(ty, &mut ty_cons);
+    for (name, span) in ty_cons {
+        let dep = match decls.iter().position(|decl| decl.name == name) {
+            None => continue,
+            Some(dep) => dep,
+        };
+        match states[dep] {
+            AliasState::Unresolved => resolve_alias(ctx, decls, states, dep)?,
+            AliasState::InProgress => {
+                return Err(TypeErr::CyclicAlias {
+                    name: name.to_owned(),
+                    span,
+                });
+            }
+            AliasState::Resolved => {}
+        }
+    }
+    let ty = resolve_ty(ctx, ty)?;
+    ctx.add_ty_alias(&decls[idx].name, ty);
+    states[idx] = AliasState::Resolved;
+    Ok(())
+}
+
+// Names of the type constructors in a type expression, with their spans
+fn ty_expr_cons<'a>(ty: &'a TyExpr, ty_cons: &mut Vec<(&'a str, Span)>) {
+    match &ty.kind {
+        TyExprKind::Con { args, name } => {
+            ty_cons.push((name, ty.span));
+            for arg in args {
+                ty_expr_cons(arg, ty_cons);
             }
         }
+        TyExprKind::Tuple(args) => {
+            for arg in args {
+                ty_expr_cons(arg, ty_cons);
+            }
+        }
+        TyExprKind::Fun { args, ret } => {
+            for arg in args {
+                ty_expr_cons(arg, ty_cons);
+            }
+            ty_expr_cons(ret, ty_cons);
+        }
     }
 }
 
-fn deref_ty<'a>(subst: &'a SubstEnv, mut ty: &'a Type) -> &'a Type {
-    loop {
-        match ty {
-            Type::Var(tyvar) => match subst.get(tyvar) {
-                None => {
-                    return ty;
+// Type of a type expression in a declaration
+fn resolve_ty(ctx: &Ctx, ty: &TyExpr) -> Result<Type, TypeErr> {
+    match &ty.kind {
+        TyExprKind::Con { args, name } => {
+            let (ty_, arity) = match name.as_str() {
+                "unit" => (Type::Unit, 0),
+                "bool" => (Type::Bool, 0),
+                "int" => (Type::Int, 0),
+                "float" => (Type::Float, 0),
+                "string" => (Type::String, 0),
+                "char" => (Type::Char, 0),
+                "array" if args.len() == 1 => {
+                    (Type::Array(Box::new(resolve_ty(ctx, &args[0])?)), 1)
                 }
-                Some(ty_) => {
-                    ty = ty_;
+                "array" => (Type::Unit, 1),
+                "list" if args.len() == 1 => {
+                    (Type::List(Box::new(resolve_ty(ctx, &args[0])?)), 1)
                 }
-            },
-            _ => {
-                return ty;
+                "list" => (Type::Unit, 1),
+                "ref" if args.len() == 1 => (Type::Ref(Box::new(resolve_ty(ctx, &args[0])?)), 1),
+                "ref" => (Type::Unit, 1),
+                _ => match ctx.get_ty_con(name) {
+                    Some(ty_con) => (Type::Con(ty_con), 0),
+                    None if ctx.get_ty_alias(name).is_some() => {
+                        let ty = Box::new(ctx.get_ty_alias(name).unwrap().clone());
+                        (Type::Alias { name: name.as_str().into(), ty }, 0)
+                    }
+                    None => {
+                        return Err(TypeErr::UnboundType {
+                            name: name.clone(),
+                            span: ty.span,
+                        });
+                    }
+                },
+            };
+            if args.len() != arity {
+                return Err(TypeErr::TyConArity {
+                    name: name.clone(),
+                    expected: arity,
+                    found: args.len(),
+                    span: ty.span,
+                });
             }
+            Ok(ty_)
         }
+        TyExprKind::Tuple(args) => Ok(Type::Tuple(
+            args.iter()
+                .map(|arg| resolve_ty(ctx, arg))
+                .collect::<Result<_, _>>()?,
+        )),
+        TyExprKind::Fun { args, ret } => Ok(Type::Fun {
+            args: args
+                .iter()
+                .map(|arg| resolve_ty(ctx, arg))
+                .collect::<Result<_, _>>()?,
+            ret: Box::new(resolve_ty(ctx, ret)?),
+        }),
+    }
+}
+
+// Look up a record field, return the field in the declaration
+fn resolve_field(ctx: &Ctx, field: VarId, span: Span) -> Result<VarId, TypeErr> {
+    match ctx.get_field(&ctx.var_name(field)) {
+        Some(field) => Ok(field),
+        None => Err(TypeErr::UnboundField { field, span }),
     }
 }
 
-fn occurs_check(subst: &SubstEnv, var: TyVar, ty: &Type) -> bool {
-    match deref_ty(subst, ty) {
-        Type::Unit | Type::Bool | Type::Int | Type::Float => false,
+// Look up a constructor in scope, return the binder and argument types
+fn resolve_con(
+    ctx: &Ctx, scope: &Scope, con: VarId, span: Span,
+) -> Result<(VarId, Vec<Type>), TypeErr> {
+    match scope.get(&ctx.var_name(con)) {
+        Some(Binder { binder, .. }) => Ok((*binder, ctx.con_info(*binder).args.clone())),
+        None => Err(TypeErr::UnboundCon { con, span }),
+    }
+}
+
+fn norm_ty(subst_env: &mut SubstEnv, ty: &Type) -> Type {
+    match ty {
+        Type::Unit
+        | Type::Bool
+        | Type::Int
+        | Type::Float
+        | Type::String
+        | Type::Char
+        | Type::Con(_)
+        | Type::Alias { .. } => ty.clone(),
+        Type::Fun { args, ret } => Type::Fun {
+            args: args.iter().map(|ty| norm_ty(subst_env, ty)).collect(),
+            ret: Box::new(norm_ty(subst_env, ret)),
+        },
+        Type::Tuple(args) => Type::Tuple(args.iter().map(|ty| norm_ty(subst_env, ty)).collect()),
+        Type::Array(ty) => Type::Array(Box::new(norm_ty(subst_env, ty))),
+        Type::List(ty) => Type::List(Box::new(norm_ty(subst_env, ty))),
+        Type::Ref(ty) => Type::Ref(Box::new(norm_ty(subst_env, ty))),
+        Type::Var(_) => match deref_ty(subst_env, ty) {
+            DerefTy::Bound(ty) => norm_ty(subst_env, &ty),
+            ty => (*ty).clone(),
+        },
+    }
+}
+
+fn deref_ty<'a>(subst_env: &mut SubstEnv, ty: &'a Type) -> DerefTy<'a> {
+    match ty {
+        Type::Var(var) => {
+            let root = subst_env.find(*var);
+            match subst_env.link(root) {
+                Link::Ty(ty) => DerefTy::Bound(ty.clone()),
+                _ => DerefTy::Unbound(Type::Var(root)),
+            }
+        }
+        _ => DerefTy::Ty(ty),
+    }
+}
+
+// Whether the variable occurs in the type. Also lowers levels of the variables in the type to
+// `level`, as the type is about to be bound to a variable of that level.
+fn occurs_check(subst_env: &mut SubstEnv, var: TyVar, level: u32, ty: &Type) -> bool {
+    match &*deref_ty(subst_env, ty) {
+        Type::Unit
+        | Type::Bool
+        | Type::Int
+        | Type::Float
+        | Type::String
+        | Type::Char
+        | Type::Con(_)
+        | Type::Alias { .. } => false,
         Type::Fun { args, ret } => {
-            args.iter().any(|ty| occurs_check(subst, var, ty)) || occurs_check(subst, var, ret)
+            args.iter().any(|ty| occurs_check(subst_env, var, level, ty))
+                || occurs_check(subst_env, var, level, ret)
+        }
+        Type::Tuple(args) => args.iter().any(|ty| occurs_check(subst_env, var, level, ty)),
+        Type::Array(ty) | Type::List(ty) | Type::Ref(ty) => occurs_check(subst_env, var, level, ty),
+        Type::Var(var_) => {
+            if let Link::Unbound { level: level_ } = subst_env.link_mut(*var_) {
+                *level_ = (*level_).min(level);
+            }
+            var == *var_
         }
-        Type::Tuple(args) => args.iter().any(|ty| occurs_check(subst, var, ty)),
-        Type::Array(ty) => occurs_check(subst, var, ty),
-        Type::Var(var_) => var == *var_,
     }
 }
 
@@ -139,77 +724,157 @@ fn type_check(
     ctx: &mut Ctx, ty_env: &mut TypeEnv, subst_env: &mut SubstEnv, scope: &mut Scope,
     expr: &mut Expr,
 ) -> Result<Type, TypeErr> {
-    match expr {
-        Expr::Unit => Ok(Type::Unit),
-        Expr::Bool(_) => Ok(Type::Bool),
-        Expr::Int(_) => Ok(Type::Int),
-        Expr::Float(_) => Ok(Type::Float),
+    let span = expr.span;
+    match &mut expr.kind {
+        ExprKind::Unit => Ok(Type::Unit),
+        ExprKind::Bool(_) => Ok(Type::Bool),
+        ExprKind::Int(_) => Ok(Type::Int),
+        ExprKind::Float(_) => Ok(Type::Float),
+        ExprKind::String(_) => Ok(Type::String),
+        ExprKind::Char(_) => Ok(Type::Char),
 
-        Expr::Not(e) => {
+        ExprKind::Printf(fmt) => {
+            let pieces = format::parse(fmt).map_err(|directive| TypeErr::InvalidFormat {
+                directive,
+                span: expr.span,
+            })?;
+            let args = format::arg_tys(&pieces);
+            if args.is_empty() {
+                Ok(Type::Unit)
+            } else {
+                Ok(Type::Fun {
+                    args,
+                    ret: Box::new(Type::Unit),
+                })
+            }
+        }
+
+        ExprKind::Not(e) => {
             let e_ty = type_check(ctx, ty_env, subst_env, scope, e)?;
-            unify(subst_env, &Type::Bool, &e_ty)?;
+            unify(subst_env, &Type::Bool, &e_ty, e.span)?;
+            Ok(Type::Bool)
+        }
+
+        ExprKind::And(e1, e2) | ExprKind::Or(e1, e2) => {
+            let e1_ty = type_check(ctx, ty_env, subst_env, scope, e1)?;
+            unify(subst_env, &Type::Bool, &e1_ty, e1.span)?;
+            let e2_ty = type_check(ctx, ty_env, subst_env, scope, e2)?;
+            unify(subst_env, &Type::Bool, &e2_ty, e2.span)?;
             Ok(Type::Bool)
         }
 
-        Expr::Neg(e) => {
+        ExprKind::Neg(e) => {
             let e_ty = type_check(ctx, ty_env, subst_env, scope, e)?;
-            unify(subst_env, &Type::Int, &e_ty)?;
+            unify(subst_env, &Type::Int, &e_ty, e.span)?;
             Ok(Type::Int)
         }
 
-        Expr::IntBinOp(e1, _, e2) => {
+        ExprKind::IntBinOp(e1, _, e2) => {
             let e1_ty = type_check(ctx, ty_env, subst_env, scope, e1)?;
             let e2_ty = type_check(ctx, ty_env, subst_env, scope, e2)?;
-            unify(subst_env, &Type::Int, &e1_ty)?;
-            unify(subst_env, &Type::Int, &e2_ty)?;
+            unify(subst_env, &Type::Int, &e1_ty, e1.span)?;
+            unify(subst_env, &Type::Int, &e2_ty, e2.span)?;
             Ok(Type::Int)
         }
 
-        Expr::FNeg(e) => {
+        ExprKind::FNeg(e) => {
             let e_ty = type_check(ctx, ty_env, subst_env, scope, e)?;
-            unify(subst_env, &Type::Float, &e_ty)?;
+            unify(subst_env, &Type::Float, &e_ty, e.span)?;
             Ok(Type::Float)
         }
 
-        Expr::FloatBinOp(e1, _, e2) => {
+        ExprKind::FloatBinOp(e1, _, e2) => {
             let e1_ty = type_check(ctx, ty_env, subst_env, scope, e1)?;
             let e2_ty = type_check(ctx, ty_env, subst_env, scope, e2)?;
-            unify(subst_env, &Type::Float, &e1_ty)?;
-            unify(subst_env, &Type::Float, &e2_ty)?;
+            unify(subst_env, &Type::Float, &e1_ty, e1.span)?;
+            unify(subst_env, &Type::Float, &e2_ty, e2.span)?;
             Ok(Type::Float)
         }
 
-        Expr::Cmp(e1, _, e2) => {
+        ExprKind::Cmp(e1, _, e2) => {
+            let e1_ty = type_check(ctx, ty_env, subst_env, scope, e1)?;
+            let e2_ty = type_check(ctx, ty_env, subst_env, scope, e2)?;
+            unify(subst_env, &e1_ty, &e2_ty, e2.span)?;
+            subst_env.compared.push(e1_ty);
+            Ok(Type::Bool)
+        }
+
+        ExprKind::PhysCmp(e1, _, e2) => {
             let e1_ty = type_check(ctx, ty_env, subst_env, scope, e1)?;
             let e2_ty = type_check(ctx, ty_env, subst_env, scope, e2)?;
-            unify(subst_env, &e1_ty, &e2_ty)?;
+            unify(subst_env, &e1_ty, &e2_ty, e2.span)?;
             Ok(Type::Bool)
         }
 
-        Expr::If(e1, e2, e3) => {
+        ExprKind::Compare(e1, e2) => {
+            let e1_ty = type_check(ctx, ty_env, subst_env, scope, e1)?;
+            let e2_ty = type_check(ctx, ty_env, subst_env, scope, e2)?;
+            unify(subst_env, &e1_ty, &e2_ty, e2.span)?;
+            subst_env.compared.push(e1_ty);
+            Ok(Type::Int)
+        }
+
+        // `compare` applied to two arguments is compiled to the comparison, other uses to a
+        // function doing the comparison
+        ExprKind::App { fun, args }
+            if args.len() == 2
+                && matches!(fun.kind, ExprKind::Var(var) if is_compare_prim(ctx, scope, var)) =>
+        {
+            let e2 = args.pop().unwrap();
+            let e1 = args.pop().unwrap();
+            expr.kind = ExprKind::Compare(Box::new(e1), Box::new(e2));
+            type_check(ctx, ty_env, subst_env, scope, expr)
+        }
+
+        ExprKind::Var(var) if is_compare_prim(ctx, scope, *var) => {
+            let x = ctx.fresh_user_var("x");
+            let y = ctx.fresh_user_var("y");
+            let x_use = Expr {
+                kind: ExprKind::Var(ctx.fresh_user_var("x")),
+                span,
+            };
+            let y_use = Expr {
+                kind: ExprKind::Var(ctx.fresh_user_var("y")),
+                span,
+            };
+            expr.kind = ExprKind::Fun {
+                args: vec![x, y],
+                body: Box::new(Expr {
+                    kind: ExprKind::Compare(Box::new(x_use), Box::new(y_use)),
+                    span,
+                }),
+            };
+            type_check(ctx, ty_env, subst_env, scope, expr)
+        }
+
+        ExprKind::If(e1, e2, e3) => {
             let e1_ty = type_check(ctx, ty_env, subst_env, scope, e1)?;
             let e2_ty = type_check(ctx, ty_env, subst_env, scope, e2)?;
             let e3_ty = type_check(ctx, ty_env, subst_env, scope, e3)?;
-            unify(subst_env, &e1_ty, &Type::Bool)?;
-            unify(subst_env, &e2_ty, &e3_ty)?;
+            unify(subst_env, &Type::Bool, &e1_ty, e1.span)?;
+            unify(subst_env, &e2_ty, &e3_ty, e3.span)?;
             Ok(e2_ty)
         }
 
-        Expr::Let {
+        ExprKind::Let {
             bndr,
             ref mut rhs,
             body,
         } => {
-            let bndr_ty = Type::Var(ctx.fresh_tyvar());
+            subst_env.enter_level();
+            let bndr_ty = subst_env.new_var(ctx);
             ty_env.insert(*bndr, bndr_ty.clone());
             let rhs_ty = type_check(ctx, ty_env, subst_env, scope, rhs)?;
-            unify(subst_env, &bndr_ty, &rhs_ty)?;
+            unify(subst_env, &bndr_ty, &rhs_ty, rhs.span)?;
+            subst_env.exit_level();
+            let tyvars = generalize_let(ctx, subst_env, *bndr, &bndr_ty, rhs);
             scope.new_scope();
             scope.add(
                 ctx.var_name(*bndr),
                 Binder {
                     binder: *bndr,
                     ty: bndr_ty,
+                    tyvars,
                 },
             );
             let ret = type_check(ctx, ty_env, subst_env, scope, body);
@@ -217,87 +882,126 @@ fn type_check(
             ret
         }
 
-        Expr::Var(ref mut var) => match scope.get(&ctx.var_name(*var)) {
-            Some(Binder { binder, ty }) => {
+        ExprKind::Var(ref mut var) => match scope.get(&ctx.var_name(*var)) {
+            Some(Binder { binder, ty, tyvars }) if tyvars.is_empty() => {
                 *var = *binder;
                 Ok(ty.clone())
             }
-            None => Err(TypeErr::UnboundVar(*var)),
-        },
-
-        Expr::LetRec {
-            bndr,
-            ref args,
-            rhs,
-            body,
-        } => {
-            // Type variables for the arguments
-            let mut arg_tys: Vec<Type> = Vec::with_capacity(args.len());
-            for arg in args {
-                let arg_ty = Type::Var(ctx.fresh_tyvar());
-                arg_tys.push(arg_ty.clone());
-                ty_env.insert(*arg, arg_ty);
+            Some(Binder { binder, ty, tyvars }) => {
+                // Instantiate the generalized type variables with fresh ones. The use is not
+                // replaced with the binder: monomorphization replaces it with the binder of the
+                // copy of the definition for the instantiated type.
+                let inst: FxHashMap<TyVar, Type> = tyvars
+                    .iter()
+                    .map(|tyvar| (*tyvar, subst_env.new_var(ctx)))
+                    .collect();
+                // The definition compares values of the instantiated types
+                for tyvar in tyvars {
+                    if ctx.is_compared_tyvar(*tyvar) {
+                        subst_env.compared.push(inst[tyvar].clone());
+                    }
+                }
+                let ty = inst_ty(&inst, &norm_ty(subst_env, ty));
+                ty_env.insert(*var, ty.clone());
+                ctx.add_poly_use(*var, *binder);
+                Ok(ty)
             }
+            None => Err(TypeErr::UnboundVar { var: *var, span }),
+        },
 
-            // Type variable for the RHS
-            let rhs_ty = Type::Var(ctx.fresh_tyvar());
-
-            // We can now give type to the recursive function
-            let fun_ty = Type::Fun {
-                args: arg_tys.clone(),
-                ret: Box::new(rhs_ty.clone()),
-            };
-
-            ty_env.insert(*bndr, fun_ty.clone());
-
-            // RHS and body will be type checked with `name` and args in scope
-            scope.new_scope(); // new scope for function
-            scope.add(
-                ctx.var_name(*bndr),
-                Binder {
-                    binder: *bndr,
-                    ty: fun_ty,
-                },
-            );
-            scope.new_scope(); // new scope for args
+        ExprKind::LetRec { defs, body } => {
+            scope.new_scope(); // new scope for functions
+            type_check_fun_defs(ctx, ty_env, subst_env, scope, defs)?;
+            // Type check body with just the functions in scope
+            let ret = type_check(ctx, ty_env, subst_env, scope, body);
+            // Reset environment
+            scope.pop_scope();
+            ret
+        }
 
-            for (binder, arg_ty) in args.iter().zip(arg_tys.iter()) {
+        ExprKind::Fun { args, body } => {
+            let mut arg_tys: Vec<Type> = Vec::with_capacity(args.len());
+            scope.new_scope();
+            for arg in args.iter() {
+                let arg_ty = subst_env.new_var(ctx);
+                ty_env.insert(*arg, arg_ty.clone());
                 scope.add(
-                    ctx.var_name(*binder),
+                    ctx.var_name(*arg),
                     Binder {
-                        binder: *binder,
+                        binder: *arg,
                         ty: arg_ty.clone(),
+                        tyvars: vec![],
                     },
                 );
+                arg_tys.push(arg_ty);
             }
-
-            // Type check RHS with fun and args in scope
-            let rhs_ty_ = type_check(ctx, ty_env, subst_env, scope, rhs)?;
-            unify(subst_env, &rhs_ty, &rhs_ty_)?;
-            // Type check body with just the fun in scope
-            scope.pop_scope();
-            let ret = type_check(ctx, ty_env, subst_env, scope, body);
-            // Reset environment
+            let body_ty = type_check(ctx, ty_env, subst_env, scope, body);
             scope.pop_scope();
-            ret
+            Ok(Type::Fun {
+                args: arg_tys,
+                ret: Box::new(body_ty?),
+            })
         }
 
-        Expr::App { fun, args } => {
-            let ret_ty = Type::Var(ctx.fresh_tyvar());
-            let mut arg_tys: Vec<Type> = Vec::with_capacity(args.len());
-            for arg in args {
-                arg_tys.push(type_check(ctx, ty_env, subst_env, scope, arg)?);
+        ExprKind::App { fun, args } => {
+            // Check the function first, so that arguments are checked against the parameter
+            // types and mismatches are reported at the argument
+            let mut fun_ty_ = type_check(ctx, ty_env, subst_env, scope, fun)?;
+            let mut args = &mut args[..];
+            loop {
+                // When the arity is known the application can be partial, or the function can
+                // return a function applied to the rest of the arguments. Otherwise the function
+                // takes all of the arguments.
+                let (arg_tys, ret_ty) = match deref_ty(subst_env, &fun_ty_).unalias() {
+                    Type::Fun { args: arg_tys, ret } => (arg_tys.clone(), (**ret).clone()),
+                    _ => {
+                        let ret_ty = subst_env.new_var(ctx);
+                        let mut arg_tys: Vec<Type> = Vec::with_capacity(args.len());
+                        for _ in args.iter() {
+                            arg_tys.push(subst_env.new_var(ctx));
+                        }
+                        let fun_ty = Type::Fun {
+                            args: arg_tys.clone(),
+                            ret: Box::new(ret_ty.clone()),
+                        };
+                        unify(subst_env, &fun_ty, &fun_ty_, fun.span)?;
+                        (arg_tys, ret_ty)
+                    }
+                };
+
+                let n_args = args.len().min(arg_tys.len());
+                let (args_, rest) = args.split_at_mut(n_args);
+                for (arg, arg_ty) in args_.iter_mut().zip(arg_tys.iter()) {
+                    let arg_ty_ = type_check(ctx, ty_env, subst_env, scope, arg)?;
+                    unify(subst_env, arg_ty, &arg_ty_, arg.span)?;
+                }
+
+                if n_args < arg_tys.len() {
+                    return Ok(Type::Fun {
+                        args: arg_tys[n_args..].to_vec(),
+                        ret: Box::new(ret_ty),
+                    });
+                }
+                if rest.is_empty() {
+                    return Ok(ret_ty);
+                }
+                fun_ty_ = ret_ty;
+                args = rest;
             }
-            let fun_ty = Type::Fun {
-                args: arg_tys,
-                ret: Box::new(ret_ty.clone()),
-            };
-            let fun_ty_ = type_check(ctx, ty_env, subst_env, scope, fun)?;
-            unify(subst_env, &fun_ty, &fun_ty_)?;
-            Ok(ret_ty)
         }
 
-        Expr::Tuple(args) => {
+        ExprKind::Annot(e, ty) => {
+            let e_ty = type_check(ctx, ty_env, subst_env, scope, e)?;
+            let annot = resolve_ty(ctx, ty)?;
+            unify_(subst_env, &annot, &e_ty).map_err(|_| TypeErr::AnnotMismatch {
+                annot: annot.clone(),
+                found: norm_ty(subst_env, &e_ty),
+                span: e.span,
+            })?;
+            Ok(annot)
+        }
+
+        ExprKind::Tuple(args) => {
             let mut arg_tys: Vec<Type> = Vec::with_capacity(args.len());
             for arg in args {
                 arg_tys.push(type_check(ctx, ty_env, subst_env, scope, arg)?);
@@ -305,78 +1009,654 @@ fn type_check(
             Ok(Type::Tuple(arg_tys))
         }
 
-        Expr::LetTuple {
-            ref bndrs,
-            rhs,
-            body,
-        } => {
-            let mut arg_tys: Vec<Type> = Vec::with_capacity(bndrs.len());
-            for bndr in bndrs {
-                let bndr_ty = Type::Var(ctx.fresh_tyvar());
-                ty_env.insert(*bndr, bndr_ty.clone());
-                arg_tys.push(bndr_ty);
-            }
-            let tuple_ty = Type::Tuple(arg_tys.clone());
-            let rhs_ty = type_check(ctx, ty_env, subst_env, scope, rhs)?;
-            unify(subst_env, &rhs_ty, &tuple_ty)?;
+        ExprKind::Nil { ty } => {
+            let list_ty = Type::List(Box::new(subst_env.new_var(ctx)));
+            ty_env.insert(*ty, list_ty.clone());
+            Ok(list_ty)
+        }
+
+        ExprKind::Cons(hd, tl) => {
+            let hd_ty = type_check(ctx, ty_env, subst_env, scope, hd)?;
+            let list_ty = Type::List(Box::new(hd_ty));
+            let tl_ty = type_check(ctx, ty_env, subst_env, scope, tl)?;
+            unify(subst_env, &list_ty, &tl_ty, tl.span)?;
+            Ok(list_ty)
+        }
+
+        ExprKind::LetTuple { pat, rhs, body } => {
+            let bndrs = type_check_let_tuple(ctx, ty_env, subst_env, scope, pat, rhs)?;
             scope.new_scope();
-            for (bndr, bndr_type) in bndrs.iter().zip(arg_tys.into_iter()) {
-                scope.add(
-                    ctx.var_name(*bndr),
-                    Binder {
-                        binder: *bndr,
-                        ty: bndr_type,
-                    },
-                );
+            for bndr in bndrs {
+                let ty = ty_env[&bndr].clone();
+                scope.add(ctx.var_name(bndr), Binder { binder: bndr, ty, tyvars: vec![] });
             }
             let ret = type_check(ctx, ty_env, subst_env, scope, body);
             scope.pop_scope();
             ret
         }
 
-        Expr::Array { len, elem } => {
+        ExprKind::Array { len, elem } => {
             let len_ty = type_check(ctx, ty_env, subst_env, scope, len)?;
-            unify(subst_env, &len_ty, &Type::Int)?;
+            unify(subst_env, &Type::Int, &len_ty, len.span)?;
             let elem_ty = type_check(ctx, ty_env, subst_env, scope, elem)?;
             Ok(Type::Array(Box::new(elem_ty)))
         }
 
-        Expr::Get(e1, e2) => {
-            let array_elem_ty = Type::Var(ctx.fresh_tyvar());
+        ExprKind::Get(e1, e2) => {
+            let array_elem_ty = subst_env.new_var(ctx);
             let array_ty = Type::Array(Box::new(array_elem_ty.clone()));
             let e1_ty = type_check(ctx, ty_env, subst_env, scope, e1)?;
-            unify(subst_env, &e1_ty, &array_ty)?;
+            unify(subst_env, &array_ty, &e1_ty, e1.span)?;
             let e2_ty = type_check(ctx, ty_env, subst_env, scope, e2)?;
-            unify(subst_env, &e2_ty, &Type::Int)?;
+            unify(subst_env, &Type::Int, &e2_ty, e2.span)?;
             Ok(array_elem_ty)
         }
 
-        Expr::Put(e1, e2, e3) => {
-            let array_elem_ty = Type::Var(ctx.fresh_tyvar());
+        ExprKind::Put(e1, e2, e3) => {
+            let array_elem_ty = subst_env.new_var(ctx);
             let array_ty = Type::Array(Box::new(array_elem_ty.clone()));
             let e1_ty = type_check(ctx, ty_env, subst_env, scope, e1)?;
-            unify(subst_env, &e1_ty, &array_ty)?;
+            unify(subst_env, &array_ty, &e1_ty, e1.span)?;
             let e2_ty = type_check(ctx, ty_env, subst_env, scope, e2)?;
-            unify(subst_env, &e2_ty, &Type::Int)?;
+            unify(subst_env, &Type::Int, &e2_ty, e2.span)?;
             let e3_ty = type_check(ctx, ty_env, subst_env, scope, e3)?;
-            unify(subst_env, &e3_ty, &array_elem_ty)?;
+            unify(subst_env, &array_elem_ty, &e3_ty, e3.span)?;
+            Ok(Type::Unit)
+        }
+
+        ExprKind::For {
+            var,
+            from,
+            to,
+            dir: _,
+            body,
+        } => {
+            let from_ty = type_check(ctx, ty_env, subst_env, scope, from)?;
+            unify(subst_env, &Type::Int, &from_ty, from.span)?;
+            let to_ty = type_check(ctx, ty_env, subst_env, scope, to)?;
+            unify(subst_env, &Type::Int, &to_ty, to.span)?;
+            ty_env.insert(*var, Type::Int);
+            scope.new_scope();
+            scope.add(
+                ctx.var_name(*var),
+                Binder {
+                    binder: *var,
+                    ty: Type::Int,
+                    tyvars: vec![],
+                },
+            );
+            let body_ty = type_check(ctx, ty_env, subst_env, scope, body);
+            scope.pop_scope();
+            unify(subst_env, &Type::Unit, &body_ty?, body.span)?;
+            Ok(Type::Unit)
+        }
+
+        ExprKind::While { cond, body } => {
+            let cond_ty = type_check(ctx, ty_env, subst_env, scope, cond)?;
+            unify(subst_env, &Type::Bool, &cond_ty, cond.span)?;
+            let body_ty = type_check(ctx, ty_env, subst_env, scope, body)?;
+            unify(subst_env, &Type::Unit, &body_ty, body.span)?;
+            Ok(Type::Unit)
+        }
+
+        ExprKind::Ref(e) => {
+            let e_ty = type_check(ctx, ty_env, subst_env, scope, e)?;
+            Ok(Type::Ref(Box::new(e_ty)))
+        }
+
+        ExprKind::Deref(e) => {
+            let elem_ty = subst_env.new_var(ctx);
+            let ref_ty = Type::Ref(Box::new(elem_ty.clone()));
+            let e_ty = type_check(ctx, ty_env, subst_env, scope, e)?;
+            unify(subst_env, &ref_ty, &e_ty, e.span)?;
+            Ok(elem_ty)
+        }
+
+        ExprKind::Assign(e1, e2) => {
+            let elem_ty = subst_env.new_var(ctx);
+            let ref_ty = Type::Ref(Box::new(elem_ty.clone()));
+            let e1_ty = type_check(ctx, ty_env, subst_env, scope, e1)?;
+            unify(subst_env, &ref_ty, &e1_ty, e1.span)?;
+            let e2_ty = type_check(ctx, ty_env, subst_env, scope, e2)?;
+            unify(subst_env, &elem_ty, &e2_ty, e2.span)?;
+            Ok(Type::Unit)
+        }
+
+        ExprKind::Match { scrut, cases } => {
+            let scrut_ty = type_check(ctx, ty_env, subst_env, scope, scrut)?;
+            let ret_ty = subst_env.new_var(ctx);
+            type_check_cases(ctx, ty_env, subst_env, scope, &scrut_ty, &ret_ty, cases)?;
+            Ok(ret_ty)
+        }
+
+        ExprKind::Raise { exn, ty } => {
+            let exn_ty = type_check(ctx, ty_env, subst_env, scope, exn)?;
+            unify(subst_env, &Type::Con(ctx.exn_ty_con()), &exn_ty, exn.span)?;
+            let ret_ty = subst_env.new_var(ctx);
+            ty_env.insert(*ty, ret_ty.clone());
+            Ok(ret_ty)
+        }
+
+        ExprKind::Try { body, cases } => {
+            let body_ty = type_check(ctx, ty_env, subst_env, scope, body)?;
+            let exn_ty = Type::Con(ctx.exn_ty_con());
+            type_check_cases(ctx, ty_env, subst_env, scope, &exn_ty, &body_ty, cases)?;
+            Ok(body_ty)
+        }
+
+        ExprKind::Assert(e) => {
+            let e_ty = type_check(ctx, ty_env, subst_env, scope, e)?;
+            unify(subst_env, &Type::Bool, &e_ty, e.span)?;
             Ok(Type::Unit)
         }
+
+        ExprKind::Con { con, arg } => {
+            let (binder, arg_tys) = resolve_con(ctx, scope, *con, span)?;
+            *con = binder;
+            let ty = Type::Con(ctx.con_info(binder).ty_con.clone());
+            // Constructors with more than one argument are applied to a tuple syntactically
+            let args: Vec<&mut Expr> = match arg {
+                None => vec![],
+                Some(arg) if arg_tys.len() > 1 => match &mut arg.kind {
+                    ExprKind::Tuple(args) => args.iter_mut().collect(),
+                    _ => vec![&mut **arg],
+                },
+                Some(arg) => vec![&mut **arg],
+            };
+            if args.len() != arg_tys.len() {
+                return Err(TypeErr::ConArity {
+                    con: binder,
+                    expected: arg_tys.len(),
+                    found: args.len(),
+                    span,
+                });
+            }
+            for (arg, arg_ty) in args.into_iter().zip(arg_tys.iter()) {
+                let arg_ty_ = type_check(ctx, ty_env, subst_env, scope, arg)?;
+                unify(subst_env, arg_ty, &arg_ty_, arg.span)?;
+            }
+            Ok(ty)
+        }
+
+        ExprKind::Record(fields) => {
+            // Type of the record is the type of the first field, other fields are checked
+            // against it
+            let mut record_ty: Option<Type> = None;
+            let mut defined: Vec<usize> = Vec::with_capacity(fields.len());
+            for (field, expr) in fields.iter_mut() {
+                *field = resolve_field(ctx, *field, span)?;
+                let info = ctx.field_info(*field).clone();
+                let field_record_ty = Type::Con(info.ty_con);
+                match &record_ty {
+                    None => record_ty = Some(field_record_ty),
+                    Some(record_ty) => unify(subst_env, record_ty, &field_record_ty, expr.span)?,
+                }
+                if defined.contains(&info.idx) {
+                    return Err(TypeErr::DuplicateField {
+                        field: *field,
+                        span: expr.span,
+                    });
+                }
+                defined.push(info.idx);
+                let expr_ty = type_check(ctx, ty_env, subst_env, scope, expr)?;
+                unify(subst_env, &info.ty, &expr_ty, expr.span)?;
+            }
+            // Parser doesn't allow empty records
+            let record_ty = record_ty.unwrap();
+            if let Type::Con(ty_con) = &record_ty {
+                for (idx, field) in ctx.record_fields(ty_con).iter().enumerate() {
+                    if !defined.contains(&idx) {
+                        return Err(TypeErr::MissingField {
+                            field: *field,
+                            span,
+                        });
+                    }
+                }
+            }
+            Ok(record_ty)
+        }
+
+        ExprKind::GetField(record, field) => {
+            *field = resolve_field(ctx, *field, span)?;
+            let info = ctx.field_info(*field).clone();
+            let record_ty = type_check(ctx, ty_env, subst_env, scope, record)?;
+            unify(subst_env, &Type::Con(info.ty_con), &record_ty, record.span)?;
+            Ok(info.ty)
+        }
+
+        ExprKind::SetField(record, field, val) => {
+            *field = resolve_field(ctx, *field, span)?;
+            let info = ctx.field_info(*field).clone();
+            if !info.mutable {
+                return Err(TypeErr::ImmutableField {
+                    field: *field,
+                    span,
+                });
+            }
+            let record_ty = type_check(ctx, ty_env, subst_env, scope, record)?;
+            unify(subst_env, &Type::Con(info.ty_con), &record_ty, record.span)?;
+            let val_ty = type_check(ctx, ty_env, subst_env, scope, val)?;
+            unify(subst_env, &info.ty, &val_ty, val.span)?;
+            Ok(Type::Unit)
+        }
+
+        ExprKind::LetRecord { fields, rhs, body } => {
+            let bndr_tys = type_check_let_record(ctx, ty_env, subst_env, scope, fields, rhs)?;
+            scope.new_scope();
+            for ((_, bndr), bndr_ty) in fields.iter().zip(bndr_tys) {
+                scope.add(
+                    ctx.var_name(*bndr),
+                    Binder {
+                        binder: *bndr,
+                        ty: bndr_ty,
+                        tyvars: vec![],
+                    },
+                );
+            }
+            let ret = type_check(ctx, ty_env, subst_env, scope, body);
+            scope.pop_scope();
+            ret
+        }
+    }
+}
+
+// Type check cases of a `match` or `try`. Patterns are checked against `scrut_ty`, right-hand
+// sides against `ret_ty`.
+fn type_check_cases(
+    ctx: &mut Ctx, ty_env: &mut TypeEnv, subst_env: &mut SubstEnv, scope: &mut Scope,
+    scrut_ty: &Type, ret_ty: &Type, cases: &mut [Case],
+) -> Result<(), TypeErr> {
+    for Case { pat, span, rhs } in cases {
+        let mut bndrs: Vec<VarId> = vec![];
+        let pat_ty = type_check_pat(ctx, ty_env, subst_env, scope, pat, *span, &mut bndrs)?;
+        unify(subst_env, scrut_ty, &pat_ty, *span)?;
+        scope.new_scope();
+        for bndr in bndrs {
+            let ty = ty_env.get(&bndr).unwrap().clone();
+            scope.add(ctx.var_name(bndr), Binder { binder: bndr, ty, tyvars: vec![] });
+        }
+        let rhs_ty = type_check(ctx, ty_env, subst_env, scope, rhs);
+        scope.pop_scope();
+        unify(subst_env, ret_ty, &rhs_ty?, rhs.span)?;
+    }
+    Ok(())
+}
+
+// Give types to variables in a pattern, return type of the pattern. Variables of the pattern are
+// added to `bndrs`. Constructors are resolved as in expressions. `span` is the span of the whole
+// pattern, for errors.
+fn type_check_pat(
+    ctx: &mut Ctx, ty_env: &mut TypeEnv, subst_env: &mut SubstEnv, scope: &Scope, pat: &mut Pat,
+    span: Span, bndrs: &mut Vec<VarId>,
+) -> Result<Type, TypeErr> {
+    match pat {
+        Pat::Unit => Ok(Type::Unit),
+        Pat::Bool(_) => Ok(Type::Bool),
+        Pat::Int(_) => Ok(Type::Int),
+        Pat::Char(_) => Ok(Type::Char),
+        Pat::Wildcard => Ok(subst_env.new_var(ctx)),
+        Pat::Var(var) => {
+            let ty = subst_env.new_var(ctx);
+            ty_env.insert(*var, ty.clone());
+            bndrs.push(*var);
+            Ok(ty)
+        }
+        Pat::Tuple(pats) => Ok(Type::Tuple(
+            pats.iter_mut()
+                .map(|pat| type_check_pat(ctx, ty_env, subst_env, scope, pat, span, bndrs))
+                .collect::<Result<_, _>>()?,
+        )),
+        Pat::Nil => Ok(Type::List(Box::new(subst_env.new_var(ctx)))),
+        Pat::Cons(hd, tl) => {
+            let hd_ty = type_check_pat(ctx, ty_env, subst_env, scope, hd, span, bndrs)?;
+            let list_ty = Type::List(Box::new(hd_ty));
+            let tl_ty = type_check_pat(ctx, ty_env, subst_env, scope, tl, span, bndrs)?;
+            unify(subst_env, &list_ty, &tl_ty, span)?;
+            Ok(list_ty)
+        }
+        Pat::Con { con, arg } => {
+            let (binder, arg_tys) = resolve_con(ctx, scope, *con, span)?;
+            *con = binder;
+            let ty = Type::Con(ctx.con_info(binder).ty_con.clone());
+            // `C _` matches all arguments of `C`. Expand it to a tuple of wildcards so that
+            // `lower` only sees tuple patterns for constructors with more than one argument.
+            if let Some(arg) = arg {
+                if arg_tys.len() > 1 {
+                    if let Pat::Wildcard = **arg {
+                        **arg = Pat::Tuple(vec![Pat::Wildcard; arg_tys.len()]);
+                    }
+                }
+            }
+            let arg_pats: Vec<&mut Pat> = match arg {
+                None => vec![],
+                Some(arg) if arg_tys.len() > 1 => match &mut **arg {
+                    Pat::Tuple(pats) => pats.iter_mut().collect(),
+                    arg => vec![arg],
+                },
+                Some(arg) => vec![&mut **arg],
+            };
+            if arg_pats.len() != arg_tys.len() {
+                return Err(TypeErr::ConArity {
+                    con: binder,
+                    expected: arg_tys.len(),
+                    found: arg_pats.len(),
+                    span,
+                });
+            }
+            for (arg_pat, arg_ty) in arg_pats.into_iter().zip(arg_tys) {
+                let arg_ty_ = type_check_pat(ctx, ty_env, subst_env, scope, arg_pat, span, bndrs)?;
+                unify(subst_env, &arg_ty, &arg_ty_, span)?;
+            }
+            Ok(ty)
+        }
+    }
+}
+
+// Type check functions of a `let rec`, and add the functions to the current scope
+fn type_check_fun_defs(
+    ctx: &mut Ctx, ty_env: &mut TypeEnv, subst_env: &mut SubstEnv, scope: &mut Scope,
+    defs: &mut [FunDef],
+) -> Result<(), TypeErr> {
+    subst_env.enter_level();
+
+    // Give types to the functions first, so that they can refer to each other
+    let mut fun_tys: Vec<(Vec<Type>, Type)> = Vec::with_capacity(defs.len());
+    for FunDef { bndr, args, .. } in defs.iter() {
+        // Type variables for the arguments
+        let mut arg_tys: Vec<Type> = Vec::with_capacity(args.len());
+        for arg in args {
+            let arg_ty = subst_env.new_var(ctx);
+            arg_tys.push(arg_ty.clone());
+            ty_env.insert(*arg, arg_ty);
+        }
+
+        // Type variable for the RHS
+        let rhs_ty = subst_env.new_var(ctx);
+
+        // We can now give type to the recursive function
+        let fun_ty = Type::Fun {
+            args: arg_tys.clone(),
+            ret: Box::new(rhs_ty.clone()),
+        };
+
+        ty_env.insert(*bndr, fun_ty);
+        fun_tys.push((arg_tys, rhs_ty));
+    }
+
+    // RHSs will be type checked with the functions in scope. Uses of the functions in the RHSs
+    // are monomorphic, the functions are generalized after checking all RHSs.
+    scope.new_scope();
+    for FunDef { bndr, .. } in defs.iter() {
+        let ty = ty_env.get(bndr).unwrap().clone();
+        scope.add(
+            ctx.var_name(*bndr),
+            Binder {
+                binder: *bndr,
+                ty,
+                tyvars: vec![],
+            },
+        );
+    }
+
+    for (FunDef { args, rhs, .. }, (arg_tys, rhs_ty)) in defs.iter_mut().zip(fun_tys) {
+        scope.new_scope(); // new scope for args
+        for (binder, arg_ty) in args.iter().zip(arg_tys) {
+            scope.add(
+                ctx.var_name(*binder),
+                Binder {
+                    binder: *binder,
+                    ty: arg_ty,
+                    tyvars: vec![],
+                },
+            );
+        }
+
+        // Type check RHS with functions and args in scope
+        let rhs_ty_ = type_check(ctx, ty_env, subst_env, scope, rhs)?;
+        unify(subst_env, &rhs_ty, &rhs_ty_, rhs.span)?;
+        scope.pop_scope();
+    }
+    scope.pop_scope();
+    subst_env.exit_level();
+
+    // Functions of a `let rec` are generalized together, as monomorphization copies them
+    // together
+    let fun_tys: Vec<Type> = defs.iter().map(|def| ty_env[&def.bndr].clone()).collect();
+    let tyvars = generalize(ctx, subst_env, &fun_tys);
+    for FunDef { bndr, .. } in defs.iter() {
+        if !tyvars.is_empty() {
+            ctx.set_poly_vars(*bndr, tyvars.clone());
+        }
+        scope.add(
+            ctx.var_name(*bndr),
+            Binder {
+                binder: *bndr,
+                ty: ty_env[bndr].clone(),
+                tyvars: tyvars.clone(),
+            },
+        );
+    }
+
+    Ok(())
+}
+
+// Generalize type of the binder of a `let`. Only syntactic values are generalized, as in OCaml's
+// value restriction: monomorphization copies the RHS for each instance, which should not change
+// the program's behavior.
+fn generalize_let(
+    ctx: &mut Ctx, subst_env: &mut SubstEnv, bndr: VarId, bndr_ty: &Type, rhs: &Expr,
+) -> Vec<TyVar> {
+    if !is_value(rhs) {
+        // Variables of the type are now in scope
+        lower_levels(subst_env, bndr_ty);
+        return vec![];
+    }
+    let tyvars = generalize(ctx, subst_env, std::slice::from_ref(bndr_ty));
+    if !tyvars.is_empty() {
+        ctx.set_poly_vars(bndr, tyvars.clone());
+    }
+    tyvars
+}
+
+fn is_value(expr: &Expr) -> bool {
+    match &expr.kind {
+        ExprKind::Unit
+        | ExprKind::Bool(_)
+        | ExprKind::Int(_)
+        | ExprKind::Float(_)
+        | ExprKind::String(_)
+        | ExprKind::Char(_)
+        | ExprKind::Nil { .. }
+        | ExprKind::Var(_)
+        | ExprKind::Fun { .. } => true,
+        ExprKind::Annot(e, _) => is_value(e),
+        ExprKind::Tuple(args) => args.iter().all(is_value),
+        ExprKind::Cons(hd, tl) => is_value(hd) && is_value(tl),
+        ExprKind::Con { arg, .. } => arg.iter().all(|arg| is_value(arg)),
+        _ => false,
+    }
+}
+
+// Unbound type variables of the types with a higher level than the current level. These don't
+// occur in the types of the variables in scope.
+fn generalize(ctx: &mut Ctx, subst_env: &mut SubstEnv, tys: &[Type]) -> Vec<TyVar> {
+    let mut tyvars: Vec<TyVar> = vec![];
+    for ty in tys {
+        generalize_(subst_env, ty, &mut tyvars);
+    }
+
+    let compared = std::mem::take(&mut subst_env.compared);
+    let mut compared_tyvars: Vec<TyVar> = vec![];
+    for ty in &compared {
+        generalize_(subst_env, ty, &mut compared_tyvars);
+    }
+    subst_env.compared = compared;
+    for tyvar in &tyvars {
+        if compared_tyvars.contains(tyvar) {
+            ctx.add_compared_tyvar(*tyvar);
+        }
+    }
+
+    tyvars
+}
+
+fn generalize_(subst_env: &mut SubstEnv, ty: &Type, tyvars: &mut Vec<TyVar>) {
+    match &*deref_ty(subst_env, ty) {
+        Type::Unit
+        | Type::Bool
+        | Type::Int
+        | Type::Float
+        | Type::String
+        | Type::Char
+        | Type::Con(_)
+        | Type::Alias { .. } => {}
+        Type::Fun { args, ret } => {
+            for arg in args {
+                generalize_(subst_env, arg, tyvars);
+            }
+            generalize_(subst_env, ret, tyvars);
+        }
+        Type::Tuple(args) => {
+            for arg in args {
+                generalize_(subst_env, arg, tyvars);
+            }
+        }
+        Type::Array(ty) | Type::List(ty) | Type::Ref(ty) => generalize_(subst_env, ty, tyvars),
+        Type::Var(var) => match subst_env.link(*var) {
+            Link::Unbound { level } if *level > subst_env.level && !tyvars.contains(var) => {
+                tyvars.push(*var);
+            }
+            _ => {}
+        },
+    }
+}
+
+// Lower levels of the variables in the type to the current level
+fn lower_levels(subst_env: &mut SubstEnv, ty: &Type) {
+    match &*deref_ty(subst_env, ty) {
+        Type::Unit
+        | Type::Bool
+        | Type::Int
+        | Type::Float
+        | Type::String
+        | Type::Char
+        | Type::Con(_)
+        | Type::Alias { .. } => {}
+        Type::Fun { args, ret } => {
+            for arg in args {
+                lower_levels(subst_env, arg);
+            }
+            lower_levels(subst_env, ret);
+        }
+        Type::Tuple(args) => {
+            for arg in args {
+                lower_levels(subst_env, arg);
+            }
+        }
+        Type::Array(ty) | Type::List(ty) | Type::Ref(ty) => lower_levels(subst_env, ty),
+        Type::Var(var) => {
+            let current = subst_env.level;
+            if let Link::Unbound { level } = subst_env.link_mut(*var) {
+                *level = (*level).min(current);
+            }
+        }
+    }
+}
+
+// Instantiate type variables in a normalized type
+fn inst_ty(inst: &FxHashMap<TyVar, Type>, ty: &Type) -> Type {
+    match ty {
+        Type::Unit
+        | Type::Bool
+        | Type::Int
+        | Type::Float
+        | Type::String
+        | Type::Char
+        | Type::Con(_)
+        | Type::Alias { .. } => ty.clone(),
+        Type::Fun { args, ret } => Type::Fun {
+            args: args.iter().map(|ty| inst_ty(inst, ty)).collect(),
+            ret: Box::new(inst_ty(inst, ret)),
+        },
+        Type::Tuple(args) => Type::Tuple(args.iter().map(|ty| inst_ty(inst, ty)).collect()),
+        Type::Array(ty) => Type::Array(Box::new(inst_ty(inst, ty))),
+        Type::List(ty) => Type::List(Box::new(inst_ty(inst, ty))),
+        Type::Ref(ty) => Type::Ref(Box::new(inst_ty(inst, ty))),
+        Type::Var(var) => inst.get(var).cloned().unwrap_or_else(|| ty.clone()),
+    }
+}
+
+// Type check the pattern and RHS of a `let <pat> = rhs`, return the binders of the pattern. The
+// binders are not added to the scope.
+fn type_check_let_tuple(
+    ctx: &mut Ctx, ty_env: &mut TypeEnv, subst_env: &mut SubstEnv, scope: &mut Scope,
+    pat: &mut Pat, rhs: &mut Expr,
+) -> Result<Vec<VarId>, TypeErr> {
+    let mut bndrs: Vec<VarId> = vec![];
+    let pat_ty = type_check_pat(ctx, ty_env, subst_env, scope, pat, rhs.span, &mut bndrs)?;
+    let rhs_ty = type_check(ctx, ty_env, subst_env, scope, rhs)?;
+    unify(subst_env, &pat_ty, &rhs_ty, rhs.span)?;
+    Ok(bndrs)
+}
+
+// Type check RHS of a `let { x = y; ... } = rhs`, resolve the fields, return types of the binders.
+// The binders are not added to the scope.
+fn type_check_let_record(
+    ctx: &mut Ctx, ty_env: &mut TypeEnv, subst_env: &mut SubstEnv, scope: &mut Scope,
+    fields: &mut [(VarId, VarId)], rhs: &mut Expr,
+) -> Result<Vec<Type>, TypeErr> {
+    let rhs_ty = type_check(ctx, ty_env, subst_env, scope, rhs)?;
+    let mut bndr_tys: Vec<Type> = Vec::with_capacity(fields.len());
+    for (field, bndr) in fields.iter_mut() {
+        *field = resolve_field(ctx, *field, rhs.span)?;
+        let info = ctx.field_info(*field);
+        let record_ty = Type::Con(info.ty_con.clone());
+        let bndr_ty = info.ty.clone();
+        unify(subst_env, &record_ty, &rhs_ty, rhs.span)?;
+        ty_env.insert(*bndr, bndr_ty.clone());
+        bndr_tys.push(bndr_ty);
     }
+    Ok(bndr_tys)
 }
 
-fn unify(subst_env: &mut SubstEnv, ty1: &Type, ty2: &Type) -> Result<(), TypeErr> {
-    let ty1 = deref_ty(subst_env, ty1).clone();
-    let ty2 = deref_ty(subst_env, ty2).clone();
+// Failure of `unify_`. `unify` turns these into `TypeErr`s with the whole types being unified.
+enum UnifyErr {
+    Mismatch,
+    Occurs,
+}
+
+// `ty1` is the expected type, `ty2` is the type found at `span`
+fn unify(subst_env: &mut SubstEnv, ty1: &Type, ty2: &Type, span: Span) -> Result<(), TypeErr> {
+    unify_(subst_env, ty1, ty2).map_err(|err| {
+        let expected = norm_ty(subst_env, ty1);
+        let found = norm_ty(subst_env, ty2);
+        match err {
+            UnifyErr::Mismatch => TypeErr::UnifyError {
+                expected,
+                found,
+                span,
+            },
+            UnifyErr::Occurs => TypeErr::InfiniteType {
+                expected,
+                found,
+                span,
+            },
+        }
+    })
+}
 
-    // println!("substs: {:?}", substs);
-    // println!("unify {:?} ~ {:?}", ty1, ty2);
+fn unify_(subst_env: &mut SubstEnv, ty1: &Type, ty2: &Type) -> Result<(), UnifyErr> {
+    let ty1 = deref_ty(subst_env, ty1);
+    let ty2 = deref_ty(subst_env, ty2);
 
-    match (&ty1, &ty2) {
+    match (&*ty1, &*ty2) {
         (Type::Unit, Type::Unit)
         | (Type::Bool, Type::Bool)
         | (Type::Int, Type::Int)
-        | (Type::Float, Type::Float) => Ok(()),
+        | (Type::Float, Type::Float)
+        | (Type::String, Type::String)
+        | (Type::Char, Type::Char) => Ok(()),
+        (Type::Con(con1), Type::Con(con2)) if con1 == con2 => Ok(()),
         (
             Type::Fun {
                 args: args1,
@@ -387,74 +1667,190 @@ fn unify(subst_env: &mut SubstEnv, ty1: &Type, ty2: &Type) -> Result<(), TypeErr
                 ret: ret2,
             },
         ) => {
-            if args1.len() != args2.len() {
-                return Err(TypeErr::UnifyError(ty1.clone(), ty2.clone()));
-            }
+            // Functions are curried: a function with more arguments is a function of the first
+            // arguments returning a function of the rest, e.g. `[a; b] -> r` unifies with
+            // `[a'] -> r'` when `a = a'` and `r' = [b] -> r`. Function values take one argument at
+            // a time, see `anormal`, so the number of arguments in the type doesn't matter.
             for (arg1, arg2) in args1.iter().zip(args2.iter()) {
-                unify(subst_env, arg1, arg2)?;
+                unify_(subst_env, arg1, arg2)?;
+            }
+            let n_args = args1.len().min(args2.len());
+            let rest = |args: &[Type], ret: &Type| Type::Fun {
+                args: args[n_args..].to_vec(),
+                ret: Box::new(ret.clone()),
+            };
+            if args1.len() > n_args {
+                unify_(subst_env, &rest(args1, ret1), ret2)
+            } else if args2.len() > n_args {
+                unify_(subst_env, ret1, &rest(args2, ret2))
+            } else {
+                unify_(subst_env, ret1, ret2)
             }
-            unify(subst_env, &*ret1, &*ret2)
         }
 
         (Type::Var(var1), Type::Var(var2)) if var1 == var2 => Ok(()),
 
-        (Type::Var(var), ty) | (ty, Type::Var(var)) => {
-            if occurs_check(subst_env, *var, ty) {
-                return Err(TypeErr::InfiniteType(ty1, ty2));
-            }
-            // println!("unify {:?} ~ {:?}", var, ty);
-            subst_env.insert(*var, ty.clone());
-            Ok(())
-        }
+        (Type::Var(var), _) => bind(subst_env, *var, ty2),
+
+        (_, Type::Var(var)) => bind(subst_env, *var, ty1),
+
+        (Type::Alias { ty: ty1, .. }, _) => unify_(subst_env, ty1, &ty2),
+        (_, Type::Alias { ty: ty2, .. }) => unify_(subst_env, &ty1, ty2),
 
         (Type::Tuple(args1), Type::Tuple(args2)) => {
             if args1.len() != args2.len() {
-                return Err(TypeErr::UnifyError(ty1.clone(), ty2.clone()));
+                return Err(UnifyErr::Mismatch);
             }
             for (arg1, arg2) in args1.iter().zip(args2.iter()) {
-                unify(subst_env, arg1, arg2)?;
+                unify_(subst_env, arg1, arg2)?;
             }
             Ok(())
         }
 
-        (Type::Array(ty1), Type::Array(ty2)) => unify(subst_env, ty1, ty2),
+        (Type::Array(ty1), Type::Array(ty2)) => unify_(subst_env, ty1, ty2),
+        (Type::List(ty1), Type::List(ty2)) => unify_(subst_env, ty1, ty2),
+        (Type::Ref(ty1), Type::Ref(ty2)) => unify_(subst_env, ty1, ty2),
 
-        _ => Err(TypeErr::UnifyError(ty1.clone(), ty2.clone())),
+        _ => Err(UnifyErr::Mismatch),
     }
 }
 
-use std::fmt;
+// Bind an unbound root variable to a type
+fn bind(subst_env: &mut SubstEnv, var: TyVar, ty: DerefTy) -> Result<(), UnifyErr> {
+    let level = match subst_env.link(var) {
+        Link::Unbound { level } => *level,
+        _ => unreachable!(),
+    };
+    if occurs_check(subst_env, var, level, &ty) {
+        return Err(UnifyErr::Occurs);
+    }
+    *subst_env.link_mut(var) = match ty {
+        DerefTy::Unbound(Type::Var(var_)) => Link::Var(var_),
+        DerefTy::Bound(ty) => Link::Ty(ty),
+        ty => Link::Ty(Rc::new((*ty).clone())),
+    };
+    Ok(())
+}
+
+/// Names of type variables when printing types, in OCaml syntax: 'a, 'b, ...
+#[derive(Debug, Default)]
+pub struct TyVarNames {
+    names: FxHashMap<TyVar, usize>,
+}
+
+impl TyVarNames {
+    fn pp(&mut self, var: TyVar, w: &mut dyn fmt::Write) -> fmt::Result {
+        let next = self.names.len();
+        let idx = *self.names.entry(var).or_insert(next);
+        let letter = char::from(b'a' + (idx % 26) as u8);
+        if idx < 26 {
+            write!(w, "'{}", letter)
+        } else {
+            write!(w, "'{}{}", letter, idx / 26)
+        }
+    }
+}
+
+// Precedence of type syntax, used to decide where to add parens
+const ARROW_TY_PREC: u8 = 0;
+const TUPLE_TY_PREC: u8 = 1;
+const ATOM_TY_PREC: u8 = 2;
 
 impl Type {
     pub fn pp(&self, w: &mut dyn fmt::Write) -> fmt::Result {
+        self.pp_named(&mut Default::default(), w)
+    }
+
+    /// Print the type with type variable names from `names`. Use the same `TyVarNames` when
+    /// printing related types (e.g. expected and found types in an error) to get consistent names.
+    pub fn pp_named(&self, names: &mut TyVarNames, w: &mut dyn fmt::Write) -> fmt::Result {
+        self.pp_prec(names, ARROW_TY_PREC, w)
+    }
+
+    fn pp_prec(&self, names: &mut TyVarNames, prec: u8, w: &mut dyn fmt::Write) -> fmt::Result {
         use Type::*;
         match self {
-            Unit => w.write_str("()"),
+            Unit => w.write_str("unit"),
             Bool => w.write_str("bool"),
             Int => w.write_str("int"),
             Float => w.write_str("float"),
+            String => w.write_str("string"),
+            Char => w.write_str("char"),
             Fun { args, ret } => {
+                if prec > ARROW_TY_PREC {
+                    w.write_str("(")?;
+                }
                 for arg in args {
-                    arg.pp(w)?;
+                    arg.pp_prec(names, TUPLE_TY_PREC, w)?;
                     w.write_str(" -> ")?;
                 }
-                ret.pp(w)
+                ret.pp_prec(names, ARROW_TY_PREC, w)?;
+                if prec > ARROW_TY_PREC {
+                    w.write_str(")")?;
+                }
+                Ok(())
             }
             Tuple(args) => {
                 assert!(!args.is_empty());
-                args[0].pp(w)?;
+                if prec > TUPLE_TY_PREC {
+                    w.write_str("(")?;
+                }
+                args[0].pp_prec(names, ATOM_TY_PREC, w)?;
                 for arg in &args[1..] {
                     w.write_str(" * ")?;
-                    arg.pp(w)?;
+                    arg.pp_prec(names, ATOM_TY_PREC, w)?;
+                }
+                if prec > TUPLE_TY_PREC {
+                    w.write_str(")")?;
                 }
                 Ok(())
             }
             Array(ty) => {
-                w.write_str("[")?;
-                ty.pp(w)?;
-                w.write_str("]")
+                ty.pp_prec(names, ATOM_TY_PREC, w)?;
+                w.write_str(" array")
+            }
+            List(ty) => {
+                ty.pp_prec(names, ATOM_TY_PREC, w)?;
+                w.write_str(" list")
             }
-            Var(var) => write!(w, "{}", var),
+            Ref(ty) => {
+                ty.pp_prec(names, ATOM_TY_PREC, w)?;
+                w.write_str(" ref")
+            }
+            Var(var) => names.pp(*var, w),
+            Con(con) => w.write_str(&con.name),
+            Alias { name, .. } => w.write_str(name),
         }
     }
 }
+
+// A large generated program: a `let rec` of `n` functions, each calling the next one, and uses of
+// the functions. Type variables of the functions' arguments are unified with each other, which
+// made the type checker quadratic in `n` before union-find.
+#[cfg(test)]
+fn large_pgm(n: usize) -> String {
+    use std::fmt::Write;
+    let mut s = String::new();
+    for i in 0..n {
+        writeln!(s, "{} f{} x y =", if i == 0 { "let rec" } else { "and" }, i).unwrap();
+        writeln!(s, "  let a = (y, x + {}) in", i).unwrap();
+        writeln!(s, "  let (b, c) = a in").unwrap();
+        writeln!(s, "  if c > {} then b else f{} (x - 1) b", n, (i + 1) % n).unwrap();
+    }
+    for i in 0..n {
+        writeln!(s, "let () = print_int (f{} 0 {})", i, i).unwrap();
+    }
+    s
+}
+
+#[cfg(test)]
+#[bench]
+fn type_check_bench(b: &mut test::Bencher) {
+    let pgm = large_pgm(5_000);
+    let tokens = crate::lexer::tokenize(&pgm).unwrap();
+    b.iter(|| {
+        let mut ctx = Default::default();
+        let mut pgm = crate::parser::parse(&mut ctx, &tokens).unwrap();
+        type_check_pgm(&mut ctx, &mut pgm).unwrap();
+    });
+}
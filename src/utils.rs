@@ -24,22 +24,6 @@ pub fn comma_sep(s: &str) -> String {
     ret
 }
 
-// Copied from take_mut crate
-pub fn take<T, F>(mut_ref: &mut T, closure: F)
-where
-    F: FnOnce(T) -> T,
-{
-    use std::panic;
-    use std::ptr;
-
-    unsafe {
-        let old_t = ptr::read(mut_ref);
-        let new_t = panic::catch_unwind(panic::AssertUnwindSafe(|| closure(old_t)))
-            .unwrap_or_else(|_| ::std::process::abort());
-        ptr::write(mut_ref, new_t);
-    }
-}
-
 #[allow(dead_code)]
 pub fn show_arg_list(ctx: &Ctx, args: &[VarId]) -> String {
     let mut s = String::new();
@@ -64,13 +48,13 @@ pub fn base62_encode(uniq: Uniq, w: &mut dyn Write) -> fmt::Result {
     let mut c = uniq.0.get() as usize;
     loop {
         if c < 62 {
-            w.write_char(char::from(BASE62_CHARS[c as usize]))?;
+            w.write_char(char::from(BASE62_CHARS[c]))?;
             break;
         }
 
         let q = c % 62;
         let r = c / 62;
-        w.write_char(char::from(BASE62_CHARS[q as usize]))?;
+        w.write_char(char::from(BASE62_CHARS[q]))?;
         c = r;
     }
 
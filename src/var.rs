@@ -139,6 +139,7 @@ impl UserVar {
 #[derive(Debug, Clone)]
 pub struct GeneratedVar {
     name: Rc<str>,
+    #[allow(dead_code)]
     phase: CompilerPhase,
     uniq: Uniq,
 }
@@ -186,6 +187,7 @@ impl GeneratedVar {
 #[derive(Debug, PartialEq, Eq, Hash, Clone, Copy)]
 pub enum CompilerPhase {
     Parser,
+    TypeCheck,
     ANormal,
     ClosureConvert,
 }
@@ -195,6 +197,7 @@ impl CompilerPhase {
         use CompilerPhase::*;
         match self {
             Parser => "p",
+            TypeCheck => "tc",
             ANormal => "an",
             ClosureConvert => "cc",
         }